@@ -0,0 +1,115 @@
+//! Serial vs parallel Parquet row extraction.
+//!
+//! Writes a generated wide-layout file to the temp directory and loads it
+//! with a single-thread rayon pool and with the default pool:
+//!
+//! ```text
+//! cargo bench --bench parquet_load
+//! ```
+
+#[allow(dead_code)]
+#[path = "../src/data/mod.rs"]
+mod data;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow::array::{ArrayRef, Float64Builder, Int64Array, ListBuilder, StringArray};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+
+use data::loader::load_file;
+use data::model::{LoadOptions, MetadataValue, SpectralDataset};
+
+const N_SPECTRA: usize = 20_000;
+const N_POINTS: usize = 1_000;
+const RUNS: u32 = 3;
+
+fn main() {
+    let path = std::env::temp_dir().join("rusty_panda_bench.parquet");
+    write_sample(&path, N_SPECTRA, N_POINTS);
+
+    let options = LoadOptions::default();
+    let serial_pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .expect("building single-thread pool");
+
+    let (serial, serial_ds) = time(|| serial_pool.install(|| load_file(&path, &options)));
+    let (parallel, parallel_ds) = time(|| load_file(&path, &options));
+
+    assert_eq!(ids(&serial_ds), ids(&parallel_ds), "row order differs");
+    assert!(
+        ids(&parallel_ds)
+            .iter()
+            .enumerate()
+            .all(|(i, &id)| id == i as i64),
+        "rows out of file order"
+    );
+
+    println!("{N_SPECTRA} spectra × {N_POINTS} points, best of {RUNS}");
+    println!("  serial   {serial:>10.2?}");
+    println!(
+        "  parallel {parallel:>10.2?}  ({} threads, speed-up {:.2}×)",
+        rayon::current_num_threads(),
+        serial.as_secs_f64() / parallel.as_secs_f64()
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Best wall time of [`RUNS`] loads, with the last dataset loaded.
+fn time(mut load: impl FnMut() -> anyhow::Result<SpectralDataset>) -> (Duration, SpectralDataset) {
+    let mut best = Duration::MAX;
+    let mut dataset = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let ds = load().expect("loading benchmark file");
+        best = best.min(start.elapsed());
+        dataset = Some(ds);
+    }
+    (best, dataset.expect("at least one run"))
+}
+
+/// The `id` column of every spectrum, in dataset order.
+fn ids(ds: &SpectralDataset) -> Vec<i64> {
+    ds.spectra
+        .iter()
+        .map(|sp| match sp.metadata.get("id") {
+            Some(MetadataValue::Integer(i)) => *i,
+            other => panic!("unexpected id {other:?}"),
+        })
+        .collect()
+}
+
+/// Write `n_spectra` synthetic spectra with an `id` and a `sample` column.
+fn write_sample(path: &Path, n_spectra: usize, n_points: usize) {
+    let mut x = ListBuilder::new(Float64Builder::new());
+    let mut y = ListBuilder::new(Float64Builder::new());
+    for i in 0..n_spectra {
+        for j in 0..n_points {
+            let wn = 4000.0 - j as f64 * 3.0;
+            x.values().append_value(wn);
+            y.values()
+                .append_value((-(wn - 1650.0 - i as f64 % 50.0).powi(2) / 800.0).exp());
+        }
+        x.append(true);
+        y.append(true);
+    }
+    let id: ArrayRef = Arc::new(Int64Array::from_iter_values(0..n_spectra as i64));
+    let sample: ArrayRef = Arc::new(StringArray::from_iter_values(
+        (0..n_spectra).map(|i| format!("S{}", i % 12)),
+    ));
+    let batch = RecordBatch::try_from_iter([
+        ("x", Arc::new(x.finish()) as ArrayRef),
+        ("y", Arc::new(y.finish()) as ArrayRef),
+        ("id", id),
+        ("sample", sample),
+    ])
+    .expect("building record batch");
+
+    let file = std::fs::File::create(path).expect("creating benchmark file");
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None).expect("creating writer");
+    writer.write(&batch).expect("writing batch");
+    writer.close().expect("closing writer");
+}
@@ -1,6 +1,11 @@
+use anyhow::Context as _;
 use eframe::egui;
 
-use crate::state::AppState;
+use crate::color::ColorMode;
+use crate::state::{AppState, MAX_RECENT_FILES, PlotTheme, PlotView, ScreenshotTarget};
+use crate::ui::correlation::CorrelationView;
+use crate::ui::heatmap::HeatmapView;
+use crate::ui::pca::PcaView;
 use crate::ui::{panels, plot};
 
 // ---------------------------------------------------------------------------
@@ -9,23 +14,208 @@ use crate::ui::{panels, plot};
 
 pub struct RustyPandaApp {
     pub state: AppState,
+    /// Cached heatmap image, rebuilt when the data or settings change.
+    heatmap: HeatmapView,
+    /// Cached correlation matrix image, rebuilt likewise.
+    correlation: CorrelationView,
+    /// Cached PCA scores, recomputed likewise.
+    pca: PcaView,
+    /// Last title sent to the viewport, to avoid resending every frame.
+    window_title: String,
+    /// Opened on the first copy and kept: on Linux the copied image is only
+    /// served while the clipboard handle is alive.
+    clipboard: Option<arboard::Clipboard>,
 }
 
+/// Storage key of the recent files list.
+const RECENT_FILES_KEY: &str = "recent_files";
+
+/// How long a confirmation such as "Copied" stays in the top bar.
+const STATUS_FLASH_SECONDS: f64 = 2.0;
+
 impl Default for RustyPandaApp {
     fn default() -> Self {
+        Self::new(AppState::default(), None)
+    }
+}
+
+impl RustyPandaApp {
+    /// Start the app with a prepared state (e.g. a dataset given on the
+    /// command line), restoring persisted settings from `storage`.
+    pub fn new(mut state: AppState, storage: Option<&dyn eframe::Storage>) -> Self {
+        if let Some(storage) = storage {
+            state.recent_files = eframe::get_value(storage, RECENT_FILES_KEY).unwrap_or_default();
+            state.recent_files.truncate(MAX_RECENT_FILES);
+        }
         Self {
-            state: AppState::default(),
+            state,
+            heatmap: HeatmapView::default(),
+            correlation: CorrelationView::default(),
+            pca: PcaView::default(),
+            window_title: "Rusty Panda – Spectral Viewer".to_string(),
+            clipboard: None,
         }
     }
 }
 
 impl eframe::App for RustyPandaApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, RECENT_FILES_KEY, &self.state.recent_files);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // ---- Background file loading ----
+        panels::poll_load(&mut self.state);
+        if self.state.loading {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        // ---- Keyboard shortcuts ----
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::R)) {
+            panels::reload_file(&mut self.state);
+        }
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::R))
+        {
+            self.state.reset_view();
+        }
+        // Arrow keys step through the visible spectra one at a time.
+        if !ctx.wants_keyboard_input() && self.state.tab.dataset.is_some() {
+            let focused = self.state.tab.focused_index.is_some();
+            let (left, right, escape) = ctx.input_mut(|i| {
+                (
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowLeft),
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowRight),
+                    focused && i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+                )
+            });
+            if left {
+                self.state.step_focus(-1);
+            }
+            if right {
+                self.state.step_focus(1);
+            }
+            if escape {
+                self.state.clear_focus();
+            }
+        }
+        // Ctrl+C arrives as a copy event rather than a key press.
+        if !ctx.wants_keyboard_input()
+            && ctx.input(|i| i.events.iter().any(|e| matches!(e, egui::Event::Copy)))
+        {
+            panels::copy_plot(ctx, &mut self.state);
+        }
+
+        // ---- Drag and drop: several files are combined like a folder ----
+        let dropped: Vec<_> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        panels::load_paths(&mut self.state, dropped);
+
+        // ---- Finish a pending PNG export or copy once the screenshot arrives ----
+        if let Some(screenshot) = ctx.input(|i| {
+            i.events.iter().find_map(|e| match e {
+                egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                _ => None,
+            })
+        }) {
+            let pixels_per_point = ctx.pixels_per_point();
+            // A transparent plot is drawn straight on the panel.
+            let transparent = (self.state.plot_theme == PlotTheme::Transparent)
+                .then(|| ctx.style().visuals.panel_fill);
+            match (self.state.pending_screenshot.take(), self.state.plot_rect) {
+                (Some(ScreenshotTarget::File(path)), Some(rect)) => {
+                    match crate::export::save_png(
+                        &path,
+                        &screenshot,
+                        rect,
+                        pixels_per_point,
+                        transparent,
+                    ) {
+                        Ok(()) => log::info!("Saved plot to {}", path.display()),
+                        Err(e) => {
+                            log::error!("Failed to save plot: {e:#}");
+                            self.state.tab.status_message = Some(format!("Error: {e:#}"));
+                        }
+                    }
+                }
+                (Some(ScreenshotTarget::Clipboard), Some(rect)) => {
+                    let copied = match &mut self.clipboard {
+                        Some(clipboard) => Ok(clipboard),
+                        None => arboard::Clipboard::new()
+                            .map(|c| self.clipboard.insert(c))
+                            .context("opening the clipboard"),
+                    }
+                    .and_then(|clipboard| {
+                        crate::export::copy_png(
+                            clipboard,
+                            &screenshot,
+                            rect,
+                            pixels_per_point,
+                            transparent,
+                        )
+                    });
+                    match copied {
+                        Ok(()) => {
+                            let until = ctx.input(|i| i.time) + STATUS_FLASH_SECONDS;
+                            self.state.status_flash = Some(("Copied".to_string(), until));
+                        }
+                        Err(e) => {
+                            log::error!("Failed to copy plot: {e:#}");
+                            self.state.tab.status_message = Some(format!("Error: {e:#}"));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // ---- Clear an expired confirmation ----
+        if let Some((_, until)) = self.state.status_flash {
+            let now = ctx.input(|i| i.time);
+            if now >= until {
+                self.state.status_flash = None;
+            } else {
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(until - now));
+            }
+        }
+
+        // ---- Theme ----
+        let theme = self.state.plot_theme.egui_theme();
+        if ctx.theme() != theme {
+            ctx.set_theme(theme);
+        }
+
+        // ---- Window title: show the loaded file ----
+        let title = match self.state.source_name() {
+            Some(name) => format!("{name} – Rusty Panda"),
+            None => "Rusty Panda – Spectral Viewer".to_string(),
+        };
+        if self.window_title != title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+
         // ---- Top panel: menu bar ----
         egui::TopBottomPanel::top("top_bar").show(ctx, |ui| {
             panels::top_bar(ui, &mut self.state);
         });
 
+        // ---- Active filter chips (only when something is filtered) ----
+        let has_active_filters = self.state.tab.dataset.as_ref().is_some_and(|ds| {
+            !crate::data::filter::active_filters(ds, &self.state.tab.filters).is_empty()
+                || !self.state.tab.metric_filters.is_empty()
+        });
+        if has_active_filters {
+            egui::TopBottomPanel::top("filter_chips").show(ctx, |ui| {
+                panels::filter_chips(ui, &mut self.state);
+            });
+        }
+
         // ---- Left side panel: filters ----
         egui::SidePanel::left("filter_panel")
             .default_width(220.0)
@@ -34,9 +224,49 @@ impl eframe::App for RustyPandaApp {
                 panels::side_panel(ui, &mut self.state);
             });
 
-        // ---- Central panel: plot ----
-        egui::CentralPanel::default().show(ctx, |ui| {
-            plot::spectral_plot(ui, &self.state);
+        // ---- Bottom panel: metadata of the clicked spectrum ----
+        if self.state.tab.selected_index.is_some() {
+            egui::TopBottomPanel::bottom("selection_panel")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    panels::selection_panel(ui, &mut self.state);
+                });
+        }
+
+        // ---- Right side panel: legend (continuous maps have a colour bar) ----
+        let discrete_colors = self
+            .state
+            .tab
+            .color_map
+            .as_ref()
+            .is_some_and(|cm| !matches!(cm.mode, ColorMode::Continuous { .. }));
+        if discrete_colors {
+            egui::SidePanel::right("legend_panel")
+                .default_width(160.0)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    panels::legend_panel(ui, &mut self.state);
+                });
+        }
+
+        // ---- Central panel: plot, heatmap, correlation matrix or PCA ----
+        let has_spectra = self
+            .state
+            .tab
+            .dataset
+            .as_ref()
+            .is_some_and(|ds| !ds.is_empty());
+        egui::CentralPanel::default().show(ctx, |ui| match self.state.plot_view {
+            PlotView::Heatmap if has_spectra => self.heatmap.show(ui, &mut self.state),
+            PlotView::Correlation if has_spectra => self.correlation.show(ui, &mut self.state),
+            PlotView::Pca if has_spectra => self.pca.show(ui, &mut self.state),
+            _ => plot::spectral_plot(ui, &mut self.state),
         });
+
+        panels::png_export_window(ctx, &mut self.state);
+
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            panels::drop_overlay(ctx);
+        }
     }
 }
@@ -0,0 +1,205 @@
+//! Convert spectral data files to Parquet without starting the viewer.
+//!
+//! ```text
+//! convert INPUT OUTPUT
+//! convert --glob PATTERN INPUT_DIR OUTPUT_DIR
+//! ```
+//!
+//! A single file is written to `OUTPUT` (or, if that is a directory, to
+//! `OUTPUT/<name>.parquet`). A directory is converted file by file into
+//! `OUTPUT_DIR`, which is created if needed: every supported file directly
+//! inside it, or those whose names match `PATTERN` (`*` and `?`
+//! wildcards). A file that fails to convert is reported and skipped, as is
+//! one that would overwrite the output of an earlier file in name order
+//! (`a.csv.gz` or `a.json` after `a.csv`).
+
+#[allow(dead_code)]
+#[path = "../data/mod.rs"]
+mod data;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{Context, Result, bail};
+
+use data::loader::{FileFormat, load_file, save_parquet};
+use data::model::LoadOptions;
+
+const USAGE: &str = "Usage: convert [--glob PATTERN] INPUT OUTPUT";
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let mut glob: Option<String> = None;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--glob" => match args.next() {
+                Some(pattern) => glob = Some(pattern),
+                None => return usage_error("--glob needs a pattern"),
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [input, output] = paths.as_slice() else {
+        return usage_error("expected an input and an output path");
+    };
+
+    let jobs = match conversion_jobs(input, output, glob.as_deref()) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let options = LoadOptions::default();
+    let mut failed = 0;
+    // Output file → the input written to it.
+    let mut written: HashMap<&Path, &Path> = HashMap::new();
+    for (from, to) in &jobs {
+        if let Some(first) = written.get(to.as_path()) {
+            failed += 1;
+            eprintln!(
+                "{}: not converted, {} is already written from {}",
+                from.display(),
+                to.display(),
+                first.display()
+            );
+            continue;
+        }
+        written.insert(to, from);
+        match convert(from, to, &options) {
+            Ok(n) => println!("{} → {}: {n} spectra", from.display(), to.display()),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {e:#}", from.display());
+            }
+        }
+    }
+    if jobs.len() > 1 || failed > 0 {
+        println!("Converted {} of {} files", jobs.len() - failed, jobs.len());
+    }
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("Error: {message}\n{USAGE}");
+    ExitCode::FAILURE
+}
+
+/// The (input file, output file) pairs to convert.
+fn conversion_jobs(
+    input: &Path,
+    output: &Path,
+    glob: Option<&str>,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    if !input.is_dir() {
+        if glob.is_some() {
+            bail!("--glob needs a directory as input");
+        }
+        let to = if output.is_dir() {
+            output.join(parquet_name(input))
+        } else {
+            output.to_path_buf()
+        };
+        return Ok(vec![(input.to_path_buf(), to)]);
+    }
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(input)
+        .with_context(|| format!("reading directory {}", input.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            let name = p.file_name().unwrap_or_default().to_string_lossy();
+            match glob {
+                Some(pattern) => wildcard_match(pattern, &name),
+                None => is_supported(p),
+            }
+        })
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        bail!("No files to convert in {}", input.display());
+    }
+    std::fs::create_dir_all(output)
+        .with_context(|| format!("creating directory {}", output.display()))?;
+    Ok(files
+        .into_iter()
+        .map(|from| {
+            let to = output.join(parquet_name(&from));
+            (from, to)
+        })
+        .collect())
+}
+
+/// Load `from` and write all of its spectra to `to`, returning how many.
+fn convert(from: &Path, to: &Path, options: &LoadOptions) -> Result<usize> {
+    let dataset = load_file(from, options).context("loading")?;
+    let indices: Vec<usize> = (0..dataset.len()).collect();
+    save_parquet(to, &dataset, &indices, None)
+        .with_context(|| format!("writing {}", to.display()))?;
+    Ok(dataset.len())
+}
+
+/// Whether `path` has the extension of a loadable file, possibly gzipped.
+fn is_supported(path: &Path) -> bool {
+    let inner = strip_gz(path);
+    let ext = inner.extension().unwrap_or_default().to_string_lossy();
+    FileFormat::from_extension(&ext).is_some()
+}
+
+/// `path` without a trailing `.gz`.
+fn strip_gz(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("gz") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// File name of the Parquet file `path` converts to: `a.json.gz` →
+/// `a.parquet`.
+fn parquet_name(path: &Path) -> PathBuf {
+    let mut name = strip_gz(path)
+        .file_stem()
+        .unwrap_or_default()
+        .to_os_string();
+    name.push(".parquet");
+    PathBuf::from(name)
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of
+/// characters and `?` any single character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    // Position in `p` of the last `*` seen and in `n` where it started.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut i, mut j) = (0, 0);
+    while j < n.len() {
+        if i < p.len() && (p[i] == '?' || p[i] == n[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == '*' {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            // Let the `*` swallow one more character.
+            i = si + 1;
+            j = sj + 1;
+            star = Some((si, sj + 1));
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|&c| c == '*')
+}
@@ -7,13 +7,16 @@ use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
 use parquet::arrow::ArrowWriter;
 
+/// A Gaussian peak: (centre, width, amplitude).
+type Peak = (f64, f64, f64);
+
 fn gaussian(x: f64, mu: f64, sigma: f64, amplitude: f64) -> f64 {
     amplitude * (-(x - mu).powi(2) / (2.0 * sigma.powi(2))).exp()
 }
 
 fn generate_spectrum(
     wavenumbers: &[f64],
-    peaks: &[(f64, f64, f64)],
+    peaks: &[Peak],
     noise_level: f64,
     rng: &mut SimpleRng,
 ) -> Vec<f64> {
@@ -82,7 +85,7 @@ fn main() {
     let concentrations = [0.1, 0.5, 1.0, 2.0, 5.0];
     let operators = ["Alice", "Bob"];
 
-    let sample_peaks: Vec<(&str, Vec<(f64, f64, f64)>)> = vec![
+    let sample_peaks: Vec<(&str, Vec<Peak>)> = vec![
         ("Sample_A", vec![(3400.0, 80.0, 0.8), (2900.0, 40.0, 0.5), (2350.0, 30.0, 0.3)]),
         ("Sample_B", vec![(3200.0, 60.0, 0.6), (2800.0, 50.0, 0.7), (2500.0, 35.0, 0.4)]),
         ("Sample_C", vec![(3600.0, 70.0, 0.9), (3000.0, 45.0, 0.4), (2200.0, 25.0, 0.5)]),
@@ -1,9 +1,10 @@
 use std::collections::BTreeMap;
 
 use eframe::egui::Color32;
-use palette::{Hsl, IntoColor, Srgb};
+use palette::{Hsl, IntoColor, LinSrgb, Mix, Srgb};
+use serde::{Deserialize, Serialize};
 
-use crate::data::model::MetadataValue;
+use crate::data::model::{MetadataValue, numeric_range};
 
 // ---------------------------------------------------------------------------
 // Color palette generator
@@ -28,48 +29,497 @@ pub fn generate_palette(n: usize) -> Vec<Color32> {
         .collect()
 }
 
+/// The Okabe-Ito colourblind-safe qualitative palette.
+const OKABE_ITO: [Color32; 8] = [
+    Color32::from_rgb(0xe6, 0x9f, 0x00),
+    Color32::from_rgb(0x56, 0xb4, 0xe9),
+    Color32::from_rgb(0x00, 0x9e, 0x73),
+    Color32::from_rgb(0xf0, 0xe4, 0x42),
+    Color32::from_rgb(0x00, 0x72, 0xb2),
+    Color32::from_rgb(0xd5, 0x5e, 0x00),
+    Color32::from_rgb(0xcc, 0x79, 0xa7),
+    Color32::from_rgb(0x00, 0x00, 0x00),
+];
+
+/// Generates `n` colourblind-safe colours from the Okabe-Ito palette.
+/// Falls back to [`generate_palette`] when `n` exceeds its 8 colours.
+pub fn generate_palette_cvd(n: usize) -> Vec<Color32> {
+    if n <= OKABE_ITO.len() {
+        OKABE_ITO[..n].to_vec()
+    } else {
+        generate_palette(n)
+    }
+}
+
+/// Which palette discrete colour maps draw from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    /// Evenly spaced HSL hues.
+    #[default]
+    EvenHue,
+    /// Perceptually uniform viridis colormap.
+    Viridis,
+    /// Google's turbo rainbow colormap.
+    Turbo,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 3] = [
+        PaletteKind::EvenHue,
+        PaletteKind::Viridis,
+        PaletteKind::Turbo,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PaletteKind::EvenHue => "Even hue",
+            PaletteKind::Viridis => "Viridis",
+            PaletteKind::Turbo => "Turbo",
+        }
+    }
+
+    /// Generate `n` colours from this palette.
+    pub fn generate(self, n: usize) -> Vec<Color32> {
+        match self {
+            PaletteKind::EvenHue => generate_palette(n),
+            PaletteKind::Viridis => generate_viridis(n),
+            PaletteKind::Turbo => generate_turbo(n),
+        }
+    }
+}
+
+/// Viridis control points (sRGB).
+const VIRIDIS: [[u8; 3]; 10] = [
+    [0x44, 0x01, 0x54],
+    [0x48, 0x28, 0x78],
+    [0x3e, 0x49, 0x89],
+    [0x31, 0x68, 0x8e],
+    [0x26, 0x82, 0x8e],
+    [0x1f, 0x9e, 0x89],
+    [0x35, 0xb7, 0x79],
+    [0x6e, 0xce, 0x58],
+    [0xb5, 0xde, 0x2b],
+    [0xfd, 0xe7, 0x25],
+];
+
+/// Turbo control points (sRGB).
+const TURBO: [[u8; 3]; 15] = [
+    [0x30, 0x12, 0x3b],
+    [0x41, 0x45, 0xab],
+    [0x46, 0x75, 0xed],
+    [0x39, 0xa2, 0xfc],
+    [0x1b, 0xcf, 0xd4],
+    [0x24, 0xec, 0xa6],
+    [0x61, 0xfc, 0x6c],
+    [0xa4, 0xfc, 0x3b],
+    [0xd1, 0xe8, 0x34],
+    [0xf3, 0xc6, 0x3a],
+    [0xfe, 0x9b, 0x2d],
+    [0xf3, 0x63, 0x15],
+    [0xd9, 0x38, 0x06],
+    [0xb1, 0x19, 0x01],
+    [0x7a, 0x04, 0x03],
+];
+
+/// Generates `n` colours sampled evenly along the viridis colormap.
+pub fn generate_viridis(n: usize) -> Vec<Color32> {
+    sample_stops(&VIRIDIS, n)
+}
+
+/// Generates `n` colours sampled evenly along the turbo colormap.
+pub fn generate_turbo(n: usize) -> Vec<Color32> {
+    sample_stops(&TURBO, n)
+}
+
+/// Sample `n` evenly spaced colours from `stops`, end to end.
+/// A single colour is taken from the middle of the map.
+fn sample_stops(stops: &[[u8; 3]], n: usize) -> Vec<Color32> {
+    match n {
+        0 => Vec::new(),
+        1 => vec![interpolate_stops(stops, 0.5)],
+        _ => (0..n)
+            .map(|i| interpolate_stops(stops, i as f32 / (n - 1) as f32))
+            .collect(),
+    }
+}
+
+/// Colour at position `t` (clamped to `[0, 1]`) along `stops`,
+/// interpolated in linear RGB.
+fn interpolate_stops(stops: &[[u8; 3]], t: f32) -> Color32 {
+    let pos = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let i = (pos.floor() as usize).min(stops.len() - 2);
+    let to_linear = |[r, g, b]: [u8; 3]| -> LinSrgb { Srgb::new(r, g, b).into_linear() };
+    let mixed = to_linear(stops[i]).mix(to_linear(stops[i + 1]), pos - i as f32);
+    let rgb: Srgb<u8> = Srgb::from_linear(mixed);
+    Color32::from_rgb(rgb.red, rgb.green, rgb.blue)
+}
+
+/// Parse a `#rrggbb`, `#rrggbbaa` or `#rgb` hex string (leading `#` optional).
+pub fn parse_hex_color(s: &str) -> Option<Color32> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !hex.is_ascii() {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    match hex.len() {
+        3 => {
+            let nibble = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|v| v * 17);
+            Some(Color32::from_rgb(nibble(0)?, nibble(1)?, nibble(2)?))
+        }
+        6 => Some(Color32::from_rgb(byte(0)?, byte(2)?, byte(4)?)),
+        8 => Some(Color32::from_rgba_unmultiplied(
+            byte(0)?,
+            byte(2)?,
+            byte(4)?,
+            byte(6)?,
+        )),
+        _ => None,
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Color mapping: metadata value → Color32
 // ---------------------------------------------------------------------------
 
+/// How a [`ColorMap`] derives colours from metadata values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    /// One generated palette colour per unique value.
+    Discrete,
+    /// One colour per integer value, spread along the colour ramp in
+    /// increasing order.
+    Ordinal,
+    /// Numeric values are placed on a colour ramp between `min` and `max`.
+    Continuous { min: f64, max: f64 },
+    /// Each value is itself a hex colour string (e.g. `"#ff8800"`).
+    FromHex,
+}
+
+/// How a continuous [`ColorMap`] places values on the colour ramp.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorScale {
+    /// Proportionally between the column's minimum and maximum.
+    #[default]
+    Linear,
+    /// By percentile among the spectra, so a few extreme values do not
+    /// squeeze the rest into one end of the ramp.
+    Quantile,
+}
+
+impl ColorScale {
+    pub const ALL: [ColorScale; 2] = [ColorScale::Linear, ColorScale::Quantile];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorScale::Linear => "Linear",
+            ColorScale::Quantile => "Quantile",
+        }
+    }
+}
+
+/// Colour at position `t` in `[0, 1]` on a blue → red hue ramp.
+pub fn ramp_color(t: f64) -> Color32 {
+    let t = t.clamp(0.0, 1.0) as f32;
+    let hsl = Hsl::new(240.0 * (1.0 - t), 0.75, 0.55);
+    let rgb: Srgb = hsl.into_color();
+    Color32::from_rgb(
+        (rgb.red * 255.0) as u8,
+        (rgb.green * 255.0) as u8,
+        (rgb.blue * 255.0) as u8,
+    )
+}
+
+/// Integer columns with more distinct values than this are coloured as
+/// ordinal (along the ramp, by value) rather than with a categorical palette.
+pub const ORDINAL_MIN_VALUES: usize = 12;
+
+/// Brightest line colour (by sRGB luma, in `[0, 1]`) kept as is on a light
+/// background.
+const MAX_LUMA_ON_LIGHT: f32 = 0.55;
+
+/// Scale `color` down to at most [`MAX_LUMA_ON_LIGHT`] so it stays legible
+/// on a white background, keeping its hue.
+pub fn darken_for_light_background(color: Color32) -> Color32 {
+    let [r, g, b] = [color.r(), color.g(), color.b()].map(|c| c as f32 / 255.0);
+    let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luma <= MAX_LUMA_ON_LIGHT {
+        return color;
+    }
+    let channel = |c: f32| (c * MAX_LUMA_ON_LIGHT / luma * 255.0).round() as u8;
+    Color32::from_rgba_premultiplied(channel(r), channel(g), channel(b), color.a())
+}
+
 /// Maps unique metadata values of a chosen column to distinct colours.
 #[derive(Debug, Clone)]
 pub struct ColorMap {
     pub column: String,
+    pub mode: ColorMode,
     mapping: BTreeMap<MetadataValue, Color32>,
+    /// User-chosen colours, taking precedence over the mode's colours.
+    overrides: BTreeMap<MetadataValue, Color32>,
     default_color: Color32,
+    /// Every spectrum's value, sorted, when a continuous map places values
+    /// by rank ([`ColorScale::Quantile`]); empty on a linear scale.
+    sorted: Vec<f64>,
+    /// User-defined order of the column's values, which colours and legend
+    /// entries follow (see [`MetadataValue::custom_cmp`]); empty if none.
+    order: Vec<MetadataValue>,
 }
 
 impl ColorMap {
-    /// Build a colour map for the given column from its unique values.
-    pub fn new(column: &str, unique_values: &std::collections::BTreeSet<MetadataValue>) -> Self {
-        let palette = generate_palette(unique_values.len());
+    /// Build a colour map for the given column from its unique values,
+    /// drawing colours from `palette` in the user's `order` of the values
+    /// (sorted order if empty). Integer columns with more than
+    /// [`ORDINAL_MIN_VALUES`] values are ordinal and use the colour ramp.
+    pub fn new(
+        column: &str,
+        unique_values: &std::collections::BTreeSet<MetadataValue>,
+        palette: PaletteKind,
+        order: &[MetadataValue],
+    ) -> Self {
+        if let Some(mut values) = ordinal_values(unique_values) {
+            if !order.is_empty() {
+                values.sort_by(|a, b| a.custom_cmp(b, order));
+            }
+            return Self::ordinal(column, &values).with_order(order);
+        }
+        let palette = palette.generate(unique_values.len());
+        Self::from_colors(column, &in_order(unique_values, order), palette).with_order(order)
+    }
+
+    /// Keep `order` to sort the legend entries by.
+    fn with_order(mut self, order: &[MetadataValue]) -> Self {
+        self.order = order.to_vec();
+        self
+    }
+
+    /// Spread `values`, in increasing order, evenly along the colour ramp.
+    fn ordinal(column: &str, values: &[&MetadataValue]) -> Self {
+        let last = values.len().saturating_sub(1).max(1) as f64;
+        let mapping: BTreeMap<MetadataValue, Color32> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| ((*v).clone(), ramp_color(i as f64 / last)))
+            .collect();
+
+        ColorMap {
+            column: column.to_string(),
+            mode: ColorMode::Ordinal,
+            mapping,
+            overrides: BTreeMap::new(),
+            default_color: Color32::GRAY,
+            sorted: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Build a colour map using the colourblind-safe Okabe-Ito palette, in
+    /// the user's `order` of the values (sorted order if empty).
+    pub fn cvd_safe(
+        column: &str,
+        unique_values: &std::collections::BTreeSet<MetadataValue>,
+        order: &[MetadataValue],
+    ) -> Self {
+        let palette = generate_palette_cvd(unique_values.len());
+        Self::from_colors(column, &in_order(unique_values, order), palette).with_order(order)
+    }
+
+    /// Assign `palette` colours to `values` in order.
+    fn from_colors(column: &str, values: &[&MetadataValue], palette: Vec<Color32>) -> Self {
+        let mapping: BTreeMap<MetadataValue, Color32> = values
+            .iter()
+            .zip(palette)
+            .map(|(v, c)| ((*v).clone(), c))
+            .collect();
+
+        ColorMap {
+            column: column.to_string(),
+            mode: ColorMode::Discrete,
+            mapping,
+            overrides: BTreeMap::new(),
+            default_color: Color32::GRAY,
+            sorted: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Build a colour map that takes each value as a hex colour string,
+    /// listing them in the legend in the user's `order`. Values that are
+    /// not valid hex colours fall back to the default colour.
+    pub fn from_hex(
+        column: &str,
+        unique_values: &std::collections::BTreeSet<MetadataValue>,
+        order: &[MetadataValue],
+    ) -> Self {
         let mapping: BTreeMap<MetadataValue, Color32> = unique_values
             .iter()
-            .zip(palette.into_iter())
-            .map(|(v, c): (&MetadataValue, Color32)| (v.clone(), c))
+            .filter_map(|v| match v {
+                MetadataValue::String(s) => parse_hex_color(s).map(|c| (v.clone(), c)),
+                _ => None,
+            })
             .collect();
 
         ColorMap {
             column: column.to_string(),
+            mode: ColorMode::FromHex,
             mapping,
+            overrides: BTreeMap::new(),
             default_color: Color32::GRAY,
+            sorted: Vec::new(),
+            order: order.to_vec(),
         }
     }
 
+    /// Build a colour map placing each numeric value on a colour ramp between
+    /// the column's minimum and maximum. Non-numeric values get the default.
+    pub fn continuous(
+        column: &str,
+        unique_values: &std::collections::BTreeSet<MetadataValue>,
+    ) -> Self {
+        let (min, max) = numeric_range(unique_values).unwrap_or((0.0, 1.0));
+        ColorMap {
+            column: column.to_string(),
+            mode: ColorMode::Continuous { min, max },
+            mapping: BTreeMap::new(),
+            overrides: BTreeMap::new(),
+            default_color: Color32::GRAY,
+            sorted: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Build a continuous colour map placing each numeric value on the
+    /// colour ramp by its percentile among `values`, the column's value in
+    /// every spectrum. Falls back to a linear scale without any.
+    pub fn quantile(
+        column: &str,
+        unique_values: &std::collections::BTreeSet<MetadataValue>,
+        mut values: Vec<f64>,
+    ) -> Self {
+        values.retain(|v| !v.is_nan());
+        values.sort_by(f64::total_cmp);
+        ColorMap {
+            sorted: values,
+            ..Self::continuous(column, unique_values)
+        }
+    }
+
+    /// Position of `v` on the colour ramp of a continuous map: its
+    /// mid-rank among the sorted values on a quantile scale, found by
+    /// binary search, or its fraction of the way from `min` to `max`.
+    fn ramp_position(&self, v: f64, min: f64, max: f64) -> f64 {
+        let n = self.sorted.len();
+        if n > 1 {
+            let below = self.sorted.partition_point(|&s| s < v);
+            let up_to = self.sorted.partition_point(|&s| s <= v);
+            ((below + up_to) as f64 / 2.0 - 0.5).clamp(0.0, (n - 1) as f64) / (n - 1) as f64
+        } else if n == 0 && max > min {
+            (v - min) / (max - min)
+        } else {
+            0.5
+        }
+    }
+
+    /// The value at position `t` in `[0, 1]` of a continuous map's colour
+    /// ramp: the `t` quantile on a quantile scale, interpolated between
+    /// neighbouring values.
+    pub fn ramp_value(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        if let Some(&last) = self.sorted.last() {
+            let pos = t * (self.sorted.len() - 1) as f64;
+            let i = pos.floor() as usize;
+            let next = self.sorted.get(i + 1).copied().unwrap_or(last);
+            return self.sorted[i] + (pos - i as f64) * (next - self.sorted[i]);
+        }
+        match self.mode {
+            ColorMode::Continuous { min, max } => min + t * (max - min),
+            _ => t,
+        }
+    }
+
+    /// Number of values with an assigned (non-default) colour.
+    pub fn n_mapped(&self) -> usize {
+        self.mapping.len()
+    }
+
+    /// Always colour `value` with `color`, whatever the mode assigns.
+    pub fn set_override(&mut self, value: MetadataValue, color: Color32) {
+        self.overrides.insert(value, color);
+    }
+
+    /// Drop all manual colours, returning to the generated ones.
+    pub fn clear_overrides(&mut self) {
+        self.overrides.clear();
+    }
+
     /// Look up the colour for a given metadata value.
     pub fn color_for(&self, value: &MetadataValue) -> Color32 {
+        if let Some(color) = self.overrides.get(value) {
+            return *color;
+        }
+        if let ColorMode::Continuous { min, max } = self.mode {
+            return match value.as_f64() {
+                Some(v) => ramp_color(self.ramp_position(v, min, max)),
+                None => self.default_color,
+            };
+        }
         self.mapping
             .get(value)
             .copied()
             .unwrap_or(self.default_color)
     }
 
-    /// Return the legend entries (value label → colour) for the UI.
+    /// Return the legend entries (value label → colour) for the UI, in the
+    /// user's order of the values, if any, else natural order. Continuous
+    /// maps are sampled at evenly spaced ticks along the ramp: across the
+    /// range, or at quartiles on a quantile scale.
     pub fn legend_entries(&self) -> Vec<(String, Color32)> {
-        self.mapping
-            .iter()
-            .map(|(v, c): (&MetadataValue, &Color32)| (v.to_string(), *c))
+        if let ColorMode::Continuous { .. } = self.mode {
+            const TICKS: usize = 5;
+            return (0..TICKS)
+                .map(|i| {
+                    let t = i as f64 / (TICKS - 1) as f64;
+                    let v = self.ramp_value(t);
+                    (MetadataValue::Float(v).to_string(), ramp_color(t))
+                })
+                .collect();
+        }
+        let mut values: Vec<&MetadataValue> = self.mapping.keys().collect();
+        values.sort_by(|a, b| a.custom_cmp(b, &self.order));
+        values
+            .into_iter()
+            .map(|v| (v.to_string(), self.color_for(v)))
             .collect()
     }
 }
+
+/// `unique_values` in the user's `order` (see
+/// [`MetadataValue::custom_cmp`]), or in sorted order if there is none.
+fn in_order<'a>(
+    unique_values: &'a std::collections::BTreeSet<MetadataValue>,
+    order: &[MetadataValue],
+) -> Vec<&'a MetadataValue> {
+    let mut values: Vec<&MetadataValue> = unique_values.iter().collect();
+    if !order.is_empty() {
+        values.sort_by(|a, b| a.custom_cmp(b, order));
+    }
+    values
+}
+
+/// The non-null values of a column that should be coloured as ordinal: all
+/// integers and more than [`ORDINAL_MIN_VALUES`] of them, in increasing
+/// order.
+fn ordinal_values(
+    unique_values: &std::collections::BTreeSet<MetadataValue>,
+) -> Option<Vec<&MetadataValue>> {
+    let values: Vec<&MetadataValue> = unique_values
+        .iter()
+        .filter(|v| !matches!(v, MetadataValue::Null))
+        .collect();
+    let ordinal = values.len() > ORDINAL_MIN_VALUES
+        && values
+            .iter()
+            .all(|v| matches!(v, MetadataValue::Integer(_)));
+    ordinal.then_some(values)
+}
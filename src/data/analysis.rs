@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::group::{common_grid, group_stats, resample_to_grid};
+use super::model::Spectrum;
+
+// ---------------------------------------------------------------------------
+// Peak detection
+// ---------------------------------------------------------------------------
+
+/// Settings for marking peaks on the highlighted spectrum.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PeakParams {
+    /// Minimum prominence as a fraction of the spectrum's y range.
+    pub min_prominence: f64,
+    /// Minimum number of points between two peaks.
+    pub min_distance: usize,
+}
+
+impl Default for PeakParams {
+    fn default() -> Self {
+        Self {
+            min_prominence: 0.05,
+            min_distance: 5,
+        }
+    }
+}
+
+/// Indices of the local maxima of `y` (sampled at `x`) that stand out by at
+/// least `min_prominence` and are at least `min_distance` points apart.
+///
+/// A flat top counts as one peak at its middle. Prominence is the height of
+/// a peak above the higher of the lowest points on either side before a
+/// higher point (or the end of the data). When peaks are too close, the
+/// taller one is kept. The result is in increasing index order.
+pub fn find_peaks(x: &[f64], y: &[f64], min_prominence: f64, min_distance: usize) -> Vec<usize> {
+    let y = &y[..x.len().min(y.len())];
+    let mut peaks: Vec<usize> = local_maxima(y)
+        .into_iter()
+        .filter(|&p| prominence(y, p) >= min_prominence)
+        .collect();
+
+    // Keep peaks tallest first, dropping any within `min_distance` of a
+    // peak already kept.
+    peaks.sort_by(|&a, &b| y[b].total_cmp(&y[a]));
+    let mut kept: Vec<usize> = Vec::new();
+    for p in peaks {
+        if kept.iter().all(|&k| k.abs_diff(p) >= min_distance) {
+            kept.push(p);
+        }
+    }
+    kept.sort_unstable();
+    kept
+}
+
+/// Interior local maxima of `y`; a plateau yields its middle index.
+fn local_maxima(y: &[f64]) -> Vec<usize> {
+    let mut peaks = Vec::new();
+    let mut i = 1;
+    while i + 1 < y.len() {
+        if y[i - 1] < y[i] {
+            let mut ahead = i + 1;
+            while ahead + 1 < y.len() && y[ahead] == y[i] {
+                ahead += 1;
+            }
+            if y[ahead] < y[i] {
+                peaks.push((i + ahead - 1) / 2);
+                i = ahead;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    peaks
+}
+
+/// Prominence of the peak at `p`.
+fn prominence(y: &[f64], p: usize) -> f64 {
+    let base = |range: &mut dyn Iterator<Item = usize>| {
+        let mut min = y[p];
+        for i in range {
+            if y[i] > y[p] {
+                break;
+            }
+            min = min.min(y[i]);
+        }
+        min
+    };
+    let left = base(&mut (0..p).rev());
+    let right = base(&mut (p + 1..y.len()));
+    y[p] - left.max(right)
+}
+
+// ---------------------------------------------------------------------------
+// Derived spectrum properties
+// ---------------------------------------------------------------------------
+
+/// A quantity computed from a spectrum's raw points, for filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DerivedMetric {
+    MaxIntensity,
+    MinIntensity,
+    /// Area under the curve over the x grid.
+    Area,
+    /// x of the highest point.
+    PeakWavenumber,
+}
+
+impl DerivedMetric {
+    pub const ALL: [DerivedMetric; 4] = [
+        DerivedMetric::MaxIntensity,
+        DerivedMetric::MinIntensity,
+        DerivedMetric::Area,
+        DerivedMetric::PeakWavenumber,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DerivedMetric::MaxIntensity => "Max intensity",
+            DerivedMetric::MinIntensity => "Min intensity",
+            DerivedMetric::Area => "Area",
+            DerivedMetric::PeakWavenumber => "Peak wavenumber",
+        }
+    }
+
+    /// Value of this metric for `sp`; NaN if it has no finite points.
+    pub fn compute(self, sp: &Spectrum) -> f64 {
+        let n = sp.x.len().min(sp.y.len());
+        let (x, y) = (&sp.x[..n], &sp.y[..n]);
+        let highest = || {
+            (0..n)
+                .filter(|&i| y[i].is_finite())
+                .max_by(|&a, &b| y[a].total_cmp(&y[b]))
+        };
+        match self {
+            DerivedMetric::MaxIntensity => highest().map_or(f64::NAN, |i| y[i]),
+            DerivedMetric::MinIntensity => y
+                .iter()
+                .copied()
+                .filter(|v| v.is_finite())
+                .min_by(f64::total_cmp)
+                .unwrap_or(f64::NAN),
+            DerivedMetric::Area if n < 2 => f64::NAN,
+            DerivedMetric::Area => trapezoid_area(x, y),
+            DerivedMetric::PeakWavenumber => highest().map_or(f64::NAN, |i| x[i]),
+        }
+    }
+}
+
+/// Area under `y` sampled at `x` by the trapezoidal rule, taken as positive
+/// when x decreases. Segments touching a non-finite value are skipped.
+pub fn trapezoid_area(x: &[f64], y: &[f64]) -> f64 {
+    let area: f64 = x
+        .windows(2)
+        .zip(y.windows(2))
+        .filter(|(x, y)| x.iter().chain(y.iter()).all(|v| v.is_finite()))
+        .map(|(x, y)| (x[1] - x[0]) * (y[0] + y[1]) / 2.0)
+        .sum();
+    area.abs()
+}
+
+// ---------------------------------------------------------------------------
+// Similarity between spectra
+// ---------------------------------------------------------------------------
+
+/// Pairwise Pearson correlation of `spectra` resampled onto `grid`, as a
+/// symmetric matrix. Each pair is compared over the grid points where both
+/// have data; pairs with fewer than two such points, or with a constant
+/// series, are NaN. Rows are computed in parallel, as the cost is
+/// O(n² · grid points).
+pub fn correlation_matrix(spectra: &[&Spectrum], grid: &[f64]) -> Vec<Vec<f64>> {
+    let series: Vec<Vec<f64>> = spectra
+        .par_iter()
+        .map(|sp| resample_to_grid(&sp.x, &sp.y, grid))
+        .collect();
+    let n = series.len();
+    // Row i holds the correlations with spectra i.. only.
+    let upper: Vec<Vec<f64>> = (0..n)
+        .into_par_iter()
+        .map(|i| (i..n).map(|j| pearson(&series[i], &series[j])).collect())
+        .collect();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    if j < i {
+                        upper[j][i - j]
+                    } else {
+                        upper[i][j - i]
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Pearson correlation of `a` and `b` over the points where both are finite.
+fn pearson(a: &[f64], b: &[f64]) -> f64 {
+    let pairs = || {
+        a.iter()
+            .zip(b)
+            .filter(|(u, v)| u.is_finite() && v.is_finite())
+    };
+    let (n, sum_a, sum_b) = pairs().fold((0usize, 0.0, 0.0), |(n, sa, sb), (u, v)| {
+        (n + 1, sa + u, sb + v)
+    });
+    if n < 2 {
+        return f64::NAN;
+    }
+    let (mean_a, mean_b) = (sum_a / n as f64, sum_b / n as f64);
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (u, v) in pairs() {
+        let (du, dv) = (u - mean_a, v - mean_b);
+        cov += du * dv;
+        var_a += du * du;
+        var_b += dv * dv;
+    }
+    if var_a <= 0.0 || var_b <= 0.0 {
+        return f64::NAN;
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+// ---------------------------------------------------------------------------
+// Outliers within groups
+// ---------------------------------------------------------------------------
+
+/// RMS difference between each spectrum and the mean of its group, keyed by
+/// spectrum index. Each group (index, spectrum pairs) is resampled onto its
+/// own common grid; points where either is missing are skipped, and a
+/// spectrum with none left scores NaN.
+pub fn group_outlier_scores(groups: &[Vec<(usize, &Spectrum)>]) -> BTreeMap<usize, f64> {
+    let mut scores = BTreeMap::new();
+    for group in groups {
+        let members: Vec<&Spectrum> = group.iter().map(|(_, sp)| *sp).collect();
+        let grid = common_grid(&members);
+        let series: Vec<Vec<f64>> = members
+            .iter()
+            .map(|sp| resample_to_grid(&sp.x, &sp.y, &grid))
+            .collect();
+        let mean = group_stats(&series).mean;
+        for ((idx, _), s) in group.iter().zip(&series) {
+            let (n, sum_sq) = s
+                .iter()
+                .zip(&mean)
+                .map(|(v, m)| v - m)
+                .filter(|d| d.is_finite())
+                .fold((0usize, 0.0), |(n, sum), d| (n + 1, sum + d * d));
+            let rms = if n == 0 {
+                f64::NAN
+            } else {
+                (sum_sq / n as f64).sqrt()
+            };
+            scores.insert(*idx, rms);
+        }
+    }
+    scores
+}
+
+// ---------------------------------------------------------------------------
+// Principal component analysis
+// ---------------------------------------------------------------------------
+
+/// Leading principal components of a set of samples.
+#[derive(Debug, Clone, Default)]
+pub struct PcaResult {
+    /// Scores of each sample (row of the input) on the components.
+    pub scores: Vec<Vec<f64>>,
+    /// Fraction of the total variance explained by each component.
+    pub explained: Vec<f64>,
+}
+
+/// Most power iterations spent on one component.
+const PCA_MAX_ITER: usize = 500;
+
+/// Squared change of the unit component vector below which it has
+/// converged.
+const PCA_TOLERANCE: f64 = 1e-12;
+
+/// Fraction of the total variance below which what is left is rounding
+/// noise rather than another component.
+const PCA_RESIDUAL: f64 = 1e-10;
+
+/// Mean-centre `matrix` (one row per sample, e.g. spectra resampled onto a
+/// common grid) and find its `n_components` leading principal components by
+/// power iteration, removing each component from the data before finding
+/// the next.
+///
+/// Variables (columns) that are not finite in every row are left out. Fewer
+/// components are returned when the data has no variance left to explain.
+pub fn pca(matrix: &[Vec<f64>], n_components: usize) -> PcaResult {
+    let n = matrix.len();
+    let width = matrix.iter().map(Vec::len).min().unwrap_or(0);
+    let columns: Vec<usize> = (0..width)
+        .filter(|&j| matrix.iter().all(|row| row[j].is_finite()))
+        .collect();
+    if n < 2 || columns.is_empty() {
+        return PcaResult {
+            scores: vec![Vec::new(); n],
+            explained: Vec::new(),
+        };
+    }
+
+    let means: Vec<f64> = columns
+        .iter()
+        .map(|&j| matrix.iter().map(|row| row[j]).sum::<f64>() / n as f64)
+        .collect();
+    let mut residual: Vec<Vec<f64>> = matrix
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .zip(&means)
+                .map(|(&j, m)| row[j] - m)
+                .collect()
+        })
+        .collect();
+    let total = sum_of_squares(&residual);
+
+    let mut result = PcaResult {
+        scores: vec![Vec::new(); n],
+        explained: Vec::new(),
+    };
+    let mut components: Vec<Vec<f64>> = Vec::new();
+    while components.len() < n_components && sum_of_squares(&residual) > PCA_RESIDUAL * total {
+        let Some(v) = leading_component(&residual, &components) else {
+            break;
+        };
+        let scores: Vec<f64> = residual.iter().map(|row| dot(row, &v)).collect();
+        let variance: f64 = scores.iter().map(|s| s * s).sum();
+        result.explained.push(variance / total);
+        // Deflate: remove the component from the data before the next one.
+        for ((row, s), out) in residual.iter_mut().zip(scores).zip(&mut result.scores) {
+            for (xj, vj) in row.iter_mut().zip(&v) {
+                *xj -= s * vj;
+            }
+            out.push(s);
+        }
+        components.push(v);
+    }
+    result
+}
+
+fn sum_of_squares(x: &[Vec<f64>]) -> f64 {
+    x.iter().flatten().map(|v| v * v).sum()
+}
+
+/// Unit vector along which the rows of `x` vary most, orthogonal to every
+/// vector in `found`; `None` if no variance is left. The sign is chosen so
+/// the components sum to a positive value, which keeps the scores stable
+/// between runs.
+fn leading_component(x: &[Vec<f64>], found: &[Vec<f64>]) -> Option<Vec<f64>> {
+    // Start from the sample with the most variance left unexplained.
+    let mut v = x
+        .iter()
+        .map(|row| orthogonalize(row.clone(), found))
+        .max_by(|a, b| dot(a, a).total_cmp(&dot(b, b)))?;
+    normalize(&mut v)?;
+
+    for _ in 0..PCA_MAX_ITER {
+        // w = Xᵀ X v, without forming the covariance matrix.
+        let mut w = vec![0.0; v.len()];
+        for row in x {
+            let s = dot(row, &v);
+            for (wj, xj) in w.iter_mut().zip(row) {
+                *wj += s * xj;
+            }
+        }
+        let mut w = orthogonalize(w, found);
+        normalize(&mut w)?;
+        let change: f64 = w.iter().zip(&v).map(|(a, b)| (a - b).powi(2)).sum();
+        v = w;
+        if change < PCA_TOLERANCE {
+            break;
+        }
+    }
+    if v.iter().sum::<f64>() < 0.0 {
+        v.iter_mut().for_each(|c| *c = -*c);
+    }
+    Some(v)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(u, v)| u * v).sum()
+}
+
+/// Remove from `v` its projection onto each of the unit vectors `basis`.
+fn orthogonalize(mut v: Vec<f64>, basis: &[Vec<f64>]) -> Vec<f64> {
+    for u in basis {
+        let p = dot(&v, u);
+        for (vi, ui) in v.iter_mut().zip(u) {
+            *vi -= p * ui;
+        }
+    }
+    v
+}
+
+/// Scale `v` to unit length; `None` if it is zero.
+fn normalize(v: &mut [f64]) -> Option<()> {
+    let norm = dot(v, v).sqrt();
+    if !norm.is_normal() {
+        return None;
+    }
+    v.iter_mut().for_each(|c| *c /= norm);
+    Some(())
+}
@@ -1,62 +1,261 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::RangeInclusive;
 
-use super::model::{MetadataValue, SpectralDataset};
+use serde::{Deserialize, Serialize};
+
+use super::model::{MetadataValue, SpectralDataset, date_range, numeric_range};
 
 // ---------------------------------------------------------------------------
-// Filter predicate: which unique values are selected per column
+// Filter predicate: which values are selected per column
 // ---------------------------------------------------------------------------
 
-/// Per-column selection state: maps column_name → set of selected values.
-/// If a column is absent or its set is empty, it means "no filter" (show all).
-pub type FilterState = BTreeMap<String, BTreeSet<MetadataValue>>;
+/// How a single column is filtered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FilterKind {
+    /// Spectra whose value is in the set pass.
+    Values(BTreeSet<MetadataValue>),
+    /// Spectra whose numeric value lies in the range pass; non-numeric and
+    /// missing values fail.
+    NumericRange(RangeInclusive<f64>),
+    /// Spectra whose date falls on a day in the range (`YYYY-MM-DD` bounds,
+    /// compared as ISO strings) pass; non-date and missing values fail.
+    DateRange(RangeInclusive<String>),
+    /// Spectra whose numeric value compares true against `value` pass;
+    /// non-numeric and missing values fail.
+    Comparison { op: CompareOp, value: f64 },
+}
+
+/// Operator of a [`FilterKind::Comparison`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareOp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+}
+
+impl CompareOp {
+    pub const ALL: [CompareOp; 5] = [
+        CompareOp::Gt,
+        CompareOp::Ge,
+        CompareOp::Lt,
+        CompareOp::Le,
+        CompareOp::Eq,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Eq => "==",
+        }
+    }
+
+    /// Whether `lhs op rhs` holds.
+    pub fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Eq => lhs == rhs,
+        }
+    }
+}
+
+impl Default for FilterKind {
+    fn default() -> Self {
+        FilterKind::Values(BTreeSet::new())
+    }
+}
+
+impl FilterKind {
+    /// Whether a spectrum with `value` in this column (`None` if the column
+    /// is missing) passes the filter.
+    pub fn matches(&self, value: Option<&MetadataValue>) -> bool {
+        match self {
+            FilterKind::Values(selected) => {
+                selected.contains(value.unwrap_or(&MetadataValue::Null))
+            }
+            FilterKind::NumericRange(range) => value
+                .and_then(MetadataValue::as_f64)
+                .is_some_and(|v| range.contains(&v)),
+            FilterKind::DateRange(range) => value
+                .and_then(MetadataValue::as_date)
+                .is_some_and(|d| range.start().as_str() <= d && d <= range.end().as_str()),
+            FilterKind::Comparison { op, value: rhs } => value
+                .and_then(MetadataValue::as_f64)
+                .is_some_and(|v| op.holds(v, *rhs)),
+        }
+    }
+
+    /// Whether this filter excludes any of the column's values `all_vals`.
+    pub fn is_active(&self, all_vals: &BTreeSet<MetadataValue>) -> bool {
+        match self {
+            FilterKind::Values(selected) => selected.len() < all_vals.len(),
+            FilterKind::NumericRange(_)
+            | FilterKind::DateRange(_)
+            | FilterKind::Comparison { .. } => all_vals.iter().any(|v| !self.matches(Some(v))),
+        }
+    }
+
+    /// Values of `all_vals` that pass the filter.
+    pub fn selected_values(&self, all_vals: &BTreeSet<MetadataValue>) -> BTreeSet<MetadataValue> {
+        match self {
+            FilterKind::Values(selected) => selected.clone(),
+            FilterKind::NumericRange(_)
+            | FilterKind::DateRange(_)
+            | FilterKind::Comparison { .. } => all_vals
+                .iter()
+                .filter(|v| self.matches(Some(v)))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Bounds of the selection for a range widget, if it has numeric values.
+    pub fn numeric_bounds(&self) -> Option<(f64, f64)> {
+        match self {
+            FilterKind::Values(selected) => numeric_range(selected),
+            FilterKind::NumericRange(range) => Some((*range.start(), *range.end())),
+            FilterKind::DateRange(_) | FilterKind::Comparison { .. } => None,
+        }
+    }
+
+    /// Bounds of the selection for a date range widget, if it has dates.
+    pub fn date_bounds(&self) -> Option<(&str, &str)> {
+        match self {
+            FilterKind::Values(selected) => date_range(selected),
+            FilterKind::DateRange(range) => Some((range.start(), range.end())),
+            FilterKind::NumericRange(_) | FilterKind::Comparison { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterKind::Values(selected) => {
+                let values: Vec<String> = selected.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", values.join(", "))
+            }
+            FilterKind::NumericRange(range) => write!(
+                f,
+                "{} – {}",
+                MetadataValue::Float(*range.start()),
+                MetadataValue::Float(*range.end())
+            ),
+            FilterKind::DateRange(range) => write!(f, "{} – {}", range.start(), range.end()),
+            FilterKind::Comparison { op, value } => {
+                write!(f, "{} {}", op.label(), MetadataValue::Float(*value))
+            }
+        }
+    }
+}
+
+/// How the filters of several columns combine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterLogic {
+    /// A spectrum must pass every active column filter.
+    #[default]
+    And,
+    /// A spectrum must pass at least one active column filter.
+    Or,
+}
+
+impl FilterLogic {
+    pub const ALL: [FilterLogic; 2] = [FilterLogic::And, FilterLogic::Or];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterLogic::And => "All columns",
+            FilterLogic::Or => "Any column",
+        }
+    }
+}
+
+/// Range filter on a value computed per spectrum rather than read from the
+/// metadata.
+pub struct MetricFilter<'a> {
+    /// The value of every spectrum, by index.
+    pub values: &'a [f64],
+    /// Spectra whose value lies in the range pass; NaN fails.
+    pub range: &'a RangeInclusive<f64>,
+}
+
+/// Per-column filter state: maps column_name → filter.
+/// If a column is absent it means "no filter" (show all).
+pub type FilterState = BTreeMap<String, FilterKind>;
 
 /// Initialise a [`FilterState`] with all values selected (i.e., show everything).
 pub fn init_filter_state(dataset: &SpectralDataset) -> FilterState {
     dataset
         .unique_values
         .iter()
-        .map(|(col, vals)| (col.clone(), vals.clone()))
+        .map(|(col, vals)| (col.clone(), FilterKind::Values(vals.clone())))
         .collect()
 }
 
-/// Return indices of spectra that pass all active filters.
+/// Columns whose filter excludes some of their unique values, paired with
+/// the filter.
+pub fn active_filters<'a>(
+    dataset: &SpectralDataset,
+    filters: &'a FilterState,
+) -> Vec<(&'a str, &'a FilterKind)> {
+    filters
+        .iter()
+        .filter(|(col, filter)| {
+            dataset
+                .unique_values
+                .get(*col)
+                .is_some_and(|all_vals| filter.is_active(all_vals))
+        })
+        .map(|(col, filter)| (col.as_str(), filter))
+        .collect()
+}
+
+/// Return indices of spectra that pass the active filters and the metric
+/// filters, combined with `logic`.
 ///
-/// A spectrum passes a column filter when:
-/// * The column is not present in `filters` → passes (no constraint)
-/// * The filter set for that column is empty → nothing selected → fails
-/// * The spectrum's value for that column is in the selected set → passes
-pub fn filtered_indices(dataset: &SpectralDataset, filters: &FilterState) -> Vec<usize> {
+/// Only active filters (ones excluding some of the column's values) take
+/// part; columns absent from `filters` or with everything selected never
+/// constrain. Against an active filter the spectrum's value (Null if
+/// missing) must match. Each metric filter counts as one more constraint.
+/// With no constraints every spectrum passes in both modes.
+///
+/// Under [`FilterLogic::Or`] a column with nothing selected matches no
+/// spectrum, so it adds nothing to the union; if every active column has
+/// nothing selected, nothing passes.
+pub fn filtered_indices(
+    dataset: &SpectralDataset,
+    filters: &FilterState,
+    logic: FilterLogic,
+    metrics: &[MetricFilter],
+) -> Vec<usize> {
+    let active = active_filters(dataset, filters);
     dataset
         .spectra
         .iter()
         .enumerate()
-        .filter(|(_, sp)| {
-            for (col, selected) in filters {
-                if selected.is_empty() {
-                    // Nothing selected for this column → hide everything
-                    return false;
-                }
-                // Check all unique values are selected → no effective filter
-                if let Some(all_vals) = dataset.unique_values.get(col) {
-                    if selected.len() == all_vals.len() {
-                        continue; // everything selected, no filtering needed
-                    }
-                }
-                match sp.metadata.get(col) {
-                    Some(val) => {
-                        if !selected.contains(val) {
-                            return false;
-                        }
-                    }
-                    None => {
-                        // spectrum doesn't have this column → include only if Null is selected
-                        if !selected.contains(&MetadataValue::Null) {
-                            return false;
-                        }
-                    }
-                }
+        .filter(|(i, sp)| {
+            if active.is_empty() && metrics.is_empty() {
+                return true;
+            }
+            let mut results = active
+                .iter()
+                .map(|(col, filter)| filter.matches(sp.metadata.get(*col)))
+                .chain(metrics.iter().map(|m| {
+                    m.values.get(*i).is_some_and(|v| m.range.contains(v))
+                }));
+            match logic {
+                FilterLogic::And => results.all(|pass| pass),
+                FilterLogic::Or => results.any(|pass| pass),
             }
-            true
         })
         .map(|(i, _)| i)
         .collect()
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use super::model::{MetadataValue, Spectrum, SpectralDataset};
+
+// ---------------------------------------------------------------------------
+// Grouping by a metadata column
+// ---------------------------------------------------------------------------
+
+/// Bucket spectrum indices by their value in `column`.
+/// Spectra lacking the column (or when no column is given) fall into the
+/// `Null` group.
+pub fn group_indices(
+    dataset: &SpectralDataset,
+    indices: &[usize],
+    column: Option<&str>,
+) -> BTreeMap<MetadataValue, Vec<usize>> {
+    let mut groups: BTreeMap<MetadataValue, Vec<usize>> = BTreeMap::new();
+    for &idx in indices {
+        let key = column
+            .and_then(|col| dataset.spectra[idx].metadata.get(col))
+            .cloned()
+            .unwrap_or(MetadataValue::Null);
+        groups.entry(key).or_default().push(idx);
+    }
+    groups
+}
+
+// ---------------------------------------------------------------------------
+// Common x grid and resampling
+// ---------------------------------------------------------------------------
+
+/// Build an evenly spaced ascending grid covering the x range shared by all
+/// `spectra`, with as many points as the longest spectrum.
+/// If the spectra do not overlap, the grid spans their combined range.
+pub fn common_grid(spectra: &[&Spectrum]) -> Vec<f64> {
+    let mut lo = f64::NEG_INFINITY;
+    let mut hi = f64::INFINITY;
+    let mut min_all = f64::INFINITY;
+    let mut max_all = f64::NEG_INFINITY;
+    let mut n_points = 0;
+
+    for sp in spectra {
+        let Some((min, max)) = finite_range(&sp.x) else {
+            continue;
+        };
+        lo = lo.max(min);
+        hi = hi.min(max);
+        min_all = min_all.min(min);
+        max_all = max_all.max(max);
+        n_points = n_points.max(sp.x.len());
+    }
+
+    if n_points == 0 {
+        return Vec::new();
+    }
+    if lo >= hi {
+        lo = min_all;
+        hi = max_all;
+    }
+    if n_points == 1 || lo >= hi {
+        return vec![lo];
+    }
+
+    let step = (hi - lo) / (n_points - 1) as f64;
+    (0..n_points).map(|i| lo + step * i as f64).collect()
+}
+
+/// Linearly interpolate `(x, y)` onto `grid`.
+/// `x` may be ascending or descending; grid points outside its range are NaN.
+pub fn resample_to_grid(x: &[f64], y: &[f64], grid: &[f64]) -> Vec<f64> {
+    let mut points: Vec<(f64, f64)> = x
+        .iter()
+        .zip(y)
+        .filter(|(xi, _)| xi.is_finite())
+        .map(|(&xi, &yi)| (xi, yi))
+        .collect();
+    if points.is_empty() {
+        return vec![f64::NAN; grid.len()];
+    }
+    if !points.windows(2).all(|w| w[0].0 <= w[1].0) {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    let (first_x, last_x) = (points[0].0, points[points.len() - 1].0);
+    grid.iter()
+        .map(|&g| {
+            if g < first_x || g > last_x {
+                return f64::NAN;
+            }
+            // First point with x >= g.
+            let hi = points.partition_point(|p| p.0 < g);
+            if hi == 0 {
+                return points[0].1;
+            }
+            let (x0, y0) = points[hi - 1];
+            let (x1, y1) = points[hi];
+            if x1 == x0 {
+                y1
+            } else {
+                y0 + (y1 - y0) * (g - x0) / (x1 - x0)
+            }
+        })
+        .collect()
+}
+
+/// Min and max of the finite values in `values`.
+fn finite_range(values: &[f64]) -> Option<(f64, f64)> {
+    values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold(None, |acc, &v| match acc {
+            None => Some((v, v)),
+            Some((min, max)) => Some((min.min(v), max.max(v))),
+        })
+}
+
+// ---------------------------------------------------------------------------
+// Per-point statistics across a group
+// ---------------------------------------------------------------------------
+
+/// Point-wise mean and sample standard deviation of resampled series.
+#[derive(Debug, Clone)]
+pub struct GroupStats {
+    pub mean: Vec<f64>,
+    pub std: Vec<f64>,
+}
+
+/// Compute point-wise mean and standard deviation across `series`, all of
+/// which must have the same length. NaN entries are ignored; points with no
+/// finite values are NaN, and the std of a single value is 0.
+pub fn group_stats(series: &[Vec<f64>]) -> GroupStats {
+    let len = series.first().map_or(0, |s| s.len());
+    let mut mean = Vec::with_capacity(len);
+    let mut std = Vec::with_capacity(len);
+
+    for i in 0..len {
+        let (mut n, mut sum, mut sum_sq) = (0usize, 0.0, 0.0);
+        for s in series {
+            let v = s[i];
+            if v.is_finite() {
+                n += 1;
+                sum += v;
+                sum_sq += v * v;
+            }
+        }
+        if n == 0 {
+            mean.push(f64::NAN);
+            std.push(f64::NAN);
+            continue;
+        }
+        let m = sum / n as f64;
+        let var = if n > 1 {
+            ((sum_sq - n as f64 * m * m) / (n - 1) as f64).max(0.0)
+        } else {
+            0.0
+        };
+        mean.push(m);
+        std.push(var.sqrt());
+    }
+
+    GroupStats { mean, std }
+}
@@ -0,0 +1,271 @@
+//! Out-of-core Parquet loading: the metadata of every row is read when the
+//! file is opened, the x/y lists of a spectrum only when it is asked for.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{Context, Result, bail};
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::{
+    ArrowReaderMetadata, ArrowReaderOptions, ParquetRecordBatchReaderBuilder, RowSelection,
+};
+
+use super::loader::{check_f64_list_type, extract_f64_list, metadata_columns};
+use super::model::{LoadOptions, SpectralDataset, Spectrum};
+
+/// Decoded spectra a [`LazyDataset`] keeps in memory. At least the default
+/// render limit, so a frame's lines stay cached until the next frame; the
+/// plot never draws more than this many spectra of a lazy dataset.
+pub const LAZY_CACHE_SPECTRA: usize = 8192;
+
+/// A wide-layout Parquet file whose spectra are decoded on demand, a row
+/// group at a time, and kept in a least-recently-used cache.
+///
+/// The metadata of every row lives in the [`SpectralDataset`] the file was
+/// opened into, as spectra with empty x/y; see [`load_lazy_parquet`].
+#[derive(Debug)]
+pub struct LazyDataset {
+    path: PathBuf,
+    metadata: ArrowReaderMetadata,
+    /// Index of the first row of each row group.
+    row_group_starts: Vec<usize>,
+    /// The x and y columns.
+    xy_mask: ProjectionMask,
+    cache: Mutex<SpectrumCache>,
+}
+
+/// Decoded spectra by row index, evicting the least recently used beyond
+/// [`LAZY_CACHE_SPECTRA`].
+#[derive(Debug, Default)]
+struct SpectrumCache {
+    spectra: HashMap<usize, (Arc<Spectrum>, u64)>,
+    /// Last use → row index, oldest first.
+    order: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+impl SpectrumCache {
+    /// The cached spectrum of row `idx`, marking it as just used.
+    fn get(&mut self, idx: usize) -> Option<Arc<Spectrum>> {
+        self.clock += 1;
+        let (sp, used) = self.spectra.get_mut(&idx)?;
+        self.order.remove(used);
+        *used = self.clock;
+        self.order.insert(self.clock, idx);
+        Some(sp.clone())
+    }
+
+    /// Cache the spectrum of row `idx`, evicting the oldest if full.
+    fn insert(&mut self, idx: usize, sp: Arc<Spectrum>) {
+        self.clock += 1;
+        if let Some((_, used)) = self.spectra.insert(idx, (sp, self.clock)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.clock, idx);
+        while self.spectra.len() > LAZY_CACHE_SPECTRA {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.spectra.remove(&oldest);
+        }
+    }
+}
+
+/// Open a wide-layout Parquet file (see [`super::loader::load_file`])
+/// without reading its spectra: only the metadata columns are scanned, to
+/// index unique values. The dataset's spectra have empty x/y until read
+/// through [`SpectralDataset::spectrum`] or [`SpectralDataset::spectra_at`].
+///
+/// Rows with a null x or y, or x and y of different lengths, cannot be
+/// skipped without reading them; they are kept and read as empty spectra.
+/// Quality flags need every spectrum's data, so are not computed.
+///
+/// `progress` receives the fraction of the file's rows scanned.
+pub fn load_lazy_parquet(
+    path: &Path,
+    options: &LoadOptions,
+    progress: &mut dyn FnMut(f64),
+) -> Result<SpectralDataset> {
+    let file = File::open(path).context("opening parquet file")?;
+    let metadata = ArrowReaderMetadata::load(&file, ArrowReaderOptions::default())
+        .context("reading parquet metadata")?;
+    let schema = metadata.schema().clone();
+    let column = |name: &str| {
+        schema
+            .index_of(name)
+            .map_err(|_| anyhow::anyhow!("Parquet file missing '{name}' column"))
+    };
+    let (x_idx, y_idx) = (column("x")?, column("y")?);
+    check_f64_list_type(schema.field(x_idx).data_type()).context("column 'x'")?;
+    check_f64_list_type(schema.field(y_idx).data_type()).context("column 'y'")?;
+
+    let mut row_group_starts = Vec::new();
+    let mut len = 0;
+    for group in metadata.metadata().row_groups() {
+        row_group_starts.push(len);
+        len += group.num_rows() as usize;
+    }
+
+    let meta_roots: Vec<usize> = (0..schema.fields().len())
+        .filter(|&i| i != x_idx && i != y_idx)
+        .collect();
+    let stub = |metadata| Spectrum {
+        x: Vec::new(),
+        y: Vec::new(),
+        metadata,
+    };
+    let mut stubs = Vec::with_capacity(len);
+    if meta_roots.is_empty() {
+        stubs.resize_with(len, || stub(BTreeMap::new()));
+    } else {
+        let mask = ProjectionMask::roots(metadata.parquet_schema(), meta_roots);
+        let reader = ParquetRecordBatchReaderBuilder::new_with_metadata(file, metadata.clone())
+            .with_projection(mask)
+            .build()
+            .context("building parquet reader")?;
+        for batch_result in reader {
+            let batch = batch_result.context("reading parquet record batch")?;
+            let meta_cols = metadata_columns(&batch, &[]);
+            stubs.extend((0..batch.num_rows()).map(|row| {
+                stub(
+                    meta_cols
+                        .iter()
+                        .map(|col| (col.name.clone(), col.value(row)))
+                        .collect(),
+                )
+            }));
+            progress((stubs.len() as f64 / len.max(1) as f64).min(1.0));
+        }
+    }
+
+    if options.compute_quality {
+        log::warn!("Quality flags are not computed for a lazily loaded file");
+    }
+    let options = LoadOptions {
+        compute_quality: false,
+        ..options.clone()
+    };
+    let xy_mask = ProjectionMask::roots(metadata.parquet_schema(), [x_idx, y_idx]);
+    let lazy = LazyDataset {
+        path: path.to_path_buf(),
+        metadata,
+        row_group_starts,
+        xy_mask,
+        cache: Mutex::default(),
+    };
+    Ok(SpectralDataset::from_lazy(stubs, lazy, &options))
+}
+
+impl LazyDataset {
+    /// The spectra of rows `indices`, reading those not cached from the
+    /// file, once per row group. `stubs` are the dataset's metadata-only
+    /// rows, giving each spectrum its metadata. Rows that fail to read come
+    /// back with empty x/y.
+    pub fn spectra(&self, indices: &[usize], stubs: &[Spectrum]) -> Vec<Arc<Spectrum>> {
+        let mut found: HashMap<usize, Arc<Spectrum>> = HashMap::new();
+        let mut missing: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        {
+            let mut cache = self.lock_cache();
+            for &idx in indices {
+                match cache.get(idx) {
+                    Some(sp) => {
+                        found.insert(idx, sp);
+                    }
+                    None => {
+                        missing.entry(self.row_group(idx)).or_default().insert(idx);
+                    }
+                }
+            }
+        }
+
+        // Read without holding the lock, so other callers are served from
+        // the cache meanwhile.
+        let mut read = Vec::new();
+        for (group, rows) in missing {
+            let rows: Vec<usize> = rows.into_iter().collect();
+            let lists = self.read_rows(group, &rows).unwrap_or_else(|e| {
+                log::error!(
+                    "Reading row group {group} of {}: {e:#}",
+                    self.path.display()
+                );
+                vec![(Vec::new(), Vec::new()); rows.len()]
+            });
+            for (idx, (x, y)) in rows.into_iter().zip(lists) {
+                let sp = Arc::new(Spectrum {
+                    x,
+                    y,
+                    metadata: stubs[idx].metadata.clone(),
+                });
+                read.push((idx, sp.clone()));
+                found.insert(idx, sp);
+            }
+        }
+        if !read.is_empty() {
+            let mut cache = self.lock_cache();
+            for (idx, sp) in read {
+                cache.insert(idx, sp);
+            }
+        }
+        indices.iter().map(|idx| found[idx].clone()).collect()
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, SpectrumCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The row group holding row `idx`.
+    fn row_group(&self, idx: usize) -> usize {
+        self.row_group_starts.partition_point(|&start| start <= idx) - 1
+    }
+
+    /// The x/y lists of `rows` (sorted, all in row group `group`).
+    fn read_rows(&self, group: usize, rows: &[usize]) -> Result<Vec<(Vec<f64>, Vec<f64>)>> {
+        let start = self.row_group_starts[group];
+        let group_len = self.metadata.metadata().row_group(group).num_rows() as usize;
+        let selection = RowSelection::from_consecutive_ranges(
+            rows.iter().map(|&row| row - start..row - start + 1),
+            group_len,
+        );
+        let file = File::open(&self.path).context("opening parquet file")?;
+        let reader =
+            ParquetRecordBatchReaderBuilder::new_with_metadata(file, self.metadata.clone())
+                .with_projection(self.xy_mask.clone())
+                .with_row_groups(vec![group])
+                .with_row_selection(selection)
+                .build()
+                .context("building parquet reader")?;
+
+        let mut lists = Vec::with_capacity(rows.len());
+        for batch_result in reader {
+            let batch = batch_result.context("reading parquet record batch")?;
+            let (Some(x_col), Some(y_col)) = (batch.column_by_name("x"), batch.column_by_name("y"))
+            else {
+                bail!("Parquet row group {group} missing 'x' or 'y' column");
+            };
+            for row in 0..batch.num_rows() {
+                let row_index = rows.get(lists.len()).copied().unwrap_or(start);
+                let x = extract_f64_list(x_col, row)
+                    .with_context(|| format!("Row {row_index}: failed to read 'x'"))?;
+                let y = extract_f64_list(y_col, row)
+                    .with_context(|| format!("Row {row_index}: failed to read 'y'"))?;
+                lists.push(match (x, y) {
+                    (Some(x), Some(y)) if x.len() == y.len() => (x, y),
+                    _ => {
+                        log::warn!("Row {row_index}: null x or y, or of different lengths; empty");
+                        (Vec::new(), Vec::new())
+                    }
+                });
+            }
+        }
+        if lists.len() != rows.len() {
+            bail!(
+                "Read {} rows of row group {group}, expected {}",
+                lists.len(),
+                rows.len()
+            );
+        }
+        Ok(lists)
+    }
+}
@@ -1,41 +1,337 @@
-use std::collections::BTreeMap;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result, bail};
+use arrow::array::temporal_conversions::as_datetime;
 use arrow::array::{
-    Array, AsArray, Float32Array, Float64Array, Int32Array, Int64Array,
-    LargeListArray, ListArray, StringArray, BooleanArray,
+    Array, ArrayRef, AsArray, BooleanArray, Float32Array, Float64Array, Float64Builder, Int32Array,
+    Int64Array, LargeListArray, ListArray, ListBuilder, StringArray,
 };
-use arrow::datatypes::DataType;
+use arrow::datatypes::{
+    DataType, Field, Schema, TimeUnit, TimestampMicrosecondType, TimestampMillisecondType,
+    TimestampNanosecondType, TimestampSecondType,
+};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::array_value_to_string;
+use bytes::Bytes;
+use calamine::{Data, Reader, open_workbook_auto_from_rs};
+use flate2::read::GzDecoder;
+use parquet::arrow::ArrowWriter;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::ChunkReader;
+use rayon::prelude::*;
 use serde_json::Value as JsonValue;
 
-use super::model::{MetadataValue, Spectrum, SpectralDataset};
+use super::lazy::load_lazy_parquet;
+use super::model::{
+    CsvDialect, CsvLayout, LoadOptions, MetadataValue, SpectralDataset, Spectrum, TableLayout,
+};
+use super::preprocess::trim_region;
 
 // ---------------------------------------------------------------------------
 // Public entry-point
 // ---------------------------------------------------------------------------
 
+/// A supported input file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Parquet,
+    Json,
+    Csv,
+    Jcamp,
+    Xlsx,
+    Mat,
+    Hdf5,
+}
+
+impl FileFormat {
+    /// Format for a (lower- or upper-case) file extension, without the dot.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "parquet" | "pq" => Some(FileFormat::Parquet),
+            "json" => Some(FileFormat::Json),
+            "csv" => Some(FileFormat::Csv),
+            "jdx" | "dx" | "jcamp" => Some(FileFormat::Jcamp),
+            "xlsx" => Some(FileFormat::Xlsx),
+            "mat" => Some(FileFormat::Mat),
+            "h5" | "hdf5" => Some(FileFormat::Hdf5),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the first bytes of a stream: Parquet files start
+    /// with `PAR1`, Excel workbooks (zip archives) with `PK`, MATLAB files
+    /// with `MATLAB`, HDF5 files with their signature, JSON with `[` or `{`,
+    /// JCAMP-DX with `##`; anything else is taken as CSV.
+    pub fn sniff(bytes: &[u8]) -> Self {
+        if bytes.starts_with(b"PAR1") {
+            return FileFormat::Parquet;
+        }
+        if bytes.starts_with(b"PK\x03\x04") {
+            return FileFormat::Xlsx;
+        }
+        if bytes.starts_with(b"MATLAB") {
+            return FileFormat::Mat;
+        }
+        if bytes.starts_with(HDF5_SIGNATURE) {
+            return FileFormat::Hdf5;
+        }
+        let start = bytes
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .map_or(&[][..], |i| &bytes[i..]);
+        match start {
+            [b'[' | b'{', ..] => FileFormat::Json,
+            [b'#', b'#', ..] => FileFormat::Jcamp,
+            _ => FileFormat::Csv,
+        }
+    }
+}
+
 /// Load a spectral dataset from a file.  Dispatch by extension.
 ///
 /// Supported formats:
 /// * `.parquet` – Parquet file with `x` and `y` list columns (recommended)
 /// * `.json`    – `[{ "x": [...], "y": [...], ...meta }, ...]`
-/// * `.csv`     – columns `x` and `y` containing semicolon-separated floats
-pub fn load_file(path: &Path) -> Result<SpectralDataset> {
-    let ext = path
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("")
-        .to_ascii_lowercase();
+/// * `.csv`     – columns `x` and `y` containing semicolon-separated floats,
+///   or one spectrum per row under numeric headers (see [`CsvLayout`]); the
+///   separators are set by [`CsvDialect`]
+/// * `.jdx`/`.dx` – JCAMP-DX, one spectrum per data block
+/// * `.xlsx`    – Excel sheet with x in the first column, one spectrum per
+///   further column
+///
+/// JSON and CSV may also be gzipped (`.json.gz`, `.csv.gz`); they are
+/// decompressed while parsing.
+///
+/// A directory is loaded with [`load_directory`].
+///
+/// `options` controls how the parsed rows are turned into a dataset.
+pub fn load_file(path: &Path, options: &LoadOptions) -> Result<SpectralDataset> {
+    load_file_with_progress(path, options, &mut |_| {})
+}
 
-    match ext.as_str() {
-        "parquet" | "pq" => load_parquet(path),
-        "json" => load_json(path),
-        "csv" => load_csv(path),
-        other => bail!("Unsupported file extension: .{other}"),
+/// Like [`load_file`], calling `progress` with the fraction of rows parsed
+/// so far (Parquet only; other formats report completion once).
+pub fn load_file_with_progress(
+    path: &Path,
+    options: &LoadOptions,
+    progress: &mut dyn FnMut(f64),
+) -> Result<SpectralDataset> {
+    if path.is_dir() {
+        return load_directory(path, options, progress);
+    }
+    let lazy = options.lazy_parquet && options.layout == TableLayout::Wide;
+    if lazy && file_format(path) == (false, Some(FileFormat::Parquet)) {
+        return load_lazy_parquet(path, options, progress);
+    }
+    let (spectra, skipped, errors) = load_spectra(path, options, progress)?;
+    progress(1.0);
+    let mut dataset = SpectralDataset::from_spectra(spectra, options);
+    dataset.n_skipped_rows = skipped;
+    dataset.load_errors = errors;
+    Ok(dataset)
+}
+
+/// Load every supported file directly inside `dir` (not recursing) into one
+/// dataset, as [`load_files`] does.
+pub fn load_directory(
+    dir: &Path,
+    options: &LoadOptions,
+    progress: &mut dyn FnMut(f64),
+) -> Result<SpectralDataset> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("reading directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && file_format(p).1.is_some())
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        bail!("No supported files in {}", dir.display());
+    }
+    load_files(&paths, options, progress)
+}
+
+/// Load several files into one dataset, adding a `source_file` column with
+/// each spectrum's file name.
+///
+/// Files that fail to parse are skipped with a warning; it is an error only
+/// if none of them load. `progress` is called with the fraction of files
+/// read so far.
+pub fn load_files(
+    paths: &[PathBuf],
+    options: &LoadOptions,
+    progress: &mut dyn FnMut(f64),
+) -> Result<SpectralDataset> {
+    let n = paths.len();
+    let mut spectra = Vec::new();
+    let mut skipped = 0;
+    let mut errors = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match load_spectra(path, options, &mut |_| {}) {
+            Ok((file_spectra, file_skipped, file_errors)) => {
+                skipped += file_skipped;
+                errors.extend(file_errors.into_iter().map(|e| format!("{name}: {e}")));
+                spectra.extend(file_spectra.into_iter().map(|mut sp| {
+                    sp.metadata.insert(
+                        "source_file".to_string(),
+                        MetadataValue::String(name.clone()),
+                    );
+                    sp
+                }));
+            }
+            Err(e) => log::warn!("Skipping {}: {e:#}", path.display()),
+        }
+        progress((i + 1) as f64 / n as f64);
+    }
+    if spectra.is_empty() {
+        bail!("None of the {n} files could be loaded");
+    }
+    let mut dataset = SpectralDataset::from_spectra(spectra, options);
+    dataset.n_skipped_rows = skipped;
+    dataset.load_errors = errors;
+    Ok(dataset)
+}
+
+/// Whether `path` is gzipped, and the format of its (decompressed) content.
+fn file_format(path: &Path) -> (bool, Option<FileFormat>) {
+    let extension = |p: &Path| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    // `name.csv.gz` is a gzipped `name.csv`.
+    let mut ext = extension(path);
+    let gzipped = ext.eq_ignore_ascii_case("gz");
+    if gzipped {
+        ext = extension(Path::new(path.file_stem().unwrap_or_default()));
+    }
+    (gzipped, FileFormat::from_extension(&ext))
+}
+
+/// Parse the spectra of a single file, dispatching on its extension, with
+/// the number of rows skipped as malformed and the errors of any other
+/// parts left out.
+fn load_spectra(
+    path: &Path,
+    options: &LoadOptions,
+    progress: &mut dyn FnMut(f64),
+) -> Result<(Vec<Spectrum>, usize, Vec<String>)> {
+    let (gzipped, format) = file_format(path);
+    let Some(format) = format else {
+        bail!("Unsupported file type: {}", path.display());
+    };
+
+    if gzipped {
+        let file = std::fs::File::open(path).context("opening gzipped file")?;
+        let reader = BufReader::new(GzDecoder::new(file));
+        let spectra = match format {
+            FileFormat::Json => load_json(reader)?,
+            FileFormat::Csv => load_csv_as(reader, options)?,
+            FileFormat::Jcamp => load_jcamp(reader)?,
+            FileFormat::Parquet => {
+                bail!("Gzipped Parquet is not supported; Parquet is already compressed")
+            }
+            FileFormat::Xlsx => {
+                bail!("Gzipped Excel is not supported; .xlsx is already compressed")
+            }
+            FileFormat::Mat => {
+                bail!("Gzipped MATLAB files are not supported; .mat v7 is already compressed")
+            }
+            FileFormat::Hdf5 => bail!("Gzipped HDF5 is not supported"),
+        };
+        return Ok((spectra, 0, Vec::new()));
     }
+
+    let mut errors = Vec::new();
+    let (spectra, skipped) = match format {
+        FileFormat::Parquet => match &options.layout {
+            TableLayout::Wide => load_parquet(
+                std::fs::File::open(path).context("opening parquet file")?,
+                progress,
+            )?,
+            TableLayout::Long {
+                id_col,
+                x_col,
+                y_col,
+            } => (load_long_parquet(path, id_col, x_col, y_col)?, 0),
+        },
+        FileFormat::Json => (
+            load_json(BufReader::new(
+                std::fs::File::open(path).context("opening JSON file")?,
+            ))?,
+            0,
+        ),
+        FileFormat::Csv => (
+            load_csv_as(std::fs::File::open(path).context("opening CSV")?, options)?,
+            0,
+        ),
+        FileFormat::Jcamp => (
+            load_jcamp(BufReader::new(
+                std::fs::File::open(path).context("opening JCAMP-DX file")?,
+            ))?,
+            0,
+        ),
+        FileFormat::Xlsx => {
+            let (spectra, column_errors) = load_xlsx(path)?;
+            errors = column_errors;
+            (spectra, 0)
+        }
+        FileFormat::Mat => (load_mat(path)?, 0),
+        FileFormat::Hdf5 => (load_hdf5(path)?, 0),
+    };
+    Ok((spectra, skipped, errors))
+}
+
+/// Load a spectral dataset from any reader, e.g. stdin.
+///
+/// The format must be given explicitly since there is no extension to go
+/// by. Parquet, Excel and MATLAB input is read whole, so it is buffered in
+/// memory; HDF5 can only be read from a file.
+pub fn load_reader(
+    mut reader: impl Read,
+    format: FileFormat,
+    options: &LoadOptions,
+) -> Result<SpectralDataset> {
+    let mut errors = Vec::new();
+    let (spectra, skipped) = match format {
+        FileFormat::Parquet => {
+            let mut buf = Vec::new();
+            reader
+                .read_to_end(&mut buf)
+                .context("reading parquet data")?;
+            load_parquet(Bytes::from(buf), &mut |_| {})?
+        }
+        FileFormat::Json => (load_json(reader)?, 0),
+        FileFormat::Csv => (load_csv_as(reader, options)?, 0),
+        FileFormat::Jcamp => (load_jcamp(BufReader::new(reader))?, 0),
+        FileFormat::Xlsx => {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).context("reading Excel data")?;
+            let (spectra, column_errors) = parse_xlsx(&buf)?;
+            errors = column_errors;
+            (spectra, 0)
+        }
+        FileFormat::Mat => {
+            let mut buf = Vec::new();
+            reader
+                .read_to_end(&mut buf)
+                .context("reading MATLAB data")?;
+            (parse_mat(&buf)?, 0)
+        }
+        FileFormat::Hdf5 => bail!("HDF5 data can only be loaded from a file"),
+    };
+    let mut dataset = SpectralDataset::from_spectra(spectra, options);
+    dataset.n_skipped_rows = skipped;
+    dataset.load_errors = errors;
+    Ok(dataset)
 }
 
 // ---------------------------------------------------------------------------
@@ -55,9 +351,8 @@ pub fn load_file(path: &Path) -> Result<SpectralDataset> {
 ///   ...
 /// ]
 /// ```
-fn load_json(path: &Path) -> Result<SpectralDataset> {
-    let text = std::fs::read_to_string(path).context("reading JSON file")?;
-    let root: JsonValue = serde_json::from_str(&text).context("parsing JSON")?;
+fn load_json(reader: impl Read) -> Result<Vec<Spectrum>> {
+    let root: JsonValue = serde_json::from_reader(reader).context("parsing JSON")?;
 
     let records = root
         .as_array()
@@ -88,7 +383,7 @@ fn load_json(path: &Path) -> Result<SpectralDataset> {
         spectra.push(Spectrum { x, y, metadata });
     }
 
-    Ok(SpectralDataset::from_spectra(spectra))
+    Ok(spectra)
 }
 
 fn json_array_to_f64(val: Option<&JsonValue>, row: usize, col: &str) -> Result<Vec<f64>> {
@@ -127,12 +422,77 @@ fn json_to_metadata(val: &JsonValue) -> MetadataValue {
 // CSV loader
 // ---------------------------------------------------------------------------
 
+/// Parse CSV in the layout and dialect of `options`.
+fn load_csv_as(reader: impl Read, options: &LoadOptions) -> Result<Vec<Spectrum>> {
+    let dialect = &options.csv_dialect;
+    let mut reader = BufReader::new(reader);
+    let mut header = Vec::new();
+    reader
+        .read_until(b'\n', &mut header)
+        .context("reading CSV headers")?;
+    let field_delim = match dialect.field_delim {
+        Some(c) => c,
+        None => sniff_field_delim(&String::from_utf8_lossy(&header), dialect.decimal),
+    };
+    let Ok(field_delim) = u8::try_from(field_delim) else {
+        bail!("CSV field delimiter '{field_delim}' is not an ASCII character");
+    };
+    let reader = csv::ReaderBuilder::new()
+        .delimiter(field_delim)
+        .from_reader(std::io::Cursor::new(header).chain(reader));
+    match options.csv_layout {
+        CsvLayout::Lists => load_csv(reader, dialect),
+        CsvLayout::Matrix => load_csv_matrix(reader, dialect),
+    }
+}
+
+/// The field delimiter of a CSV file with `header` as its first line: the
+/// most frequent of [`CsvDialect::FIELD_DELIMS`] outside quotes, or a
+/// comma (semicolon with a decimal comma) if there is none. The `decimal`
+/// separator is not a candidate, so numeric headers like `1000,5` are not
+/// split.
+fn sniff_field_delim(header: &str, decimal: char) -> char {
+    let mut counts = [0usize; CsvDialect::FIELD_DELIMS.len()];
+    let mut quoted = false;
+    for c in header.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && c != decimal {
+            if let Some(i) = CsvDialect::FIELD_DELIMS.iter().position(|&(d, _)| d == c) {
+                counts[i] += 1;
+            }
+        }
+    }
+    // `max_by_key` keeps the last maximum, so search from the back.
+    let (i, &count) = counts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|&(_, n)| n)
+        .unwrap_or((0, &0));
+    if count == 0 {
+        if decimal == ',' {
+            ';'
+        } else {
+            ','
+        }
+    } else {
+        CsvDialect::FIELD_DELIMS[i].0
+    }
+}
+
 /// CSV layout:  header row with column names.
-/// `x` and `y` columns contain semicolon-separated floats:
+/// `x` and `y` columns contain floats separated by the dialect's list
+/// delimiter, semicolons by default:
 ///   `"4000.0;3999.0;3998.0"`, `"0.12;0.14;0.11"`
 /// All other columns are treated as metadata.
-fn load_csv(path: &Path) -> Result<SpectralDataset> {
-    let mut reader = csv::Reader::from_path(path).context("opening CSV")?;
+fn load_csv(mut reader: csv::Reader<impl Read>, dialect: &CsvDialect) -> Result<Vec<Spectrum>> {
+    if dialect.list_delim == dialect.decimal {
+        bail!(
+            "CSV list delimiter and decimal separator are both '{}'",
+            dialect.list_delim
+        );
+    }
     let headers: Vec<String> = reader
         .headers()
         .context("reading CSV headers")?
@@ -154,8 +514,8 @@ fn load_csv(path: &Path) -> Result<SpectralDataset> {
     for (row_no, result) in reader.records().enumerate() {
         let record = result.with_context(|| format!("CSV row {row_no}"))?;
 
-        let x = parse_semicolon_floats(record.get(x_idx).unwrap_or(""), row_no, "x")?;
-        let y = parse_semicolon_floats(record.get(y_idx).unwrap_or(""), row_no, "y")?;
+        let x = parse_float_list(record.get(x_idx).unwrap_or(""), dialect, row_no, "x")?;
+        let y = parse_float_list(record.get(y_idx).unwrap_or(""), dialect, row_no, "y")?;
 
         if x.len() != y.len() {
             bail!(
@@ -171,26 +531,110 @@ fn load_csv(path: &Path) -> Result<SpectralDataset> {
                 continue;
             }
             let col_name = &headers[col_idx];
-            metadata.insert(col_name.clone(), guess_metadata_type(value));
+            metadata.insert(col_name.clone(), guess_csv_type(value, dialect));
         }
 
         spectra.push(Spectrum { x, y, metadata });
     }
 
-    Ok(SpectralDataset::from_spectra(spectra))
+    Ok(spectra)
 }
 
-fn parse_semicolon_floats(s: &str, row: usize, col: &str) -> Result<Vec<f64>> {
-    s.split(';')
+/// CSV matrix layout: one spectrum per row, with the x axis in the header.
+///
+/// Headers that parse as numbers are the x values, in column order, and
+/// the row's cells under them its intensities (empty cells are NaN). All
+/// other columns are metadata.
+fn load_csv_matrix(
+    mut reader: csv::Reader<impl Read>,
+    dialect: &CsvDialect,
+) -> Result<Vec<Spectrum>> {
+    let headers = reader.headers().context("reading CSV headers")?.clone();
+
+    // (column index, x value) of the numeric headers.
+    let x_cols: Vec<(usize, f64)> = headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| Some((i, dialect.parse_f64(h.trim())?)))
+        .collect();
+    if x_cols.is_empty() {
+        bail!("CSV matrix has no numeric column headers to use as x");
+    }
+    let x: Vec<f64> = x_cols.iter().map(|&(_, x)| x).collect();
+    let meta_cols: Vec<usize> = (0..headers.len())
+        .filter(|i| !x_cols.iter().any(|(x_idx, _)| x_idx == i))
+        .collect();
+
+    let mut spectra = Vec::new();
+    for (row_no, result) in reader.records().enumerate() {
+        let record = result.with_context(|| format!("CSV row {row_no}"))?;
+
+        let y = x_cols
+            .iter()
+            .map(|&(col_idx, _)| {
+                let tok = record.get(col_idx).unwrap_or("").trim();
+                if tok.is_empty() {
+                    return Ok(f64::NAN);
+                }
+                dialect.parse_f64(tok).with_context(|| {
+                    format!(
+                        "Row {row_no}, column '{}': '{tok}' is not a number",
+                        &headers[col_idx]
+                    )
+                })
+            })
+            .collect::<Result<Vec<f64>>>()?;
+
+        let metadata = meta_cols
+            .iter()
+            .map(|&col_idx| {
+                let value = record.get(col_idx).unwrap_or("");
+                (headers[col_idx].to_string(), guess_csv_type(value, dialect))
+            })
+            .collect();
+
+        spectra.push(Spectrum {
+            x: x.clone(),
+            y,
+            metadata,
+        });
+    }
+
+    Ok(spectra)
+}
+
+/// Parse a list cell: floats separated by the dialect's list delimiter,
+/// written with its decimal separator. Runs of spaces count as one
+/// separator when the list delimiter is a space.
+fn parse_float_list(s: &str, dialect: &CsvDialect, row: usize, col: &str) -> Result<Vec<f64>> {
+    let tokens: Vec<&str> = if dialect.list_delim == ' ' {
+        s.split_whitespace().collect()
+    } else {
+        s.split(dialect.list_delim).collect()
+    };
+    tokens
+        .into_iter()
         .enumerate()
         .map(|(j, tok)| {
-            tok.trim()
-                .parse::<f64>()
+            dialect
+                .parse_f64(tok.trim())
                 .with_context(|| format!("Row {row}, {col}[{j}]: '{tok}' is not a number"))
         })
         .collect()
 }
 
+/// [`guess_metadata_type`] reading floats with the dialect's decimal
+/// separator.
+fn guess_csv_type(s: &str, dialect: &CsvDialect) -> MetadataValue {
+    match guess_metadata_type(s) {
+        MetadataValue::String(_) if dialect.decimal != '.' => match dialect.parse_f64(s) {
+            Some(f) => MetadataValue::Float(f),
+            None => MetadataValue::String(s.to_string()),
+        },
+        value => value,
+    }
+}
+
 fn guess_metadata_type(s: &str) -> MetadataValue {
     if s.is_empty() {
         return MetadataValue::Null;
@@ -207,6 +651,706 @@ fn guess_metadata_type(s: &str) -> MetadataValue {
     MetadataValue::String(s.to_string())
 }
 
+// ---------------------------------------------------------------------------
+// JCAMP-DX loader
+// ---------------------------------------------------------------------------
+
+/// Load a JCAMP-DX file. Each `##TITLE= … ##END=` block holding data
+/// becomes one spectrum, with its other labelled records as metadata.
+///
+/// Supported data forms are `##XYDATA=(X++(Y..Y))` in AFFN or the
+/// compressed ASDF encodings (PAC, SQZ, DIF, DUP), with x reconstructed from
+/// `##FIRSTX`, `##LASTX` and the point count and y scaled by `##YFACTOR`,
+/// and `##XYPOINTS=(XY..XY)` scaled by `##XFACTOR`/`##YFACTOR`.
+fn load_jcamp(reader: impl BufRead) -> Result<Vec<Spectrum>> {
+    let mut spectra = Vec::new();
+    let mut labels: BTreeMap<String, String> = BTreeMap::new();
+    let mut block = JcampBlock::default();
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("reading JCAMP-DX line {}", line_no + 1))?;
+        // `$$` starts a comment.
+        let line = line.split("$$").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some(record) = line.strip_prefix("##") else {
+            block
+                .push_data_line(line)
+                .with_context(|| format!("JCAMP-DX line {}", line_no + 1))?;
+            continue;
+        };
+        let (label, value) = record.split_once('=').unwrap_or((record, ""));
+        let label = normalize_jcamp_label(label);
+        let value = value.trim();
+
+        match label.as_str() {
+            "TITLE" => {
+                labels.clear();
+                block = JcampBlock::default();
+                labels.insert(label, value.to_string());
+            }
+            "END" => {
+                if let Some(sp) = block.finish(&labels)? {
+                    spectra.push(sp);
+                }
+                block = JcampBlock::default();
+            }
+            "XYDATA" => {
+                let form: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+                block.form = match form.as_str() {
+                    "(X++(Y..Y))" => JcampForm::XyData,
+                    _ => bail!("Unsupported JCAMP-DX XYDATA form {value}"),
+                };
+            }
+            "XYPOINTS" => {
+                let form: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+                block.form = match form.as_str() {
+                    "(XY..XY)" => JcampForm::XyPoints,
+                    _ => bail!("Unsupported JCAMP-DX XYPOINTS form {value}"),
+                };
+            }
+            "PEAKTABLE" | "NTUPLES" | "DATATABLE" => {
+                bail!("Unsupported JCAMP-DX data record ##{label}")
+            }
+            _ => {
+                block.form = JcampForm::None;
+                labels.insert(label, value.to_string());
+            }
+        }
+    }
+
+    if spectra.is_empty() {
+        bail!("No spectra found in JCAMP-DX file");
+    }
+    Ok(spectra)
+}
+
+/// Label in canonical form: upper case without spaces, dashes, slashes or
+/// underscores (`##Data Type` → `DATATYPE`).
+fn normalize_jcamp_label(label: &str) -> String {
+    label
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '/' | '_'))
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Data record currently being read.
+#[derive(Default, PartialEq)]
+enum JcampForm {
+    #[default]
+    None,
+    XyData,
+    XyPoints,
+}
+
+/// Data collected for one JCAMP-DX block.
+#[derive(Default)]
+struct JcampBlock {
+    form: JcampForm,
+    /// Decoded ordinates (XYDATA) or raw x values (XYPOINTS).
+    y: Vec<f64>,
+    x: Vec<f64>,
+    /// Whether the previous XYDATA line ended in DIF form, in which case
+    /// the next line repeats its last ordinate as a check value.
+    last_dif: bool,
+}
+
+impl JcampBlock {
+    fn push_data_line(&mut self, line: &str) -> Result<()> {
+        match self.form {
+            JcampForm::None => Ok(()),
+            JcampForm::XyPoints => {
+                let numbers: Vec<f64> = line
+                    .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                    .filter(|t| !t.is_empty())
+                    .map(|t| {
+                        t.parse::<f64>()
+                            .with_context(|| format!("'{t}' is not a number"))
+                    })
+                    .collect::<Result<_>>()?;
+                for pair in numbers.chunks_exact(2) {
+                    self.x.push(pair[0]);
+                    self.y.push(pair[1]);
+                }
+                Ok(())
+            }
+            JcampForm::XyData => {
+                let (values, ends_dif) = decode_asdf_line(line)?;
+                // The first value is the abscissa of the line.
+                let mut ys = values.into_iter().skip(1);
+                if self.last_dif && !self.y.is_empty() {
+                    ys.next();
+                }
+                self.y.extend(ys);
+                self.last_dif = ends_dif;
+                Ok(())
+            }
+        }
+    }
+
+    /// Build the spectrum for this block, if it held any data.
+    fn finish(self, labels: &BTreeMap<String, String>) -> Result<Option<Spectrum>> {
+        if self.y.is_empty() {
+            return Ok(None);
+        }
+        let number = |label: &str| -> Option<f64> {
+            labels.get(label).and_then(|v| v.trim().parse::<f64>().ok())
+        };
+        let x_factor = number("XFACTOR").unwrap_or(1.0);
+        let y_factor = number("YFACTOR").unwrap_or(1.0);
+
+        let (x, y) = if self.x.is_empty() {
+            let (Some(first), Some(last)) = (number("FIRSTX"), number("LASTX")) else {
+                bail!("JCAMP-DX XYDATA block lacks ##FIRSTX/##LASTX");
+            };
+            let n = self.y.len();
+            if let Some(expected) = number("NPOINTS") {
+                if expected as usize != n {
+                    log::warn!("JCAMP-DX ##NPOINTS={expected} but {n} values were read");
+                }
+            }
+            let step = if n > 1 {
+                (last - first) / (n - 1) as f64
+            } else {
+                0.0
+            };
+            let x = (0..n).map(|i| first + step * i as f64).collect();
+            (x, self.y.iter().map(|v| v * y_factor).collect())
+        } else {
+            (
+                self.x.iter().map(|v| v * x_factor).collect(),
+                self.y.iter().map(|v| v * y_factor).collect(),
+            )
+        };
+
+        let metadata = labels
+            .iter()
+            .map(|(k, v)| (k.clone(), guess_metadata_type(v)))
+            .collect();
+        Ok(Some(Spectrum { x, y, metadata }))
+    }
+}
+
+/// Decode one ASDF data line (AFFN, PAC, SQZ, DIF and DUP forms) into its
+/// values, abscissa first. Also returns whether the line ended in DIF form.
+fn decode_asdf_line(line: &str) -> Result<(Vec<f64>, bool)> {
+    enum Token {
+        Abs(f64),
+        Dif(f64),
+        Dup(usize),
+    }
+
+    // Split into tokens; each starts at a sign, a pseudo-digit or after a
+    // separator.
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let flush = |current: &mut String, tokens: &mut Vec<Token>| -> Result<()> {
+        if current.is_empty() {
+            return Ok(());
+        }
+        let mut chars = current.chars();
+        let lead = chars.next().unwrap_or('0');
+        let rest: String = chars.collect();
+        let digits = |d: u32, negative: bool| -> Result<f64> {
+            let v: f64 = format!("{d}{rest}")
+                .parse()
+                .with_context(|| format!("invalid ASDF value '{current}'"))?;
+            Ok(if negative { -v } else { v })
+        };
+        let token = match lead {
+            '0'..='9' | '.' | '+' | '-' => Token::Abs(
+                current
+                    .parse()
+                    .with_context(|| format!("invalid number '{current}'"))?,
+            ),
+            '?' => Token::Abs(f64::NAN),
+            '@' => Token::Abs(digits(0, false)?),
+            'A'..='I' => Token::Abs(digits(lead as u32 - 'A' as u32 + 1, false)?),
+            'a'..='i' => Token::Abs(digits(lead as u32 - 'a' as u32 + 1, true)?),
+            '%' => Token::Dif(digits(0, false)?),
+            'J'..='R' => Token::Dif(digits(lead as u32 - 'J' as u32 + 1, false)?),
+            'j'..='r' => Token::Dif(digits(lead as u32 - 'j' as u32 + 1, true)?),
+            'S'..='Z' => Token::Dup(digits(lead as u32 - 'S' as u32 + 1, false)? as usize),
+            's' => Token::Dup(digits(9, false)? as usize),
+            other => bail!("unsupported JCAMP-DX character '{other}'"),
+        };
+        tokens.push(token);
+        current.clear();
+        Ok(())
+    };
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | ',' | ';' => flush(&mut current, &mut tokens)?,
+            '0'..='9' | '.' => current.push(c),
+            // Exponent inside a plain number, e.g. `1.5E+03`. AFFN exponents
+            // always carry a sign; without one, `E`/`e` is the SQZ digit
+            // +5/-5, as in `100E1234`.
+            'E' | 'e'
+                if chars.peek().is_some_and(|n| matches!(n, '+' | '-'))
+                    && current
+                        .chars()
+                        .next()
+                        .is_some_and(|l| l.is_ascii_digit() || "+-.".contains(l)) =>
+            {
+                current.push(c)
+            }
+            '+' | '-' if current.ends_with(['E', 'e']) => current.push(c),
+            _ => {
+                flush(&mut current, &mut tokens)?;
+                current.push(c);
+            }
+        }
+    }
+    flush(&mut current, &mut tokens)?;
+
+    let mut values: Vec<f64> = Vec::new();
+    let mut last_dif: Option<f64> = None;
+    for token in tokens {
+        match token {
+            Token::Abs(v) => {
+                values.push(v);
+                last_dif = None;
+            }
+            Token::Dif(d) => {
+                let prev = values
+                    .last()
+                    .copied()
+                    .context("DIF value without a preceding value")?;
+                values.push(prev + d);
+                last_dif = Some(d);
+            }
+            Token::Dup(n) => {
+                let prev = values
+                    .last()
+                    .copied()
+                    .context("DUP count without a preceding value")?;
+                for _ in 1..n {
+                    match last_dif {
+                        Some(d) => {
+                            let p = values.last().copied().unwrap_or(prev);
+                            values.push(p + d);
+                        }
+                        None => values.push(prev),
+                    }
+                }
+            }
+        }
+    }
+    Ok((values, last_dif.is_some()))
+}
+
+// ---------------------------------------------------------------------------
+// Excel loader
+// ---------------------------------------------------------------------------
+
+/// Load the first sheet of an Excel workbook in wide layout: the first
+/// column holds the x values shared by all spectra and every other column
+/// is one spectrum, its header stored in a `sample` metadata column.
+///
+/// The first row is the header. Rows without an x value are skipped and
+/// empty intensity cells become NaN. A column with any other non-numeric
+/// cell is left out; the error naming its column and row is returned with
+/// the spectra. It is an error if every column fails.
+pub fn load_xlsx(path: &Path) -> Result<(Vec<Spectrum>, Vec<String>)> {
+    let bytes = std::fs::read(path).context("reading Excel file")?;
+    parse_xlsx(&bytes)
+}
+
+fn parse_xlsx(bytes: &[u8]) -> Result<(Vec<Spectrum>, Vec<String>)> {
+    let mut workbook = open_workbook_auto_from_rs(std::io::Cursor::new(bytes))
+        .context("reading Excel workbook")?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .context("Excel workbook has no sheets")?
+        .context("reading Excel sheet")?;
+    // Cells are looked up by absolute position, so a sheet whose used range
+    // starts below or right of A1 keeps its rows and columns.
+    let Some((last_row, last_col)) = sheet.end() else {
+        bail!("Excel sheet is empty");
+    };
+    let cell = |row: u32, col: u32| sheet.get_value((row, col)).unwrap_or(&Data::Empty);
+
+    // Sheet row numbers (1-based, as Excel shows them) of the data rows.
+    let mut x = Vec::new();
+    let mut data_rows = Vec::new();
+    for row in 1..=last_row {
+        let row_no = row + 1;
+        match cell(row, 0) {
+            Data::Float(v) => x.push(*v),
+            Data::Int(v) => x.push(*v as f64),
+            Data::Empty => continue,
+            other => bail!("Excel row {row_no}: x value '{other}' is not a number"),
+        }
+        data_rows.push((row_no, row));
+    }
+
+    let mut spectra = Vec::new();
+    let mut errors = Vec::new();
+    'columns: for col in 1..=last_col {
+        let name = match cell(0, col) {
+            Data::Empty => format!("column {}", col + 1),
+            other => other.to_string(),
+        };
+        let mut y = Vec::with_capacity(x.len());
+        for &(row_no, row) in &data_rows {
+            y.push(match cell(row, col) {
+                Data::Float(v) => *v,
+                Data::Int(v) => *v as f64,
+                Data::Empty => f64::NAN,
+                other => {
+                    let error = format!(
+                        "Excel column '{name}' skipped: row {row_no}: '{other}' is not a number"
+                    );
+                    log::warn!("{error}");
+                    errors.push(error);
+                    continue 'columns;
+                }
+            });
+        }
+        // Columns with only a header (or nothing) hold no spectrum.
+        if y.iter().all(|v| v.is_nan()) {
+            continue;
+        }
+        let metadata = BTreeMap::from([("sample".to_string(), MetadataValue::String(name))]);
+        spectra.push(Spectrum {
+            x: x.clone(),
+            y,
+            metadata,
+        });
+    }
+    if spectra.is_empty() && !errors.is_empty() {
+        bail!("{}", errors.join("; "));
+    }
+    Ok((spectra, errors))
+}
+
+// ---------------------------------------------------------------------------
+// MATLAB loader
+// ---------------------------------------------------------------------------
+
+/// Signature of an HDF5 file, found at its start or, in MATLAB v7.3 files,
+/// after their 512-byte text header.
+const HDF5_SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+
+/// Load a MATLAB `.mat` (v5 / v7) file holding numeric variables `x` and
+/// `y`.
+///
+/// `y` holds one spectrum per column, or per row when `x` is a vector
+/// matching its number of columns instead. `x` is either that vector,
+/// shared by all spectra, or a matrix shaped like `y` with one spectrum's
+/// x values per column. Every other numeric vector with one value per
+/// spectrum becomes a metadata column; other variables are ignored.
+///
+/// Only numeric arrays can be read, so the fields of a struct must be saved
+/// as separate variables (`save(file, '-struct', 's')`). v7.3 files are
+/// HDF5-based and rejected with an error.
+pub fn load_mat(path: &Path) -> Result<Vec<Spectrum>> {
+    let bytes = std::fs::read(path).context("reading MATLAB file")?;
+    parse_mat(&bytes)
+}
+
+fn parse_mat(bytes: &[u8]) -> Result<Vec<Spectrum>> {
+    if bytes.get(512..520) == Some(HDF5_SIGNATURE) {
+        bail!("MATLAB v7.3 files are not supported; save the data with save(..., '-v7')");
+    }
+    let file = matfile::MatFile::parse(bytes).context("parsing MATLAB file")?;
+    let variable = |name: &str| -> Result<(&[usize], Vec<f64>)> {
+        let Some(array) = file.find_by_name(name) else {
+            bail!(
+                "MATLAB file has no numeric variable '{name}' \
+                 (struct fields must be saved with save(..., '-struct', ...))"
+            );
+        };
+        let Some(values) = mat_values(array) else {
+            bail!("MATLAB variable '{name}' is complex");
+        };
+        Ok((array.size(), values))
+    };
+    let (y_size, y) = variable("y")?;
+    let (x_size, x) = variable("x")?;
+    let &[rows, cols] = y_size else {
+        bail!(
+            "MATLAB variable 'y' must be a matrix, not {}-dimensional",
+            y_size.len()
+        );
+    };
+
+    let shared_x = is_vector(x_size);
+    let by_column = if shared_x {
+        match x.len() {
+            n if n == rows => true,
+            n if n == cols => false,
+            n => bail!("MATLAB 'x' has {n} points but 'y' is {rows}×{cols}"),
+        }
+    } else if x_size == y_size {
+        true
+    } else {
+        bail!("MATLAB 'x' must be a vector or a matrix shaped like 'y'");
+    };
+    let n_spectra = if by_column { cols } else { rows };
+    // Column-major: element (i, j) is at i + j * rows.
+    let series = |values: &[f64], k: usize| -> Vec<f64> {
+        if by_column {
+            values[k * rows..(k + 1) * rows].to_vec()
+        } else {
+            (0..cols).map(|j| values[k + j * rows]).collect()
+        }
+    };
+
+    let mut metadata_columns = Vec::new();
+    for array in file.arrays() {
+        if matches!(array.name(), "x" | "y") {
+            continue;
+        }
+        match mat_metadata(array) {
+            Some(values) if is_vector(array.size()) && values.len() == n_spectra => {
+                metadata_columns.push((array.name(), values));
+            }
+            _ => log::warn!(
+                "MATLAB variable '{}' is not one real value per spectrum; ignored",
+                array.name()
+            ),
+        }
+    }
+
+    let spectra = (0..n_spectra)
+        .map(|k| Spectrum {
+            x: if shared_x { x.clone() } else { series(&x, k) },
+            y: series(&y, k),
+            metadata: metadata_columns
+                .iter()
+                .map(|(name, values)| (name.to_string(), values[k].clone()))
+                .collect(),
+        })
+        .collect();
+    Ok(spectra)
+}
+
+/// Whether an array of size `size` has at most one dimension longer than 1.
+fn is_vector(size: &[usize]) -> bool {
+    size.iter().filter(|&&d| d != 1).count() <= 1
+}
+
+/// Values of a real MATLAB array as floats, in column-major order; `None`
+/// if the array is complex.
+fn mat_values(array: &matfile::Array) -> Option<Vec<f64>> {
+    use matfile::NumericData::*;
+    fn real<T: Copy>(real: &[T], imag: &Option<Vec<T>>, f: fn(T) -> f64) -> Option<Vec<f64>> {
+        imag.is_none().then(|| real.iter().map(|&v| f(v)).collect())
+    }
+    match array.data() {
+        Int8 { real: r, imag: i } => real(r, i, f64::from),
+        UInt8 { real: r, imag: i } => real(r, i, f64::from),
+        Int16 { real: r, imag: i } => real(r, i, f64::from),
+        UInt16 { real: r, imag: i } => real(r, i, f64::from),
+        Int32 { real: r, imag: i } => real(r, i, f64::from),
+        UInt32 { real: r, imag: i } => real(r, i, f64::from),
+        Int64 { real: r, imag: i } => real(r, i, |v| v as f64),
+        UInt64 { real: r, imag: i } => real(r, i, |v| v as f64),
+        Single { real: r, imag: i } => real(r, i, f64::from),
+        Double { real: r, imag: i } => real(r, i, |v| v),
+    }
+}
+
+/// Values of a real MATLAB array as metadata: integers for integer classes,
+/// floats otherwise.
+fn mat_metadata(array: &matfile::Array) -> Option<Vec<MetadataValue>> {
+    use matfile::NumericData::*;
+    let values = mat_values(array)?;
+    let integer = !matches!(array.data(), Single { .. } | Double { .. });
+    Some(
+        values
+            .into_iter()
+            .map(|v| {
+                if integer {
+                    MetadataValue::Integer(v as i64)
+                } else {
+                    MetadataValue::Float(v)
+                }
+            })
+            .collect(),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// HDF5 loader
+// ---------------------------------------------------------------------------
+
+/// Longest fixed-length string attribute read in full; longer ones are
+/// truncated.
+#[cfg(feature = "hdf5")]
+const MAX_ATTRIBUTE_LEN: usize = 1024;
+
+/// Load an HDF5 file holding datasets `x` and `y`.
+///
+/// `y` is 2-D with one spectrum per row (`[n_spectra, n_points]`), or 1-D
+/// for a single spectrum. `x` is either 1-D with `n_points` values shared by
+/// all spectra or shaped like `y`. The datasets are read from the root group
+/// and from every top-level group holding them; spectra from a group get a
+/// `group` column with its name.
+///
+/// Attributes of the file, of the spectra's group and of `y` become
+/// metadata, the innermost winning: scalars apply to every spectrum, 1-D
+/// arrays with one value per spectrum to each in turn. Other attributes are
+/// skipped with a warning.
+#[cfg(feature = "hdf5")]
+pub fn load_hdf5(path: &Path) -> Result<Vec<Spectrum>> {
+    let file = hdf5::File::open(path).context("opening HDF5 file")?;
+    let mut groups = Vec::new();
+    if file.link_exists("y") {
+        groups.push((None, (*file).clone()));
+    }
+    for group in file.groups()? {
+        if group.link_exists("y") {
+            let name = group.name().trim_start_matches('/').to_string();
+            groups.push((Some(name), group));
+        }
+    }
+    if groups.is_empty() {
+        bail!("HDF5 file has no 'y' dataset in its root or top-level groups");
+    }
+
+    let root_attributes = hdf5_attributes(&file)?;
+    let mut spectra = Vec::new();
+    for (name, group) in groups {
+        let mut attributes = root_attributes.clone();
+        if let Some(name) = name {
+            attributes.push(("group".to_string(), vec![MetadataValue::String(name)]));
+            attributes.extend(hdf5_attributes(&group)?);
+        }
+        spectra.extend(
+            hdf5_group_spectra(&group, attributes)
+                .with_context(|| format!("reading HDF5 group '{}'", group.name()))?,
+        );
+    }
+    Ok(spectra)
+}
+
+#[cfg(not(feature = "hdf5"))]
+pub fn load_hdf5(_path: &Path) -> Result<Vec<Spectrum>> {
+    bail!("HDF5 support is not enabled in this build; rebuild with `--features hdf5`")
+}
+
+/// Spectra of the `x` and `y` datasets in `group`, with the metadata
+/// `attributes` named in [`load_hdf5`] plus those of `y`.
+#[cfg(feature = "hdf5")]
+fn hdf5_group_spectra(
+    group: &hdf5::Group,
+    mut attributes: Vec<(String, Vec<MetadataValue>)>,
+) -> Result<Vec<Spectrum>> {
+    let y_dataset = group.dataset("y")?;
+    let x_dataset = group.dataset("x").context("no 'x' dataset beside 'y'")?;
+    let (n_spectra, n_points) = match y_dataset.shape()[..] {
+        [n] => (1, n),
+        [rows, cols] => (rows, cols),
+        ref shape => bail!("'y' must be 1-D or 2-D, not {}-D", shape.len()),
+    };
+    // Both datasets are read in row-major order.
+    let y: Vec<f64> = y_dataset.read_raw()?;
+    let x: Vec<f64> = x_dataset.read_raw()?;
+    let shared_x = x_dataset.ndim() == 1;
+    if shared_x && x.len() != n_points {
+        bail!(
+            "'x' has {} points but the spectra in 'y' have {n_points}",
+            x.len()
+        );
+    }
+    if !shared_x && x_dataset.shape() != y_dataset.shape() {
+        bail!("'x' must be 1-D or shaped like 'y'");
+    }
+
+    attributes.extend(hdf5_attributes(&y_dataset)?);
+    attributes.retain(|(name, values)| {
+        let fits = values.len() == 1 || values.len() == n_spectra;
+        if !fits {
+            log::warn!("HDF5 attribute '{name}' is not one value per spectrum; skipped");
+        }
+        fits
+    });
+
+    let spectra = (0..n_spectra)
+        .map(|k| {
+            let row = k * n_points..(k + 1) * n_points;
+            Spectrum {
+                x: if shared_x {
+                    x.clone()
+                } else {
+                    x[row.clone()].to_vec()
+                },
+                y: y[row].to_vec(),
+                metadata: attributes
+                    .iter()
+                    .map(|(name, values)| (name.clone(), values[k.min(values.len() - 1)].clone()))
+                    .collect(),
+            }
+        })
+        .collect();
+    Ok(spectra)
+}
+
+/// The scalar and 1-D attributes of `location` as metadata values, in
+/// order. Attributes of other shapes or types are skipped with a warning.
+#[cfg(feature = "hdf5")]
+fn hdf5_attributes(location: &hdf5::Location) -> Result<Vec<(String, Vec<MetadataValue>)>> {
+    use hdf5::types::{FixedAscii, FixedUnicode, TypeDescriptor as TD, VarLenAscii, VarLenUnicode};
+    fn strings<'a>(values: impl Iterator<Item = &'a str>) -> Vec<MetadataValue> {
+        values
+            .map(|s| MetadataValue::String(s.to_string()))
+            .collect()
+    }
+
+    let mut attributes = Vec::new();
+    for name in location.attr_names()? {
+        let attr = location.attr(&name)?;
+        if attr.ndim() > 1 {
+            log::warn!("HDF5 attribute '{name}' has more than one dimension; skipped");
+            continue;
+        }
+        let values = match attr.dtype()?.to_descriptor()? {
+            TD::Integer(_) | TD::Unsigned(_) => attr
+                .read_raw::<i64>()?
+                .into_iter()
+                .map(MetadataValue::Integer)
+                .collect(),
+            TD::Float(_) => attr
+                .read_raw::<f64>()?
+                .into_iter()
+                .map(MetadataValue::Float)
+                .collect(),
+            TD::Boolean => attr
+                .read_raw::<bool>()?
+                .into_iter()
+                .map(MetadataValue::Bool)
+                .collect(),
+            TD::VarLenUnicode => {
+                strings(attr.read_raw::<VarLenUnicode>()?.iter().map(|s| s.as_str()))
+            }
+            TD::VarLenAscii => strings(attr.read_raw::<VarLenAscii>()?.iter().map(|s| s.as_str())),
+            TD::FixedAscii(_) => strings(
+                attr.read_raw::<FixedAscii<MAX_ATTRIBUTE_LEN>>()?
+                    .iter()
+                    .map(|s| s.as_str()),
+            ),
+            TD::FixedUnicode(_) => strings(
+                attr.read_raw::<FixedUnicode<MAX_ATTRIBUTE_LEN>>()?
+                    .iter()
+                    .map(|s| s.as_str()),
+            ),
+            _ => {
+                log::warn!("HDF5 attribute '{name}' has an unsupported type; skipped");
+                continue;
+            }
+        };
+        attributes.push((name, values));
+    }
+    Ok(attributes)
+}
+
 // ---------------------------------------------------------------------------
 // Parquet loader
 // ---------------------------------------------------------------------------
@@ -220,15 +1364,38 @@ fn guess_metadata_type(s: &str) -> MetadataValue {
 ///
 /// Works with files written by both **Pandas** (`df.to_parquet()`) and
 /// **Polars** (`df.write_parquet()`).
-fn load_parquet(path: &Path) -> Result<SpectralDataset> {
-    let file = std::fs::File::open(path).context("opening parquet file")?;
-    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+///
+/// Rows with a null x or y list, or with x and y of different lengths, are
+/// skipped with a warning; their number is returned with the spectra.
+///
+/// `progress` receives the fraction of the file's rows read after each
+/// record batch, using the row count from the footer metadata.
+fn load_parquet<R: ChunkReader + 'static>(
+    source: R,
+    progress: &mut dyn FnMut(f64),
+) -> Result<(Vec<Spectrum>, usize)> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(source)
         .context("reading parquet metadata")?;
+    let total_rows = builder.metadata().file_metadata().num_rows().max(1) as f64;
     let reader = builder.build().context("building parquet reader")?;
+    spectra_from_batches(reader, total_rows, progress)
+}
 
+/// Spectra of wide-layout record batches, with the number of rows skipped
+/// for a null x or y or x and y of different lengths. Batches may differ in
+/// list type and float width. `progress` receives the fraction of
+/// `total_rows` read.
+fn spectra_from_batches(
+    batches: impl Iterator<Item = std::result::Result<RecordBatch, ArrowError>>,
+    total_rows: f64,
+    progress: &mut dyn FnMut(f64),
+) -> Result<(Vec<Spectrum>, usize)> {
     let mut spectra = Vec::new();
+    let mut rows_read = 0;
+    // x/y types seen in the first batch, to detect changes across batches.
+    let mut list_types: Option<(DataType, DataType)> = None;
 
-    for batch_result in reader {
+    for batch_result in batches {
         let batch = batch_result.context("reading parquet record batch")?;
         let schema = batch.schema();
         let n_rows = batch.num_rows();
@@ -244,45 +1411,175 @@ fn load_parquet(path: &Path) -> Result<SpectralDataset> {
         let x_col = batch.column(x_idx);
         let y_col = batch.column(y_idx);
 
-        // Collect metadata column indices (everything except x, y)
-        let meta_cols: Vec<(usize, String)> = schema
-            .fields()
-            .iter()
-            .enumerate()
-            .filter(|(i, _)| *i != x_idx && *i != y_idx)
-            .map(|(i, f)| (i, f.name().clone()))
-            .collect();
+        // Validate the list types up front; a later batch may use a
+        // different (but still supported) list/float width than the first.
+        check_f64_list_type(x_col.data_type()).context("column 'x'")?;
+        check_f64_list_type(y_col.data_type()).context("column 'y'")?;
+        let types = (x_col.data_type().clone(), y_col.data_type().clone());
+        match &list_types {
+            None => list_types = Some(types),
+            Some(first) if *first != types => log::warn!(
+                "x/y column types differ between record batches ({:?}, {:?}) vs ({:?}, {:?}); reading both",
+                first.0, first.1, types.0, types.1
+            ),
+            Some(_) => {}
+        }
 
-        for row in 0..n_rows {
-            let x = extract_f64_list(x_col, row)
-                .with_context(|| format!("Row {row}: failed to read 'x'"))?;
-            let y = extract_f64_list(y_col, row)
-                .with_context(|| format!("Row {row}: failed to read 'y'"))?;
+        // Metadata columns: everything except x, y, with structs flattened
+        let meta_cols = metadata_columns(&batch, &[x_idx, y_idx]);
 
-            if x.len() != y.len() {
-                bail!("Row {row}: x has {} values but y has {}", x.len(), y.len());
-            }
+        // Rows are independent, so extract them in parallel; collecting an
+        // indexed parallel iterator keeps row order.
+        let batch_spectra = (0..n_rows)
+            .into_par_iter()
+            .map(|row| {
+                let row_index = rows_read + row;
+                let x = extract_f64_list(x_col, row)
+                    .with_context(|| format!("Row {row_index}: failed to read 'x'"))?;
+                let y = extract_f64_list(y_col, row)
+                    .with_context(|| format!("Row {row_index}: failed to read 'y'"))?;
+                let (x, y) = match (x, y) {
+                    (Some(x), Some(y)) if x.len() == y.len() => (x, y),
+                    (Some(x), Some(y)) => {
+                        log::warn!(
+                            "Row {row_index}: x has {} values but y has {}; skipped",
+                            x.len(),
+                            y.len()
+                        );
+                        return Ok(None);
+                    }
+                    _ => {
+                        log::warn!("Row {row_index}: null x or y; skipped");
+                        return Ok(None);
+                    }
+                };
 
-            let mut metadata = BTreeMap::new();
-            for (col_idx, col_name) in &meta_cols {
-                let col_array = batch.column(*col_idx);
-                let value = extract_metadata_value(col_array, row);
-                metadata.insert(col_name.clone(), value);
-            }
+                let metadata = meta_cols
+                    .iter()
+                    .map(|col| (col.name.clone(), col.value(row)))
+                    .collect();
+
+                Ok(Some(Spectrum { x, y, metadata }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        spectra.extend(batch_spectra.into_iter().flatten());
+        rows_read += n_rows;
+        progress((rows_read as f64 / total_rows).min(1.0));
+    }
+
+    let skipped = rows_read - spectra.len();
+    Ok((spectra, skipped))
+}
+
+/// Points of one spectrum in a long table, with its first row's metadata.
+type PointGroup = (Vec<(f64, f64)>, BTreeMap<String, MetadataValue>);
 
-            spectra.push(Spectrum { x, y, metadata });
+/// Load a Parquet file in "long" layout: one row per point, with the
+/// spectrum given by `id_col`, the abscissa by `x_col` and the intensity by
+/// `y_col`. Rows are grouped by id (in order of first appearance) and each
+/// spectrum's points are sorted by x.
+///
+/// All other columns are metadata, taken from the first row of each group;
+/// a warning is logged when they vary within a group.
+pub fn load_long_parquet(
+    path: &Path,
+    id_col: &str,
+    x_col: &str,
+    y_col: &str,
+) -> Result<Vec<Spectrum>> {
+    let file = std::fs::File::open(path).context("opening parquet file")?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+        .context("reading parquet metadata")?
+        .build()
+        .context("building parquet reader")?;
+
+    // id → position in `groups`, to keep first-appearance order.
+    let mut index: BTreeMap<MetadataValue, usize> = BTreeMap::new();
+    let mut groups: Vec<PointGroup> = Vec::new();
+    let mut varying: BTreeSet<MetadataValue> = BTreeSet::new();
+
+    for batch_result in reader {
+        let batch = batch_result.context("reading parquet record batch")?;
+        let schema = batch.schema();
+        let column = |name: &str| {
+            schema
+                .index_of(name)
+                .map_err(|_| anyhow::anyhow!("Parquet file missing '{name}' column"))
+        };
+        let (id_idx, x_idx, y_idx) = (column(id_col)?, column(x_col)?, column(y_col)?);
+        let meta_cols = metadata_columns(&batch, &[x_idx, y_idx]);
+
+        for row in 0..batch.num_rows() {
+            let id = extract_metadata_value(batch.column(id_idx), row);
+            let number = |idx: usize, name: &str| {
+                extract_metadata_value(batch.column(idx), row)
+                    .as_f64()
+                    .with_context(|| format!("Row {row}: '{name}' is not numeric"))
+            };
+            let point = (number(x_idx, x_col)?, number(y_idx, y_col)?);
+
+            let metadata: BTreeMap<String, MetadataValue> = meta_cols
+                .iter()
+                .map(|col| (col.name.clone(), col.value(row)))
+                .collect();
+
+            match index.get(&id) {
+                Some(&g) => {
+                    let (points, first_meta) = &mut groups[g];
+                    points.push(point);
+                    if *first_meta != metadata {
+                        varying.insert(id);
+                    }
+                }
+                None => {
+                    index.insert(id, groups.len());
+                    groups.push((vec![point], metadata));
+                }
+            }
         }
     }
 
-    Ok(SpectralDataset::from_spectra(spectra))
+    if !varying.is_empty() {
+        log::warn!(
+            "Metadata varies within {} spectrum group(s) (e.g. {id_col} = {}); using each group's first row",
+            varying.len(),
+            varying
+                .iter()
+                .next()
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        );
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(mut points, metadata)| {
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let (x, y) = points.into_iter().unzip();
+            Spectrum { x, y, metadata }
+        })
+        .collect())
 }
 
 // -- Parquet / Arrow helpers --
 
-/// Extract a `Vec<f64>` from a List or LargeList column at the given row.
-fn extract_f64_list(col: &Arc<dyn Array>, row: usize) -> Result<Vec<f64>> {
+/// Check that a column is a List or LargeList of Float64 or Float32.
+pub(super) fn check_f64_list_type(data_type: &DataType) -> Result<()> {
+    let inner = match data_type {
+        DataType::List(field) | DataType::LargeList(field) => field.data_type(),
+        other => bail!("Expected List or LargeList column, got {other:?}"),
+    };
+    match inner {
+        DataType::Float64 | DataType::Float32 => Ok(()),
+        other => bail!("List inner type is {other:?}, expected Float64 or Float32"),
+    }
+}
+
+/// Extract a `Vec<f64>` from a List or LargeList column at the given row;
+/// `None` if the cell is null.
+pub(super) fn extract_f64_list(col: &Arc<dyn Array>, row: usize) -> Result<Option<Vec<f64>>> {
     if col.is_null(row) {
-        bail!("null value in list column");
+        return Ok(None);
     }
 
     let values_array = match col.data_type() {
@@ -305,9 +1602,11 @@ fn extract_f64_list(col: &Arc<dyn Array>, row: usize) -> Result<Vec<f64>> {
 
     // The inner array can be Float64 or Float32
     if let Some(f64_arr) = values_array.as_any().downcast_ref::<Float64Array>() {
-        Ok(f64_arr.iter().map(|v| v.unwrap_or(f64::NAN)).collect())
+        Ok(Some(f64_arr.iter().map(|v| v.unwrap_or(f64::NAN)).collect()))
     } else if let Some(f32_arr) = values_array.as_any().downcast_ref::<Float32Array>() {
-        Ok(f32_arr.iter().map(|v| v.unwrap_or(f32::NAN) as f64).collect())
+        Ok(Some(
+            f32_arr.iter().map(|v| v.unwrap_or(f32::NAN) as f64).collect(),
+        ))
     } else {
         bail!(
             "List inner type is {:?}, expected Float64 or Float32",
@@ -316,7 +1615,59 @@ fn extract_f64_list(col: &Arc<dyn Array>, row: usize) -> Result<Vec<f64>> {
     }
 }
 
+/// One metadata column of a record batch: a top-level column, or a field
+/// of a struct column named `parent.child`.
+pub(super) struct MetaColumn {
+    pub(super) name: String,
+    array: ArrayRef,
+    /// The struct column holding `array`, whose nulls also null the field.
+    parent: Option<ArrayRef>,
+}
+
+impl MetaColumn {
+    /// The column's value at `row`.
+    pub(super) fn value(&self, row: usize) -> MetadataValue {
+        if self.parent.as_ref().is_some_and(|p| p.is_null(row)) {
+            return MetadataValue::Null;
+        }
+        extract_metadata_value(&self.array, row)
+    }
+}
+
+/// Metadata columns of `batch`, skipping the columns at `exclude`. Struct
+/// columns are flattened one level into `parent.child` columns; deeper
+/// structs are kept whole and read as text.
+pub(super) fn metadata_columns(batch: &RecordBatch, exclude: &[usize]) -> Vec<MetaColumn> {
+    let schema = batch.schema();
+    let mut columns = Vec::new();
+    for (i, field) in schema.fields().iter().enumerate() {
+        if exclude.contains(&i) {
+            continue;
+        }
+        let array = batch.column(i);
+        match array.as_struct_opt() {
+            Some(parent) => {
+                for (child, child_array) in parent.fields().iter().zip(parent.columns()) {
+                    columns.push(MetaColumn {
+                        name: format!("{}.{}", field.name(), child.name()),
+                        array: child_array.clone(),
+                        parent: Some(array.clone()),
+                    });
+                }
+            }
+            None => columns.push(MetaColumn {
+                name: field.name().clone(),
+                array: array.clone(),
+                parent: None,
+            }),
+        }
+    }
+    columns
+}
+
 /// Extract a single metadata value from an Arrow column at a given row.
+/// Types without a direct counterpart (lists, nested structs, …) are read
+/// as their display text.
 fn extract_metadata_value(col: &Arc<dyn Array>, row: usize) -> MetadataValue {
     if col.is_null(row) {
         return MetadataValue::Null;
@@ -351,6 +1702,280 @@ fn extract_metadata_value(col: &Arc<dyn Array>, row: usize) -> MetadataValue {
             let arr = col.as_any().downcast_ref::<BooleanArray>().unwrap();
             MetadataValue::Bool(arr.value(row))
         }
-        _ => MetadataValue::String(format!("{:?}", col.data_type())),
+        DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, _) => {
+            temporal_to_iso(col, row).map_or(MetadataValue::Null, MetadataValue::Date)
+        }
+        _ => match array_value_to_string(col, row) {
+            Ok(text) => MetadataValue::String(text),
+            Err(_) => MetadataValue::String(format!("{:?}", col.data_type())),
+        },
+    }
+}
+
+/// ISO-8601 text of a date or timestamp cell. Timestamps are given in UTC
+/// whatever their time zone, so that they sort chronologically.
+fn temporal_to_iso(col: &Arc<dyn Array>, row: usize) -> Option<String> {
+    let datetime = match col.data_type() {
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            as_datetime::<TimestampSecondType>(col.as_primitive::<TimestampSecondType>().value(row))
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => as_datetime::<TimestampMillisecondType>(
+            col.as_primitive::<TimestampMillisecondType>().value(row),
+        ),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => as_datetime::<TimestampMicrosecondType>(
+            col.as_primitive::<TimestampMicrosecondType>().value(row),
+        ),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => as_datetime::<TimestampNanosecondType>(
+            col.as_primitive::<TimestampNanosecondType>().value(row),
+        ),
+        // Dates display as `YYYY-MM-DD`.
+        _ => return array_value_to_string(col, row).ok(),
+    };
+    datetime.map(|dt| dt.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+}
+
+// ---------------------------------------------------------------------------
+// Parquet writer
+// ---------------------------------------------------------------------------
+
+/// Write the spectra at `indices` to a Parquet file with the same layout
+/// [`load_parquet`] reads: `x`/`y` as `List<Float64>` plus one column per
+/// metadata column.
+///
+/// Each metadata column gets the Arrow type of its values (Int64, Float64,
+/// Boolean or Utf8); integer/float mixes become Float64 and any other mix
+/// falls back to Utf8 using the values' `Display` text.
+pub fn save_parquet(
+    path: &Path,
+    dataset: &SpectralDataset,
+    indices: &[usize],
+    region: Option<(f64, f64)>,
+) -> Result<()> {
+    let spectra: Vec<Spectrum> = dataset
+        .spectra_at(indices)
+        .iter()
+        .map(|sp| match region {
+            Some((lo, hi)) => trim_region(sp, lo, hi),
+            None => Spectrum::clone(sp),
+        })
+        .collect();
+
+    let mut fields = vec![
+        Field::new(
+            "x",
+            DataType::List(Arc::new(Field::new_list_field(DataType::Float64, true))),
+            false,
+        ),
+        Field::new(
+            "y",
+            DataType::List(Arc::new(Field::new_list_field(DataType::Float64, true))),
+            false,
+        ),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        f64_list_array(spectra.iter().map(|sp| &sp.x)),
+        f64_list_array(spectra.iter().map(|sp| &sp.y)),
+    ];
+    for col in &dataset.column_names {
+        let values: Vec<&MetadataValue> = spectra
+            .iter()
+            .map(|sp| sp.metadata.get(col).unwrap_or(&MetadataValue::Null))
+            .collect();
+        let array = metadata_array(&values);
+        fields.push(Field::new(col, array.data_type().clone(), true));
+        columns.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns).context("building record batch")?;
+
+    let file =
+        std::fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut writer = ArrowWriter::try_new(file, schema, None).context("creating parquet writer")?;
+    writer.write(&batch).context("writing parquet data")?;
+    writer.close().context("finishing parquet file")?;
+    Ok(())
+}
+
+/// Build a `List<Float64>` array from one vector per row.
+fn f64_list_array<'a>(rows: impl Iterator<Item = &'a Vec<f64>>) -> ArrayRef {
+    let mut builder = ListBuilder::new(Float64Builder::new());
+    for row in rows {
+        builder.values().append_slice(row);
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+/// Build an Arrow array for one metadata column, inferring its type from
+/// the non-null values.
+fn metadata_array(values: &[&MetadataValue]) -> ArrayRef {
+    let non_null = || values.iter().filter(|v| !matches!(v, MetadataValue::Null));
+    let all = |f: fn(&MetadataValue) -> bool| non_null().all(|v| f(v));
+    let any_value = non_null().next().is_some();
+
+    if any_value && all(|v| matches!(v, MetadataValue::Integer(_))) {
+        let arr: Int64Array = values
+            .iter()
+            .map(|v| match v {
+                MetadataValue::Integer(i) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        Arc::new(arr)
+    } else if any_value && all(|v| matches!(v, MetadataValue::Integer(_) | MetadataValue::Float(_)))
+    {
+        let arr: Float64Array = values.iter().map(|v| v.as_f64()).collect();
+        Arc::new(arr)
+    } else if any_value && all(|v| matches!(v, MetadataValue::Bool(_))) {
+        let arr: BooleanArray = values
+            .iter()
+            .map(|v| match v {
+                MetadataValue::Bool(b) => Some(*b),
+                _ => None,
+            })
+            .collect();
+        Arc::new(arr)
+    } else {
+        let arr: StringArray = values
+            .iter()
+            .map(|v| match v {
+                MetadataValue::Null => None,
+                MetadataValue::String(s) | MetadataValue::Date(s) => Some(s.clone()),
+                other => Some(other.to_string()),
+            })
+            .collect();
+        Arc::new(arr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Imports live in the tests: the bench compiles this module under
+    // `cfg(test)` without its `#[test]` functions.
+
+    /// Batches of a reader may switch list type and float width; every row
+    /// is read, in order, with its values widened to f64.
+    #[test]
+    fn reads_batches_of_different_list_types() {
+        use super::*;
+        use arrow::array::{Float32Builder, LargeListBuilder};
+
+        let mut x = ListBuilder::new(Float64Builder::new());
+        let mut y = ListBuilder::new(Float64Builder::new());
+        for i in 0..2 {
+            x.append_value([Some(1.0), Some(2.0)]);
+            y.append_value([Some(i as f64), Some(0.5)]);
+        }
+        let first = RecordBatch::try_from_iter([
+            ("x", Arc::new(x.finish()) as ArrayRef),
+            ("y", Arc::new(y.finish()) as ArrayRef),
+            ("id", Arc::new(Int64Array::from(vec![0, 1])) as ArrayRef),
+        ])
+        .unwrap();
+
+        let mut x = LargeListBuilder::new(Float32Builder::new());
+        let mut y = LargeListBuilder::new(Float32Builder::new());
+        x.append_value([Some(3.0), Some(4.0), Some(5.0)]);
+        y.append_value([Some(0.25), Some(0.75), Some(1.5)]);
+        let second = RecordBatch::try_from_iter([
+            ("x", Arc::new(x.finish()) as ArrayRef),
+            ("y", Arc::new(y.finish()) as ArrayRef),
+            ("id", Arc::new(Int64Array::from(vec![2])) as ArrayRef),
+        ])
+        .unwrap();
+
+        let (spectra, skipped) =
+            spectra_from_batches([Ok(first), Ok(second)].into_iter(), 3.0, &mut |_| {}).unwrap();
+        assert_eq!(skipped, 0);
+        let ids: Vec<_> = spectra.iter().map(|sp| sp.metadata["id"].clone()).collect();
+        assert_eq!(ids, (0..3).map(MetadataValue::Integer).collect::<Vec<_>>());
+        assert_eq!(spectra[1].x, [1.0, 2.0]);
+        assert_eq!(spectra[1].y, [1.0, 0.5]);
+        assert_eq!(spectra[2].x, [3.0, 4.0, 5.0]);
+        assert_eq!(spectra[2].y, [0.25, 0.75, 1.5]);
+    }
+
+    /// A row with a null x list is skipped and counted; the rows around it
+    /// still load, in order.
+    #[test]
+    fn skips_rows_with_null_x() {
+        use super::*;
+
+        let mut x = ListBuilder::new(Float64Builder::new());
+        let mut y = ListBuilder::new(Float64Builder::new());
+        for i in 0..3 {
+            if i == 1 {
+                x.append_null();
+            } else {
+                x.append_value([Some(1.0), Some(2.0)]);
+            }
+            y.append_value([Some(i as f64), Some(0.5)]);
+        }
+        let batch = RecordBatch::try_from_iter([
+            ("x", Arc::new(x.finish()) as ArrayRef),
+            ("y", Arc::new(y.finish()) as ArrayRef),
+            ("id", Arc::new(Int64Array::from(vec![0, 1, 2])) as ArrayRef),
+        ])
+        .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("rusty-panda-null-x-{}.parquet", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let dataset = load_file(&path, &LoadOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        let dataset = dataset.unwrap();
+        assert_eq!(dataset.n_skipped_rows, 1);
+        let ids: Vec<_> = dataset
+            .spectra
+            .iter()
+            .map(|sp| sp.metadata["id"].clone())
+            .collect();
+        assert_eq!(ids, [MetadataValue::Integer(0), MetadataValue::Integer(2)]);
+        assert_eq!(dataset.spectra[1].y, [2.0, 0.5]);
+    }
+
+    #[test]
+    fn decodes_asdf_lines() {
+        use super::*;
+
+        // AFFN, with and without exponents.
+        let (values, dif) = decode_asdf_line("4000 1.5E+03 -2e-1 12").unwrap();
+        assert_eq!(values, [4000.0, 1500.0, -0.2, 12.0]);
+        assert!(!dif);
+        // SQZ: `E` is +5 and `e` is -5 when not followed by a sign.
+        let (values, _) = decode_asdf_line("100E1234j5").unwrap();
+        assert_eq!(values, [100.0, 51234.0, 51219.0]);
+        let (values, _) = decode_asdf_line("100e12A").unwrap();
+        assert_eq!(values, [100.0, -512.0, 1.0]);
+        // DIF with DUP: `J0` adds 10 and `T` makes it two in a row.
+        let (values, dif) = decode_asdf_line("10@J0T").unwrap();
+        assert_eq!(values, [10.0, 0.0, 10.0, 20.0]);
+        assert!(dif);
+        // DUP of an absolute value repeats it.
+        let (values, dif) = decode_asdf_line("10AU").unwrap();
+        assert_eq!(values, [10.0, 1.0, 1.0, 1.0]);
+        assert!(!dif);
+        assert!(decode_asdf_line("J1").is_err());
+    }
+
+    /// A line following one in DIF form starts by repeating its last
+    /// ordinate as a check value, which is not a new point.
+    #[test]
+    fn drops_dif_check_value() {
+        use super::*;
+
+        let mut block = JcampBlock {
+            form: JcampForm::XyData,
+            ..JcampBlock::default()
+        };
+        block.push_data_line("1 AJJ").unwrap();
+        block.push_data_line("4 CJ").unwrap();
+        block.push_data_line("6 DE").unwrap();
+        assert_eq!(block.y, [1.0, 2.0, 3.0, 4.0, 5.0]);
     }
 }
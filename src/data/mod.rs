@@ -1,25 +1,41 @@
-/// Data layer: core types, loading, and filtering.
-///
-/// Architecture:
-/// ```text
-///  .pkl / .json / .csv
-///        │
-///        ▼
-///   ┌──────────┐
-///   │  loader   │  parse file → SpectralDataset
-///   └──────────┘
-///        │
-///        ▼
-///   ┌──────────────┐
-///   │ SpectralDataset│  Vec<Spectrum>, column index
-///   └──────────────┘
-///        │
-///        ▼
-///   ┌──────────┐
-///   │  filter   │  apply metadata predicates → filtered indices
-///   └──────────┘
-/// ```
+//! Data layer: core types, loading, filtering, and preprocessing.
+//!
+//! Architecture:
+//! ```text
+//!  .pkl / .json / .csv
+//!        │
+//!        ▼
+//!   ┌──────────┐
+//!   │  loader   │  parse file → SpectralDataset
+//!   └──────────┘
+//!        │
+//!        ▼
+//!   ┌──────────────┐
+//!   │ SpectralDataset│  Vec<Spectrum>, column index
+//!   └──────────────┘
+//!        │
+//!        ▼
+//!   ┌──────────┐
+//!   │  filter   │  apply metadata predicates → filtered indices
+//!   └──────────┘
+//!        │
+//!        ▼
+//!   ┌──────────┐
+//!   │  group    │  bucket by column, resample to a common grid, mean/std
+//!   └──────────┘
+//! ```
+//!
+//! `preprocess` transforms individual spectra for display; `analysis`
+//! extracts features (e.g. peaks) from them.
+//!
+//! `lazy` opens Parquet files larger than memory: the dataset then holds
+//! only metadata, and spectra are read through [`model::SpectralDataset::spectrum`].
 
+pub mod analysis;
+pub mod filter;
+pub mod group;
+pub mod lazy;
 pub mod loader;
 pub mod model;
-pub mod filter;
+pub mod preprocess;
+pub mod quality;
@@ -1,5 +1,17 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+use std::ops::Deref;
+use std::str::Chars;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::lazy::{LAZY_CACHE_SPECTRA, LazyDataset};
+use super::quality::quality_flags;
 
 // ---------------------------------------------------------------------------
 // MetadataValue – a single cell in a metadata column
@@ -7,13 +19,14 @@ use std::fmt;
 
 /// A dynamically-typed metadata value mirroring common Pandas dtypes.
 /// Using `BTreeMap` / `BTreeSet` downstream so `MetadataValue` must be `Ord`.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum MetadataValue {
     String(String),
     Integer(i64),
     Float(f64),
     Bool(bool),
-    /// ISO-8601 date string kept as text for simplicity.
+    /// ISO-8601 date or date-time string kept as text for simplicity;
+    /// such strings sort chronologically.
     Date(String),
     Null,
 }
@@ -57,6 +70,44 @@ impl Ord for MetadataValue {
     }
 }
 
+/// Compare strings in human order: runs of digits compare by their numeric
+/// value and everything else character by character, so `a2b` < `a10b`.
+/// Strings that only differ in leading zeros fall back to plain order.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ord = match (x.peek(), y.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d)) if c.is_ascii_digit() && d.is_ascii_digit() => {
+                let (m, n) = (digit_run(&mut x), digit_run(&mut y));
+                let (m, n) = (m.trim_start_matches('0'), n.trim_start_matches('0'));
+                // Without leading zeros, a longer run is a larger number.
+                m.len().cmp(&n.len()).then_with(|| m.cmp(n))
+            }
+            (Some(c), Some(d)) => {
+                let ord = c.cmp(d);
+                x.next();
+                y.next();
+                ord
+            }
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// Consume the run of ASCII digits at the front of `chars`.
+fn digit_run(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
 impl std::hash::Hash for MetadataValue {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         std::mem::discriminant(self).hash(state);
@@ -84,6 +135,17 @@ impl fmt::Display for MetadataValue {
 }
 
 impl MetadataValue {
+    /// Display text with floats rounded to `precision` decimals, or in
+    /// scientific notation with `precision` decimals in the mantissa. Other
+    /// values display as usual.
+    pub fn format_value(&self, precision: usize, scientific: bool) -> String {
+        match self {
+            MetadataValue::Float(v) if scientific => format!("{v:.precision$e}"),
+            MetadataValue::Float(v) => format!("{v:.precision$}"),
+            other => other.to_string(),
+        }
+    }
+
     /// Try to interpret the value as an `f64` for numeric colour mapping.
     pub fn as_f64(&self) -> Option<f64> {
         match self {
@@ -92,12 +154,126 @@ impl MetadataValue {
             _ => None,
         }
     }
+
+    /// Calendar day (`YYYY-MM-DD`) of a date value.
+    pub fn as_date(&self) -> Option<&str> {
+        match self {
+            MetadataValue::Date(d) => Some(d.get(..10).unwrap_or(d)),
+            _ => None,
+        }
+    }
+
+    /// Order for display: strings in [`natural_cmp`] order, so `Sample_2`
+    /// comes before `Sample_10`; everything else as [`Ord`].
+    pub fn natural_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (MetadataValue::String(a), MetadataValue::String(b)) => natural_cmp(a, b),
+            _ => self.cmp(other),
+        }
+    }
+
+    /// Order for display given a user-defined `order` of some values: those
+    /// listed come first, in that order, and the rest after them in
+    /// [`MetadataValue::natural_cmp`] order.
+    pub fn custom_cmp(&self, other: &Self, order: &[MetadataValue]) -> Ordering {
+        let rank = |v: &Self| order.iter().position(|o| o == v).unwrap_or(order.len());
+        rank(self)
+            .cmp(&rank(other))
+            .then_with(|| self.natural_cmp(other))
+    }
+
+    /// Kind of the value; `None` for `Null`.
+    pub fn kind(&self) -> Option<MetadataKind> {
+        match self {
+            MetadataValue::String(_) => Some(MetadataKind::String),
+            MetadataValue::Integer(_) => Some(MetadataKind::Integer),
+            MetadataValue::Float(_) => Some(MetadataKind::Float),
+            MetadataValue::Bool(_) => Some(MetadataKind::Bool),
+            MetadataValue::Date(_) => Some(MetadataKind::Date),
+            MetadataValue::Null => None,
+        }
+    }
+
+    /// The value reinterpreted as `kind`, or `None` if it has no such
+    /// reading (e.g. a non-numeric string as an integer). `Null` stays
+    /// `Null`.
+    pub fn cast(&self, kind: MetadataKind) -> Option<MetadataValue> {
+        use MetadataValue::*;
+        let text = match self {
+            Null => return Some(Null),
+            String(s) | Date(s) => s.trim().to_string(),
+            Integer(i) => i.to_string(),
+            Float(v) => v.to_string(),
+            Bool(b) => b.to_string(),
+        };
+        match kind {
+            MetadataKind::String => Some(String(text)),
+            MetadataKind::Integer => match self {
+                Bool(b) => Some(Integer(*b as i64)),
+                Float(v) if v.fract() == 0.0 && v.abs() < i64::MAX as f64 => {
+                    Some(Integer(*v as i64))
+                }
+                Float(_) | Date(_) => None,
+                _ => text.parse().ok().map(Integer),
+            },
+            MetadataKind::Float => match self {
+                Bool(b) => Some(Float(*b as i64 as f64)),
+                Date(_) => None,
+                _ => text.parse().ok().map(Float),
+            },
+            MetadataKind::Bool => match text.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Some(Bool(true)),
+                "false" | "no" | "0" => Some(Bool(false)),
+                _ => None,
+            },
+            MetadataKind::Date => {
+                let day = text.get(..10)?;
+                chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                    .is_ok()
+                    .then_some(Date(text))
+            }
+        }
+    }
+}
+
+/// Type of a metadata column, for overriding the type guessed at load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataKind {
+    String,
+    Integer,
+    Float,
+    Bool,
+    Date,
+}
+
+impl MetadataKind {
+    pub const ALL: [MetadataKind; 5] = [
+        MetadataKind::String,
+        MetadataKind::Integer,
+        MetadataKind::Float,
+        MetadataKind::Bool,
+        MetadataKind::Date,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MetadataKind::String => "Text",
+            MetadataKind::Integer => "Integer",
+            MetadataKind::Float => "Float",
+            MetadataKind::Bool => "Boolean",
+            MetadataKind::Date => "Date",
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Spectrum – one row of the DataFrame
 // ---------------------------------------------------------------------------
 
+/// Spread of a spectrum's x spacings (standard deviation), as a fraction
+/// of its median step, above which it counts as irregularly sampled.
+pub const IRREGULAR_X_TOLERANCE: f64 = 0.01;
+
 /// A single spectrum (one row of the source DataFrame).
 #[derive(Debug, Clone)]
 pub struct Spectrum {
@@ -109,10 +285,317 @@ pub struct Spectrum {
     pub metadata: BTreeMap<String, MetadataValue>,
 }
 
+impl Spectrum {
+    /// Whether `x` is entirely non-decreasing or entirely non-increasing.
+    pub fn x_monotonic(&self) -> bool {
+        self.x.windows(2).all(|w| w[0] <= w[1]) || self.x.windows(2).all(|w| w[0] >= w[1])
+    }
+
+    /// Distances between successive x values, ignoring non-finite ones.
+    fn x_spacings(&self) -> Vec<f64> {
+        self.x
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .filter(|d| d.is_finite())
+            .collect()
+    }
+
+    /// Median distance between successive x values, or `None` with fewer
+    /// than two points.
+    pub fn x_step(&self) -> Option<f64> {
+        let mut spacings = self.x_spacings();
+        if spacings.is_empty() {
+            return None;
+        }
+        spacings.sort_by(f64::total_cmp);
+        let mid = spacings.len() / 2;
+        Some(if spacings.len().is_multiple_of(2) {
+            (spacings[mid - 1] + spacings[mid]) / 2.0
+        } else {
+            spacings[mid]
+        })
+    }
+
+    /// Whether the x spacing varies by more than [`IRREGULAR_X_TOLERANCE`]
+    /// of the median step, as when ranges were stitched together or points
+    /// dropped.
+    pub fn x_irregular(&self) -> bool {
+        let (Some(step), spacings) = (self.x_step(), self.x_spacings()) else {
+            return false;
+        };
+        let n = spacings.len() as f64;
+        let mean = spacings.iter().sum::<f64>() / n;
+        let variance = spacings.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n;
+        variance.sqrt() > IRREGULAR_X_TOLERANCE * step
+    }
+
+    /// Sort the points by increasing x, keeping points with equal x in
+    /// their original order.
+    pub fn sort_by_x(&mut self) {
+        let mut order: Vec<usize> = (0..self.x.len().min(self.y.len())).collect();
+        order.sort_by(|&a, &b| self.x[a].total_cmp(&self.x[b]));
+        self.x = order.iter().map(|&i| self.x[i]).collect();
+        self.y = order.iter().map(|&i| self.y[i]).collect();
+    }
+
+    /// Whether `other` has bit-identical x and y values and equal metadata.
+    pub fn is_duplicate_of(&self, other: &Spectrum) -> bool {
+        let same = |a: &[f64], b: &[f64]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(u, v)| u.to_bits() == v.to_bits())
+        };
+        same(&self.x, &other.x) && same(&self.y, &other.y) && self.metadata == other.metadata
+    }
+
+    /// Hash of the bit patterns of x and y and of the metadata; equal for
+    /// duplicates (see [`Spectrum::is_duplicate_of`]).
+    fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.x.len().hash(&mut hasher);
+        for v in self.x.iter().chain(&self.y) {
+            v.to_bits().hash(&mut hasher);
+        }
+        self.metadata.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Whether any x or y value is NaN or infinite.
+    pub fn has_invalid(&self) -> bool {
+        self.x.iter().chain(&self.y).any(|v| !v.is_finite())
+    }
+
+    /// Remove NaN/infinite values. Points with an invalid x are always
+    /// dropped; with [`CleanMode::Interpolate`] an invalid y is filled in
+    /// linearly from the nearest valid neighbours (or copied from the only
+    /// one, at the ends).
+    pub fn clean_invalid(&mut self, mode: CleanMode) {
+        let n = self.x.len().min(self.y.len());
+        let keep: Vec<usize> = (0..n)
+            .filter(|&i| {
+                self.x[i].is_finite() && (mode == CleanMode::Interpolate || self.y[i].is_finite())
+            })
+            .collect();
+        let x: Vec<f64> = keep.iter().map(|&i| self.x[i]).collect();
+        let mut y: Vec<f64> = keep.iter().map(|&i| self.y[i]).collect();
+
+        let valid: Vec<usize> = (0..y.len()).filter(|&i| y[i].is_finite()).collect();
+        if valid.is_empty() {
+            self.x = Vec::new();
+            self.y = Vec::new();
+            return;
+        }
+        for i in 0..y.len() {
+            if y[i].is_finite() {
+                continue;
+            }
+            // First valid point after i.
+            let next = valid.partition_point(|&j| j < i);
+            y[i] = match (next.checked_sub(1).map(|k| valid[k]), valid.get(next)) {
+                (Some(a), Some(&b)) if x[b] != x[a] => {
+                    y[a] + (y[b] - y[a]) * (x[i] - x[a]) / (x[b] - x[a])
+                }
+                (Some(a), _) => y[a],
+                (None, Some(&b)) => y[b],
+                (None, None) => unreachable!("at least one valid point"),
+            };
+        }
+        self.x = x;
+        self.y = y;
+    }
+}
+
+/// How [`Spectrum::clean_invalid`] treats NaN/infinite values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanMode {
+    /// Remove the point.
+    Drop,
+    /// Fill y in linearly from the neighbouring points.
+    Interpolate,
+}
+
+impl CleanMode {
+    pub const ALL: [CleanMode; 2] = [CleanMode::Drop, CleanMode::Interpolate];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CleanMode::Drop => "Drop points",
+            CleanMode::Interpolate => "Interpolate",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// LoadOptions – how a dataset is built from parsed spectra
+// ---------------------------------------------------------------------------
+
+/// How spectra are laid out in a Parquet table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum TableLayout {
+    /// One row per spectrum with `x`/`y` list columns.
+    #[default]
+    Wide,
+    /// One row per (x, y) point; rows sharing `id_col` form a spectrum.
+    Long {
+        id_col: String,
+        x_col: String,
+        y_col: String,
+    },
+}
+
+impl TableLayout {
+    /// Long layout with the usual tidy column names.
+    pub fn default_long() -> Self {
+        TableLayout::Long {
+            id_col: "spectrum_id".to_string(),
+            x_col: "wavenumber".to_string(),
+            y_col: "intensity".to_string(),
+        }
+    }
+}
+
+/// How spectra are laid out in a CSV file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CsvLayout {
+    /// One row per spectrum with `x`/`y` cells listing their values (see
+    /// [`CsvDialect::list_delim`]).
+    #[default]
+    Lists,
+    /// Numeric column headers form the x axis and each row's cells under
+    /// them are its intensities.
+    Matrix,
+}
+
+impl CsvLayout {
+    pub const ALL: [CsvLayout; 2] = [CsvLayout::Lists, CsvLayout::Matrix];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CsvLayout::Lists => "Lists (x/y columns)",
+            CsvLayout::Matrix => "Matrix (x in header)",
+        }
+    }
+}
+
+/// Separators of a CSV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvDialect {
+    /// Separator between fields, or `None` to pick the most frequent of
+    /// [`CsvDialect::FIELD_DELIMS`] other than the decimal separator in the
+    /// header line.
+    pub field_delim: Option<char>,
+    /// Separator between the values of an `x`/`y` list cell.
+    pub list_delim: char,
+    /// Decimal separator of numbers, `.` or `,`.
+    pub decimal: char,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            field_delim: None,
+            list_delim: ';',
+            decimal: '.',
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Field separators offered and sniffed for, with their names; on a
+    /// tie, the earlier one wins.
+    pub const FIELD_DELIMS: [(char, &'static str); 4] = [
+        (',', "Comma"),
+        (';', "Semicolon"),
+        ('\t', "Tab"),
+        ('|', "Pipe"),
+    ];
+    /// List separators offered, with their names.
+    pub const LIST_DELIMS: [(char, &'static str); 4] = [
+        (';', "Semicolon"),
+        (',', "Comma"),
+        (' ', "Space"),
+        ('|', "Pipe"),
+    ];
+    /// Decimal separators offered, with their names.
+    pub const DECIMALS: [(char, &'static str); 2] = [('.', "Point"), (',', "Comma")];
+
+    /// The name of separator `c` in `options`, or `c` itself.
+    pub fn name(options: &[(char, &'static str)], c: char) -> String {
+        options
+            .iter()
+            .find(|&&(o, _)| o == c)
+            .map_or_else(|| format!("'{c}'"), |(_, name)| name.to_string())
+    }
+
+    /// Parse `s` as a number written with this dialect's decimal separator.
+    pub fn parse_f64(&self, s: &str) -> Option<f64> {
+        if self.decimal == '.' {
+            s.parse().ok()
+        } else {
+            s.replace(self.decimal, ".").parse().ok()
+        }
+    }
+}
+
+/// Options controlling how files are parsed and how a [`SpectralDataset`]
+/// is built from the parsed rows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadOptions {
+    /// In columns holding only numbers, store integers as floats so that
+    /// `1` and `1.0` are the same value for filtering and colouring.
+    pub unify_numeric: bool,
+    /// Add per-spectrum quality flag columns (see [`quality_flags`]).
+    pub compute_quality: bool,
+    /// List `Null` among a column's unique values when some spectra lack
+    /// the column, so missing values get their own filter checkbox.
+    pub missing_as_null: bool,
+    /// Open wide-layout Parquet files without reading their spectra,
+    /// reading each one when it is needed (see
+    /// [`super::lazy::load_lazy_parquet`]).
+    pub lazy_parquet: bool,
+    /// Table layout of Parquet files.
+    pub layout: TableLayout,
+    /// Layout of CSV files.
+    pub csv_layout: CsvLayout,
+    /// Separators of CSV files.
+    pub csv_dialect: CsvDialect,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            unify_numeric: true,
+            compute_quality: false,
+            missing_as_null: false,
+            lazy_parquet: false,
+            layout: TableLayout::Wide,
+            csv_layout: CsvLayout::Lists,
+            csv_dialect: CsvDialect::default(),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // SpectralDataset – the complete loaded dataset
 // ---------------------------------------------------------------------------
 
+/// Distinct values above which an all-numeric column is indexed by its
+/// range only. At least the largest categorical threshold the side panel
+/// offers, so such columns are always shown as continuous.
+pub const MAX_UNIQUE_VALUES: usize = 1000;
+
+/// Summary of a metadata column, computed when the dataset is indexed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnProfile {
+    /// Number of distinct non-null values.
+    pub cardinality: usize,
+    /// Smallest and largest value if every non-null value is numeric.
+    pub numeric_range: Option<(f64, f64)>,
+    /// Numeric with more than [`MAX_UNIQUE_VALUES`] distinct values, so only
+    /// the range is kept in `unique_values`.
+    pub continuous: bool,
+}
+
 /// The full parsed dataset with pre-computed column indices.
 #[derive(Debug, Clone)]
 pub struct SpectralDataset {
@@ -120,31 +603,323 @@ pub struct SpectralDataset {
     pub spectra: Vec<Spectrum>,
     /// Ordered list of metadata column names (excludes x, y).
     pub column_names: Vec<String>,
-    /// For each metadata column the sorted set of unique values.
+    /// For each metadata column the sorted set of unique values. Columns
+    /// profiled as continuous keep only their minimum, maximum and null.
     pub unique_values: BTreeMap<String, BTreeSet<MetadataValue>>,
+    /// Cardinality and range of each metadata column.
+    pub profiles: BTreeMap<String, ColumnProfile>,
+    /// Number of spectra whose x values are not monotonic.
+    pub n_unordered_x: usize,
+    /// Number of source rows skipped at load for null or malformed x/y.
+    pub n_skipped_rows: usize,
+    /// Why parts of the source were left out at load, such as an Excel
+    /// column with text among its intensities.
+    pub load_errors: Vec<String>,
+    /// Number of spectra with NaN or infinite x/y values.
+    pub n_invalid: usize,
+    /// Number of spectra that exactly duplicate an earlier one.
+    pub n_duplicates: usize,
+    /// Whether columns some spectra lack list `Null` among their unique
+    /// values (see [`LoadOptions::missing_as_null`]).
+    pub missing_as_null: bool,
+    /// Whether integers are stored as floats in columns holding only
+    /// numbers, also after appending (see [`LoadOptions::unify_numeric`]).
+    pub unify_numeric: bool,
+    /// The file the x/y of `spectra` are read from, if loaded lazily; the
+    /// spectra themselves then hold only metadata. Read them with
+    /// [`SpectralDataset::spectrum`] or [`SpectralDataset::spectra_at`].
+    pub lazy: Option<Arc<LazyDataset>>,
+}
+
+/// A spectrum of a [`SpectralDataset`]: borrowed from memory, or shared
+/// from the cache of a lazily loaded file.
+#[derive(Debug, Clone)]
+pub enum SpectrumRef<'a> {
+    Borrowed(&'a Spectrum),
+    Loaded(Arc<Spectrum>),
+}
+
+impl Deref for SpectrumRef<'_> {
+    type Target = Spectrum;
+
+    fn deref(&self) -> &Spectrum {
+        match self {
+            SpectrumRef::Borrowed(sp) => sp,
+            SpectrumRef::Loaded(sp) => sp,
+        }
+    }
 }
 
 impl SpectralDataset {
-    /// Build column indices from the loaded spectra.
-    pub fn from_spectra(spectra: Vec<Spectrum>) -> Self {
+    /// Build column indices from the loaded spectra, applying `options`.
+    pub fn from_spectra(spectra: Vec<Spectrum>, options: &LoadOptions) -> Self {
+        Self::build(spectra, None, options)
+    }
+
+    /// Build column indices from the metadata-only `stubs` of a file whose
+    /// spectra `lazy` reads on demand.
+    pub fn from_lazy(stubs: Vec<Spectrum>, lazy: LazyDataset, options: &LoadOptions) -> Self {
+        Self::build(stubs, Some(Arc::new(lazy)), options)
+    }
+
+    fn build(
+        mut spectra: Vec<Spectrum>,
+        lazy: Option<Arc<LazyDataset>>,
+        options: &LoadOptions,
+    ) -> Self {
+        if options.unify_numeric {
+            unify_numeric_columns(&mut spectra);
+        }
+        if options.compute_quality {
+            for sp in &mut spectra {
+                let flags = quality_flags(sp);
+                sp.metadata.extend(flags);
+            }
+        }
+
+        let mut dataset = SpectralDataset {
+            spectra,
+            column_names: Vec::new(),
+            unique_values: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            n_unordered_x: 0,
+            n_skipped_rows: 0,
+            load_errors: Vec::new(),
+            n_invalid: 0,
+            n_duplicates: 0,
+            missing_as_null: options.missing_as_null,
+            unify_numeric: options.unify_numeric,
+            lazy,
+        };
+        dataset.rebuild_index();
+        dataset
+    }
+
+    /// Add the spectra of `other` after this dataset's own. Spectra lacking
+    /// a column that the other dataset has get `Null` for it.
+    ///
+    /// A lazily loaded dataset is read into memory first.
+    pub fn append(&mut self, mut other: SpectralDataset) {
+        self.materialize();
+        other.materialize();
+        let columns: BTreeSet<String> = self
+            .column_names
+            .iter()
+            .chain(&other.column_names)
+            .cloned()
+            .collect();
+        self.spectra.extend(other.spectra);
+        self.n_skipped_rows += other.n_skipped_rows;
+        self.load_errors.extend(other.load_errors);
+        for sp in &mut self.spectra {
+            for col in &columns {
+                sp.metadata
+                    .entry(col.clone())
+                    .or_insert(MetadataValue::Null);
+            }
+        }
+        // A column may be integers in one dataset and floats in the other.
+        if self.unify_numeric {
+            unify_numeric_columns(&mut self.spectra);
+        }
+        self.rebuild_index();
+    }
+
+    /// Read the x/y of every spectrum of a lazily loaded dataset into
+    /// memory, so it no longer depends on the file.
+    fn materialize(&mut self) {
+        let Some(lazy) = self.lazy.take() else {
+            return;
+        };
+        let all: Vec<usize> = (0..self.spectra.len()).collect();
+        for chunk in all.chunks(LAZY_CACHE_SPECTRA) {
+            for (&idx, loaded) in chunk.iter().zip(lazy.spectra(chunk, &self.spectra)) {
+                self.spectra[idx].x = loaded.x.clone();
+                self.spectra[idx].y = loaded.y.clone();
+            }
+        }
+    }
+
+    /// Spectrum `idx`, read from the file if the dataset is lazy.
+    pub fn spectrum(&self, idx: usize) -> SpectrumRef<'_> {
+        match &self.lazy {
+            Some(lazy) => SpectrumRef::Loaded(lazy.spectra(&[idx], &self.spectra).remove(0)),
+            None => SpectrumRef::Borrowed(&self.spectra[idx]),
+        }
+    }
+
+    /// The spectra at `indices`, in order. A lazy dataset reads those not
+    /// cached in one pass over the file.
+    pub fn spectra_at(&self, indices: &[usize]) -> Vec<SpectrumRef<'_>> {
+        match &self.lazy {
+            Some(lazy) => lazy
+                .spectra(indices, &self.spectra)
+                .into_iter()
+                .map(SpectrumRef::Loaded)
+                .collect(),
+            None => indices
+                .iter()
+                .map(|&idx| SpectrumRef::Borrowed(&self.spectra[idx]))
+                .collect(),
+        }
+    }
+
+    /// Sort the points of every spectrum by increasing x.
+    pub fn sort_by_x(&mut self) {
+        for sp in &mut self.spectra {
+            sp.sort_by_x();
+        }
+        self.n_unordered_x = 0;
+    }
+
+    /// Remove NaN/infinite values from every spectrum (see
+    /// [`Spectrum::clean_invalid`]).
+    pub fn clean_invalid(&mut self, mode: CleanMode) {
+        for sp in &mut self.spectra {
+            if sp.has_invalid() {
+                sp.clean_invalid(mode);
+            }
+        }
+        self.n_invalid = 0;
+        self.n_unordered_x = self.spectra.iter().filter(|sp| !sp.x_monotonic()).count();
+        self.n_duplicates = count_duplicates(&self.find_duplicates());
+    }
+
+    /// Groups of spectra that are exact copies of each other (see
+    /// [`Spectrum::is_duplicate_of`]). Only groups of two or more are
+    /// returned, each in index order, ordered by their first spectrum.
+    ///
+    /// A lazy dataset's spectra are not in memory to compare, so it has none.
+    pub fn find_duplicates(&self) -> Vec<Vec<usize>> {
+        if self.lazy.is_some() {
+            return Vec::new();
+        }
+        let mut buckets: HashMap<u64, Vec<Vec<usize>>> = HashMap::new();
+        for (idx, sp) in self.spectra.iter().enumerate() {
+            let groups = buckets.entry(sp.content_hash()).or_default();
+            match groups
+                .iter_mut()
+                .find(|group| self.spectra[group[0]].is_duplicate_of(sp))
+            {
+                Some(group) => group.push(idx),
+                None => groups.push(vec![idx]),
+            }
+        }
+        let mut duplicates: Vec<Vec<usize>> = buckets
+            .into_values()
+            .flatten()
+            .filter(|group| group.len() > 1)
+            .collect();
+        duplicates.sort_unstable_by_key(|group| group[0]);
+        duplicates
+    }
+
+    /// Drop every spectrum that duplicates an earlier one, keeping the first
+    /// of each group, and rebuild the column indices. Returns how many were
+    /// dropped.
+    pub fn remove_duplicates(&mut self) -> usize {
+        let dropped: HashSet<usize> = self
+            .find_duplicates()
+            .into_iter()
+            .flat_map(|group| group.into_iter().skip(1))
+            .collect();
+        if dropped.is_empty() {
+            return 0;
+        }
+        let mut idx = 0;
+        self.spectra.retain(|_| {
+            idx += 1;
+            !dropped.contains(&(idx - 1))
+        });
+        self.rebuild_index();
+        dropped.len()
+    }
+
+    /// Recompute `column_names`, `unique_values`, `n_unordered_x`,
+    /// `n_invalid` and `n_duplicates` from the spectra.
+    fn rebuild_index(&mut self) {
         let mut column_names_set: BTreeSet<String> = BTreeSet::new();
         let mut unique_values: BTreeMap<String, BTreeSet<MetadataValue>> = BTreeMap::new();
+        let mut present: HashMap<&str, usize> = HashMap::new();
 
-        for sp in &spectra {
+        for sp in &self.spectra {
             for (col, val) in &sp.metadata {
                 column_names_set.insert(col.clone());
                 unique_values
                     .entry(col.clone())
                     .or_default()
                     .insert(val.clone());
+                *present.entry(col).or_default() += 1;
             }
         }
-        let column_names: Vec<String> = column_names_set.into_iter().collect();
-        SpectralDataset {
-            spectra,
-            column_names,
-            unique_values,
+        if self.missing_as_null {
+            for (col, values) in unique_values.iter_mut() {
+                if present[col.as_str()] < self.spectra.len() {
+                    values.insert(MetadataValue::Null);
+                }
+            }
+        }
+        self.column_names = column_names_set.into_iter().collect();
+        self.profiles = unique_values
+            .iter_mut()
+            .map(|(col, values)| (col.clone(), profile_column(values)))
+            .collect();
+        self.unique_values = unique_values;
+        // A lazy dataset's x/y are not in memory to check.
+        if self.lazy.is_some() {
+            return;
+        }
+        self.n_unordered_x = self.spectra.iter().filter(|sp| !sp.x_monotonic()).count();
+        self.n_invalid = self.spectra.iter().filter(|sp| sp.has_invalid()).count();
+        self.n_duplicates = count_duplicates(&self.find_duplicates());
+    }
+
+    /// Whether `column` should be treated as continuous rather than
+    /// categorical: every non-null value is numeric and there are more than
+    /// `threshold` distinct values.
+    pub fn is_continuous(&self, column: &str, threshold: usize) -> bool {
+        if self.profiles.get(column).is_some_and(|p| p.continuous) {
+            return true;
+        }
+        let Some(values) = self.unique_values.get(column) else {
+            return false;
+        };
+        let mut n_numeric = 0;
+        for v in values {
+            match v {
+                MetadataValue::Null => {}
+                v if v.as_f64().is_some() => n_numeric += 1,
+                _ => return false,
+            }
+        }
+        n_numeric > threshold
+    }
+
+    /// Whether the x values of the first spectrum are strictly decreasing,
+    /// as is usual for wavenumber data.
+    pub fn x_decreasing(&self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let sp = self.spectrum(0);
+        sp.x.len() > 1 && sp.x.windows(2).all(|w| w[0] > w[1])
+    }
+
+    /// Reinterpret every value of column `col` as `to` and rebuild the
+    /// column indices. Values that cannot be converted become `Null`;
+    /// returns how many did.
+    pub fn recast_column(&mut self, col: &str, to: MetadataKind) -> usize {
+        let mut failed = 0;
+        for sp in &mut self.spectra {
+            if let Some(value) = sp.metadata.get_mut(col) {
+                *value = value.cast(to).unwrap_or_else(|| {
+                    failed += 1;
+                    MetadataValue::Null
+                });
+            }
         }
+        self.rebuild_index();
+        failed
     }
 
     /// Number of spectra.
@@ -157,3 +932,123 @@ impl SpectralDataset {
         self.spectra.is_empty()
     }
 }
+
+/// Number of spectra beyond the first in each group of duplicates.
+fn count_duplicates(groups: &[Vec<usize>]) -> usize {
+    groups.iter().map(|group| group.len() - 1).sum()
+}
+
+/// Profile a column from its set of unique values. A continuous column's
+/// set is reduced to its extremes (and null, if present).
+fn profile_column(values: &mut BTreeSet<MetadataValue>) -> ColumnProfile {
+    let has_null = values.contains(&MetadataValue::Null);
+    let cardinality = values.len() - usize::from(has_null);
+    let numeric: Option<Vec<f64>> = values
+        .iter()
+        .filter(|v| !matches!(v, MetadataValue::Null))
+        .map(MetadataValue::as_f64)
+        .collect();
+    let numeric_range = numeric.filter(|v| !v.is_empty()).map(|v| {
+        v.iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| {
+                (lo.min(x), hi.max(x))
+            })
+    });
+    let continuous = numeric_range.is_some() && cardinality > MAX_UNIQUE_VALUES;
+    if let Some((lo, hi)) = numeric_range.filter(|_| continuous) {
+        values.retain(|v| v.as_f64().is_none_or(|x| x == lo || x == hi));
+    }
+    ColumnProfile {
+        cardinality,
+        numeric_range,
+        continuous,
+    }
+}
+
+/// Minimum and maximum of the numeric values in `values`, if any.
+pub fn numeric_range<'a>(
+    values: impl IntoIterator<Item = &'a MetadataValue>,
+) -> Option<(f64, f64)> {
+    values
+        .into_iter()
+        .filter_map(MetadataValue::as_f64)
+        .fold(None, |acc, v| match acc {
+            None => Some((v, v)),
+            Some((min, max)) => Some((min.min(v), max.max(v))),
+        })
+}
+
+/// Counts of the finite `values` in `bins` equal-width bins spanning their
+/// minimum to maximum. If all values are equal they fall in the first bin.
+pub fn histogram(values: &[f64], bins: usize) -> Vec<u32> {
+    let mut counts = vec![0; bins];
+    let finite = || values.iter().copied().filter(|v| v.is_finite());
+    let Some((lo, hi)) = finite().fold(None, |acc: Option<(f64, f64)>, v| match acc {
+        None => Some((v, v)),
+        Some((lo, hi)) => Some((lo.min(v), hi.max(v))),
+    }) else {
+        return counts;
+    };
+    if bins == 0 {
+        return counts;
+    }
+    let width = (hi - lo) / bins as f64;
+    for v in finite() {
+        let bin = if width > 0.0 {
+            ((v - lo) / width) as usize
+        } else {
+            0
+        };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    counts
+}
+
+/// Earliest and latest calendar day among the date values in `values`.
+pub fn date_range<'a>(
+    values: impl IntoIterator<Item = &'a MetadataValue>,
+) -> Option<(&'a str, &'a str)> {
+    values
+        .into_iter()
+        .filter_map(MetadataValue::as_date)
+        .fold(None, |acc, d| match acc {
+            None => Some((d, d)),
+            Some((min, max)) => Some((min.min(d), max.max(d))),
+        })
+}
+
+/// Convert `Integer` values to `Float` in every column that mixes the two
+/// and holds nothing else but nulls.
+fn unify_numeric_columns(spectra: &mut [Spectrum]) {
+    // column → (has_integer, has_float, has_other)
+    let mut kinds: BTreeMap<&str, (bool, bool, bool)> = BTreeMap::new();
+    for sp in spectra.iter() {
+        for (col, val) in &sp.metadata {
+            let entry = kinds.entry(col.as_str()).or_default();
+            match val {
+                MetadataValue::Integer(_) => entry.0 = true,
+                MetadataValue::Float(_) => entry.1 = true,
+                MetadataValue::Null => {}
+                _ => entry.2 = true,
+            }
+        }
+    }
+    let mixed: BTreeSet<String> = kinds
+        .into_iter()
+        .filter(|(_, (int, float, other))| *int && *float && !*other)
+        .map(|(col, _)| col.to_string())
+        .collect();
+    if mixed.is_empty() {
+        return;
+    }
+
+    for sp in spectra.iter_mut() {
+        for col in &mixed {
+            if let Some(val) = sp.metadata.get_mut(col) {
+                if let MetadataValue::Integer(i) = *val {
+                    *val = MetadataValue::Float(i as f64);
+                }
+            }
+        }
+    }
+}
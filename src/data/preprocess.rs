@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::model::Spectrum;
+
+// ---------------------------------------------------------------------------
+// Region trimming
+// ---------------------------------------------------------------------------
+
+/// Copy of `sp` keeping only the points with x in `[lo, hi]` (the bounds
+/// may be given in either order).
+pub fn trim_region(sp: &Spectrum, lo: f64, hi: f64) -> Spectrum {
+    let (lo, hi) = if lo > hi { (hi, lo) } else { (lo, hi) };
+    let (x, y) =
+        sp.x.iter()
+            .zip(&sp.y)
+            .filter(|(x, _)| (lo..=hi).contains(*x))
+            .map(|(&x, &y)| (x, y))
+            .unzip();
+    Spectrum {
+        x,
+        y,
+        metadata: sp.metadata.clone(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Binning
+// ---------------------------------------------------------------------------
+
+/// Copy of `sp` averaged into bins `bin_width` wide, starting at its
+/// smallest x. Each bin becomes one point at its centre with the mean
+/// intensity of the points in it; bins without a finite point are omitted
+/// rather than left as gaps. Points keep the direction of the input x.
+///
+/// A non-positive or non-finite `bin_width` leaves the spectrum unchanged.
+pub fn bin_spectrum(sp: &Spectrum, bin_width: f64) -> Spectrum {
+    let points = || {
+        sp.x.iter()
+            .zip(&sp.y)
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+    };
+    let x0 = points().map(|(&x, _)| x).fold(f64::INFINITY, f64::min);
+    if !(bin_width > 0.0 && bin_width.is_finite() && x0.is_finite()) {
+        return sp.clone();
+    }
+
+    // Bin number → (sum of y, count).
+    let mut bins: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for (&x, &y) in points() {
+        let bin = bins
+            .entry(((x - x0) / bin_width).floor() as i64)
+            .or_default();
+        bin.0 += y;
+        bin.1 += 1;
+    }
+    let (mut x, mut y): (Vec<f64>, Vec<f64>) = bins
+        .into_iter()
+        .map(|(bin, (sum, n))| (x0 + (bin as f64 + 0.5) * bin_width, sum / n as f64))
+        .unzip();
+    let mut finite_x = points().map(|(&x, _)| x);
+    if finite_x.next() > finite_x.next_back() {
+        x.reverse();
+        y.reverse();
+    }
+    Spectrum {
+        x,
+        y,
+        metadata: sp.metadata.clone(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Offset correction
+// ---------------------------------------------------------------------------
+
+/// `y` minus its value at the point nearest to `ref_x`, so the spectrum is
+/// zero there. Points without a finite value are not considered; if there
+/// are none, `y` is returned unchanged.
+pub fn offset_at(x: &[f64], y: &[f64], ref_x: f64) -> Vec<f64> {
+    let nearest = x
+        .iter()
+        .zip(y)
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .min_by(|a, b| (a.0 - ref_x).abs().total_cmp(&(b.0 - ref_x).abs()));
+    match nearest {
+        Some((_, &offset)) => y.iter().map(|v| v - offset).collect(),
+        None => y.to_vec(),
+    }
+}
+
+/// Piecewise-linear baseline through the points nearest to each anchor x,
+/// evaluated at every `x`; constant beyond the outer anchors. `None` with
+/// fewer than two distinct anchor points.
+pub fn anchor_baseline(x: &[f64], y: &[f64], anchors: &[f64]) -> Option<Vec<f64>> {
+    let finite: Vec<(f64, f64)> = x
+        .iter()
+        .zip(y)
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .map(|(&x, &y)| (x, y))
+        .collect();
+    let mut knots: Vec<(f64, f64)> = anchors
+        .iter()
+        .filter_map(|&a| {
+            finite
+                .iter()
+                .copied()
+                .min_by(|p, q| (p.0 - a).abs().total_cmp(&(q.0 - a).abs()))
+        })
+        .collect();
+    knots.sort_by(|a, b| a.0.total_cmp(&b.0));
+    knots.dedup_by(|a, b| a.0 == b.0);
+    if knots.len() < 2 {
+        return None;
+    }
+
+    let baseline = x
+        .iter()
+        .map(|&xi| {
+            let i = knots.partition_point(|k| k.0 < xi);
+            if i == 0 {
+                knots[0].1
+            } else if i == knots.len() {
+                knots[i - 1].1
+            } else {
+                let ((x0, y0), (x1, y1)) = (knots[i - 1], knots[i]);
+                y0 + (y1 - y0) * (xi - x0) / (x1 - x0)
+            }
+        })
+        .collect();
+    Some(baseline)
+}
+
+/// `y` minus the [`anchor_baseline`] through `anchors`; unchanged with
+/// fewer than two anchors.
+pub fn linear_baseline(x: &[f64], y: &[f64], anchors: &[f64]) -> Vec<f64> {
+    match anchor_baseline(x, y, anchors) {
+        Some(baseline) => y.iter().zip(baseline).map(|(v, b)| v - b).collect(),
+        None => y.to_vec(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Normalization
+// ---------------------------------------------------------------------------
+
+/// Per-spectrum intensity normalization.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Normalization {
+    #[default]
+    None,
+    /// Scale to `[0, 1]`.
+    MinMax,
+    /// Standard Normal Variate: zero mean, unit standard deviation.
+    Snv,
+    /// Unit Euclidean norm.
+    Vector,
+    /// Unit area under the curve.
+    Area,
+}
+
+impl Normalization {
+    pub const ALL: [Normalization; 5] = [
+        Normalization::None,
+        Normalization::MinMax,
+        Normalization::Snv,
+        Normalization::Vector,
+        Normalization::Area,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Normalization::None => "None",
+            Normalization::MinMax => "Min-Max",
+            Normalization::Snv => "SNV",
+            Normalization::Vector => "Vector",
+            Normalization::Area => "Area",
+        }
+    }
+
+    /// Apply this normalization to `y`, sampled at `x`.
+    pub fn apply(self, x: &[f64], y: Vec<f64>) -> Vec<f64> {
+        match self {
+            Normalization::None => y,
+            Normalization::MinMax => min_max(&y),
+            Normalization::Snv => snv(&y),
+            Normalization::Vector => vector_normalize(&y),
+            Normalization::Area => area_normalize(x, &y),
+        }
+    }
+}
+
+/// Scale `y` linearly to `[0, 1]`. A constant vector maps to all zeros.
+pub fn min_max(y: &[f64]) -> Vec<f64> {
+    min_max_with_range(y, y_range(y))
+}
+
+/// Smallest and largest value of `y`.
+pub fn y_range(y: &[f64]) -> (f64, f64) {
+    let min = y.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = y.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    (min, max)
+}
+
+/// [`min_max`] with the range of `y` already known.
+pub fn min_max_with_range(y: &[f64], (min, max): (f64, f64)) -> Vec<f64> {
+    let range = max - min;
+    if range.abs() < f64::EPSILON {
+        vec![0.0; y.len()]
+    } else {
+        y.iter().map(|&yi| (yi - min) / range).collect()
+    }
+}
+
+/// Standard Normal Variate: subtract the mean and divide by the sample
+/// standard deviation. If the deviation is zero the mean-centred vector is
+/// returned unscaled.
+pub fn snv(y: &[f64]) -> Vec<f64> {
+    let n = y.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mean = y.iter().sum::<f64>() / n as f64;
+    let centred: Vec<f64> = y.iter().map(|v| v - mean).collect();
+    if n < 2 {
+        return centred;
+    }
+    let sd = (centred.iter().map(|v| v * v).sum::<f64>() / (n - 1) as f64).sqrt();
+    if sd > 0.0 {
+        centred.iter().map(|v| v / sd).collect()
+    } else {
+        centred
+    }
+}
+
+/// Divide `y` by its Euclidean norm. A zero vector is returned unchanged.
+pub fn vector_normalize(y: &[f64]) -> Vec<f64> {
+    let norm = y.iter().map(|v| v * v).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        y.iter().map(|v| v / norm).collect()
+    } else {
+        y.to_vec()
+    }
+}
+
+/// Divide `y` by its area over `x` so the spectrum integrates to 1. A
+/// spectrum with zero or negative area (e.g. after baseline subtraction) is
+/// returned unchanged.
+pub fn area_normalize(x: &[f64], y: &[f64]) -> Vec<f64> {
+    let area = area_between(x, y, f64::NEG_INFINITY, f64::INFINITY);
+    scale_to_unit_area(y.to_vec(), area)
+}
+
+/// Area under `y` over the points with x in `[lo, hi]` (the bounds may be
+/// given in either order), by the trapezoidal rule along increasing x, so
+/// non-uniform grids in either direction work. Segments touching a
+/// non-finite value are skipped.
+pub fn area_between(x: &[f64], y: &[f64], lo: f64, hi: f64) -> f64 {
+    let (lo, hi) = if lo > hi { (hi, lo) } else { (lo, hi) };
+    x.windows(2)
+        .zip(y.windows(2))
+        .filter(|(x, y)| x.iter().all(|v| (lo..=hi).contains(v)) && y.iter().all(|v| v.is_finite()))
+        .map(|(x, y)| (x[1] - x[0]).abs() * (y[0] + y[1]) / 2.0)
+        .sum()
+}
+
+/// `y` divided by `area`, or unchanged if the area is not positive.
+pub fn scale_to_unit_area(y: Vec<f64>, area: f64) -> Vec<f64> {
+    if area > 0.0 && area.is_finite() {
+        y.into_iter().map(|v| v / area).collect()
+    } else {
+        y
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Savitzky–Golay smoothing
+// ---------------------------------------------------------------------------
+
+/// Parameters for [`savitzky_golay`] smoothing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavGolParams {
+    /// Number of points in the moving window (odd).
+    pub window: usize,
+    /// Order of the local polynomial fit (less than `window`).
+    pub poly_order: usize,
+}
+
+impl Default for SavGolParams {
+    fn default() -> Self {
+        SavGolParams {
+            window: 11,
+            poly_order: 2,
+        }
+    }
+}
+
+/// Smooth `y` with a Savitzky–Golay filter: each point is replaced by the
+/// value at its centre of a least-squares polynomial fit over the window.
+///
+/// `window` is clamped to an odd size larger than `poly_order` and no larger
+/// than `y`. Near the ends the window shrinks symmetrically (and the
+/// polynomial order with it) so that no samples are invented.
+pub fn savitzky_golay(y: &[f64], window: usize, poly_order: usize) -> Vec<f64> {
+    let n = y.len();
+    if n < 3 {
+        return y.to_vec();
+    }
+    let max_window = if n % 2 == 1 { n } else { n - 1 };
+    let window = (window.max(poly_order + 1) | 1).min(max_window);
+    let half = window / 2;
+
+    // Coefficients depend only on the half-width, which takes at most
+    // `half + 1` distinct values.
+    let coeffs: Vec<Vec<f64>> = (0..=half)
+        .map(|m| savgol_coefficients(m, poly_order.min(2 * m)))
+        .collect();
+
+    (0..n)
+        .map(|i| {
+            let m = half.min(i).min(n - 1 - i);
+            coeffs[m]
+                .iter()
+                .zip(&y[i - m..=i + m])
+                .map(|(c, v)| c * v)
+                .sum()
+        })
+        .collect()
+}
+
+/// Convolution coefficients giving the centre value of a least-squares
+/// polynomial of order `order` fitted to the `2m + 1` points `-m..=m`.
+fn savgol_coefficients(m: usize, order: usize) -> Vec<f64> {
+    let k = order + 1;
+    let offsets: Vec<f64> = (-(m as isize)..=m as isize).map(|j| j as f64).collect();
+
+    // Normal equations: (JᵀJ) b = e₀, where J[j][p] = offset_j^p.
+    // The centre coefficient for offset j is then Σ_p b_p · offset_j^p.
+    let mut a = vec![vec![0.0; k + 1]; k];
+    for (r, row) in a.iter_mut().enumerate() {
+        for (c, cell) in row.iter_mut().take(k).enumerate() {
+            *cell = offsets.iter().map(|x| x.powi((r + c) as i32)).sum();
+        }
+        row[k] = if r == 0 { 1.0 } else { 0.0 };
+    }
+    let b = solve_augmented(a);
+
+    offsets
+        .iter()
+        .map(|x| {
+            b.iter()
+                .enumerate()
+                .map(|(p, bp)| bp * x.powi(p as i32))
+                .sum()
+        })
+        .collect()
+}
+
+/// Solve a small linear system given as an augmented `k × (k + 1)` matrix,
+/// by Gaussian elimination with partial pivoting.
+fn solve_augmented(mut a: Vec<Vec<f64>>) -> Vec<f64> {
+    let k = a.len();
+    for col in 0..k {
+        let pivot = (col..k)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .unwrap_or(col);
+        a.swap(col, pivot);
+        let p = a[col][col];
+        if p == 0.0 {
+            continue;
+        }
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower {
+            let f = row[col] / p;
+            for (cell, pv) in row.iter_mut().zip(pivot_row).skip(col) {
+                *cell -= f * pv;
+            }
+        }
+    }
+
+    let mut x = vec![0.0; k];
+    for row in (0..k).rev() {
+        let s: f64 = (row + 1..k).map(|c| a[row][c] * x[c]).sum();
+        x[row] = if a[row][row] == 0.0 {
+            0.0
+        } else {
+            (a[row][k] - s) / a[row][row]
+        };
+    }
+    x
+}
+
+// ---------------------------------------------------------------------------
+// Derivatives
+// ---------------------------------------------------------------------------
+
+/// First (`order == 1`) or second (`order == 2`) derivative of `y` with
+/// respect to `x`, by three-point finite differences that allow non-uniform
+/// spacing. End points use the nearest interior stencil.
+///
+/// Spectra too short for the requested order yield zeros; any other order
+/// returns `y` unchanged. Coincident x values give NaN.
+pub fn derivative(y: &[f64], x: &[f64], order: u8) -> Vec<f64> {
+    let n = y.len().min(x.len());
+    match order {
+        1 if n >= 3 => (0..n)
+            .map(|i| {
+                let c = i.clamp(1, n - 2);
+                let (h1, h2) = (x[c] - x[c - 1], x[c + 1] - x[c]);
+                let (y0, y1, y2) = (y[c - 1], y[c], y[c + 1]);
+                if i == 0 {
+                    // Forward: derivative of the parabola at x[0].
+                    (-(2.0 * h1 + h2) / (h1 * (h1 + h2))) * y0 + ((h1 + h2) / (h1 * h2)) * y1
+                        - (h1 / (h2 * (h1 + h2))) * y2
+                } else if i == n - 1 {
+                    (h2 / (h1 * (h1 + h2))) * y0 - ((h1 + h2) / (h1 * h2)) * y1
+                        + ((2.0 * h2 + h1) / (h2 * (h1 + h2))) * y2
+                } else {
+                    -(h2 / (h1 * (h1 + h2))) * y0
+                        + ((h2 - h1) / (h1 * h2)) * y1
+                        + (h1 / (h2 * (h1 + h2))) * y2
+                }
+            })
+            .collect(),
+        1 if n == 2 => {
+            let slope = (y[1] - y[0]) / (x[1] - x[0]);
+            vec![slope; 2]
+        }
+        2 if n >= 3 => (0..n)
+            .map(|i| {
+                let c = i.clamp(1, n - 2);
+                let (h1, h2) = (x[c] - x[c - 1], x[c + 1] - x[c]);
+                2.0 * (y[c - 1] / (h1 * (h1 + h2)) - y[c] / (h1 * h2) + y[c + 1] / (h2 * (h1 + h2)))
+            })
+            .collect(),
+        1 | 2 => vec![0.0; y.len()],
+        _ => y.to_vec(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Asymmetric least squares baseline
+// ---------------------------------------------------------------------------
+
+/// Parameters for [`als_baseline`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AlsParams {
+    /// Smoothness penalty; larger values give a stiffer baseline.
+    pub lambda: f64,
+    /// Asymmetry: weight of points above the baseline (typically 0.001–0.1).
+    pub p: f64,
+    /// Number of reweighting iterations.
+    pub iters: usize,
+}
+
+impl Default for AlsParams {
+    fn default() -> Self {
+        AlsParams {
+            lambda: 1e5,
+            p: 0.01,
+            iters: 10,
+        }
+    }
+}
+
+/// Estimate a baseline under `y` with Eilers' asymmetric least squares:
+/// repeatedly solve `(W + λ DᵀD) z = W y`, where `D` is the second-difference
+/// operator, then give points above `z` weight `p` and points below `1 - p`.
+pub fn als_baseline(y: &[f64], lambda: f64, p: f64, iters: usize) -> Vec<f64> {
+    let n = y.len();
+    if n < 3 {
+        return y.to_vec();
+    }
+    let p = p.clamp(1e-6, 1.0 - 1e-6);
+    let lambda = lambda.max(0.0);
+
+    // Bands of DᵀD: main diagonal and the first two sub-diagonals.
+    let mut d0 = vec![0.0; n];
+    let mut d1 = vec![0.0; n];
+    let mut d2 = vec![0.0; n];
+    for k in 0..n - 2 {
+        d0[k] += 1.0;
+        d0[k + 1] += 4.0;
+        d0[k + 2] += 1.0;
+        d1[k + 1] -= 2.0;
+        d1[k + 2] -= 2.0;
+        d2[k + 2] += 1.0;
+    }
+
+    let mut w = vec![1.0; n];
+    let mut z = y.to_vec();
+    for _ in 0..iters.max(1) {
+        let a0: Vec<f64> = w.iter().zip(&d0).map(|(wi, d)| wi + lambda * d).collect();
+        let a1: Vec<f64> = d1.iter().map(|d| lambda * d).collect();
+        let a2: Vec<f64> = d2.iter().map(|d| lambda * d).collect();
+        let b: Vec<f64> = w.iter().zip(y).map(|(wi, yi)| wi * yi).collect();
+        z = solve_pentadiagonal(&a0, &a1, &a2, &b);
+        for ((wi, yi), zi) in w.iter_mut().zip(y).zip(&z) {
+            *wi = if yi > zi { p } else { 1.0 - p };
+        }
+    }
+    z
+}
+
+/// `y` minus its [`als_baseline`].
+pub fn als_correct(y: &[f64], params: AlsParams) -> Vec<f64> {
+    let baseline = als_baseline(y, params.lambda, params.p, params.iters);
+    y.iter().zip(&baseline).map(|(yi, zi)| yi - zi).collect()
+}
+
+/// Solve a symmetric positive-definite banded system with bandwidth 2 by
+/// Cholesky factorisation. `a0[i] = A[i][i]`, `a1[i] = A[i][i-1]` and
+/// `a2[i] = A[i][i-2]`.
+fn solve_pentadiagonal(a0: &[f64], a1: &[f64], a2: &[f64], b: &[f64]) -> Vec<f64> {
+    let n = a0.len();
+    let mut l0 = vec![0.0; n];
+    let mut l1 = vec![0.0; n];
+    let mut l2 = vec![0.0; n];
+    for i in 0..n {
+        if i >= 2 {
+            l2[i] = a2[i] / l0[i - 2];
+        }
+        if i >= 1 {
+            let prev = if i >= 2 { l2[i] * l1[i - 1] } else { 0.0 };
+            l1[i] = (a1[i] - prev) / l0[i - 1];
+        }
+        l0[i] = (a0[i] - l1[i] * l1[i] - l2[i] * l2[i])
+            .max(f64::MIN_POSITIVE)
+            .sqrt();
+    }
+
+    // L u = b, then Lᵀ z = u.
+    let mut u = vec![0.0; n];
+    for i in 0..n {
+        let mut s = b[i];
+        if i >= 1 {
+            s -= l1[i] * u[i - 1];
+        }
+        if i >= 2 {
+            s -= l2[i] * u[i - 2];
+        }
+        u[i] = s / l0[i];
+    }
+    let mut z = vec![0.0; n];
+    for i in (0..n).rev() {
+        let mut s = u[i];
+        if i + 1 < n {
+            s -= l1[i + 1] * z[i + 1];
+        }
+        if i + 2 < n {
+            s -= l2[i + 2] * z[i + 2];
+        }
+        z[i] = s / l0[i];
+    }
+    z
+}
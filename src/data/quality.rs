@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use super::model::{MetadataValue, Spectrum};
+
+// ---------------------------------------------------------------------------
+// Per-spectrum quality flags
+// ---------------------------------------------------------------------------
+
+/// Relative standard deviation (std / mean |y|) below which a spectrum
+/// counts as flat.
+const FLAT_RELATIVE_STD: f64 = 1e-6;
+
+/// Number of consecutive points at the maximum that indicate clipping.
+const SATURATION_RUN: usize = 3;
+
+/// Compute simple QC flags for a spectrum, as metadata columns:
+///
+/// * `__is_flat__`   – near-zero variance in y
+/// * `__has_nan__`   – any NaN or infinite x/y value
+/// * `__saturated__` – y sits at its maximum for several consecutive points
+/// * `__snr__`       – rough signal-to-noise ratio (peak height above the
+///   median over the noise level estimated from point-to-point differences)
+pub fn quality_flags(sp: &Spectrum) -> BTreeMap<String, MetadataValue> {
+    let has_nan = sp.x.iter().chain(&sp.y).any(|v| !v.is_finite());
+    let y: Vec<f64> = sp.y.iter().copied().filter(|v| v.is_finite()).collect();
+
+    let mut flags = BTreeMap::new();
+    flags.insert("__has_nan__".to_string(), MetadataValue::Bool(has_nan));
+    flags.insert("__is_flat__".to_string(), MetadataValue::Bool(is_flat(&y)));
+    flags.insert(
+        "__saturated__".to_string(),
+        MetadataValue::Bool(is_saturated(&y)),
+    );
+    flags.insert(
+        "__snr__".to_string(),
+        snr(&y).map_or(MetadataValue::Null, MetadataValue::Float),
+    );
+    flags
+}
+
+fn is_flat(y: &[f64]) -> bool {
+    if y.len() < 2 {
+        return true;
+    }
+    let n = y.len() as f64;
+    let mean = y.iter().sum::<f64>() / n;
+    let var = y.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    let scale = y.iter().map(|v| v.abs()).sum::<f64>() / n;
+    var.sqrt() <= FLAT_RELATIVE_STD * scale.max(f64::MIN_POSITIVE)
+}
+
+fn is_saturated(y: &[f64]) -> bool {
+    let max = y.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let mut run = 0;
+    for &v in y {
+        run = if v == max { run + 1 } else { 0 };
+        if run >= SATURATION_RUN {
+            return true;
+        }
+    }
+    false
+}
+
+/// Peak height above the median, divided by the noise level estimated from
+/// the median absolute point-to-point difference.
+fn snr(y: &[f64]) -> Option<f64> {
+    if y.len() < 3 {
+        return None;
+    }
+    let max = y.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let signal = max - median(y.to_vec());
+
+    let diffs: Vec<f64> = y.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    // For white noise Δy has std σ√2, and median |Δy| ≈ 0.6745 · σ√2.
+    let noise = 1.4826 * median(diffs) / std::f64::consts::SQRT_2;
+    if noise > 0.0 {
+        Some(signal / noise)
+    } else {
+        None
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
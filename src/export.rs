@@ -0,0 +1,644 @@
+use std::iter::once;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use eframe::egui::epaint::text::{Fonts, Galley};
+use eframe::egui::{
+    Color32, ColorImage, FontDefinitions, FontId, FontImage, Pos2, Rect, Vec2, pos2, vec2,
+};
+
+use crate::color::{ColorMap, ColorMode, ramp_color};
+use crate::data::group::{group_indices, group_stats, resample_to_grid};
+use crate::data::model::{MetadataValue, SpectralDataset};
+
+// ---------------------------------------------------------------------------
+// Plot image export
+// ---------------------------------------------------------------------------
+
+/// Crop `rect` (in points) out of a viewport screenshot. Pixels of exactly
+/// the `transparent` colour, if given, become fully transparent.
+fn crop_screenshot(
+    screenshot: &ColorImage,
+    rect: Rect,
+    pixels_per_point: f32,
+    transparent: Option<Color32>,
+) -> Result<image::RgbaImage> {
+    let [w, h] = screenshot.size;
+    let screen = Rect::from_min_max(
+        pos2(0.0, 0.0),
+        pos2(w as f32 / pixels_per_point, h as f32 / pixels_per_point),
+    );
+    let rect = rect.intersect(screen);
+    if !rect.is_positive() {
+        bail!("plot area is not visible");
+    }
+    let region = screenshot.region(&rect, Some(pixels_per_point));
+
+    let [w, h] = region.size;
+    let bytes: Vec<u8> = region
+        .pixels
+        .iter()
+        .map(|&c| match transparent {
+            Some(key) if c == key => Color32::TRANSPARENT,
+            _ => c,
+        })
+        .flat_map(|c| c.to_array())
+        .collect();
+    image::RgbaImage::from_raw(w as u32, h as u32, bytes).context("building image buffer")
+}
+
+/// Crop `rect` (in points) out of a viewport screenshot and write it to
+/// `path` as a PNG, with the `transparent` colour keyed out if given.
+pub fn save_png(
+    path: &Path,
+    screenshot: &ColorImage,
+    rect: Rect,
+    pixels_per_point: f32,
+    transparent: Option<Color32>,
+) -> Result<()> {
+    let img = crop_screenshot(screenshot, rect, pixels_per_point, transparent)?;
+    img.save_with_format(path, image::ImageFormat::Png)
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+/// Crop `rect` (in points) out of a viewport screenshot and put it on the
+/// clipboard as an image, with the `transparent` colour keyed out if given.
+pub fn copy_png(
+    clipboard: &mut arboard::Clipboard,
+    screenshot: &ColorImage,
+    rect: Rect,
+    pixels_per_point: f32,
+    transparent: Option<Color32>,
+) -> Result<()> {
+    let img = crop_screenshot(screenshot, rect, pixels_per_point, transparent)?;
+    let (width, height) = img.dimensions();
+    clipboard
+        .set_image(arboard::ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: img.into_raw().into(),
+        })
+        .context("copying the plot to the clipboard")
+}
+
+// ---------------------------------------------------------------------------
+// Rendered plot export
+// ---------------------------------------------------------------------------
+
+/// Largest width or height of a rendered plot image, in pixels.
+pub const MAX_RENDER_SIDE: u32 = 10_000;
+/// Largest supersampling factor of a rendered plot image.
+pub const MAX_SUPERSAMPLE: u32 = 8;
+/// Output rows rendered at a time, bounding the supersampled buffer.
+const STRIP_ROWS: usize = 32;
+/// Tick label and axis title size, in points.
+const PLOT_FONT_SIZE: f32 = 12.0;
+/// Gap between the frame, tick labels and titles, in points.
+const PLOT_MARGIN: f32 = 6.0;
+/// Length of the tick marks outside the frame, in points.
+const TICK_LENGTH: f32 = 4.0;
+
+/// Pixel size, supersampling and resolution of a rendered plot image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSize {
+    pub width: u32,
+    pub height: u32,
+    /// Each output pixel averages `supersample`² rendered ones.
+    pub supersample: u32,
+    /// Dots per inch, recorded in the PNG. Line widths and text are sized
+    /// in points of `dpi / 96` pixels, so they keep their printed size.
+    pub dpi: u32,
+}
+
+impl Default for RenderSize {
+    fn default() -> Self {
+        Self {
+            width: 3000,
+            height: 2000,
+            supersample: 4,
+            dpi: 300,
+        }
+    }
+}
+
+impl RenderSize {
+    /// Output pixels per point.
+    pub fn pixels_per_point(&self) -> f32 {
+        self.dpi as f32 / 96.0
+    }
+}
+
+/// A plot to render offscreen, in plot coordinates.
+#[derive(Debug, Clone)]
+pub struct PlotImage {
+    /// Points and colour of each line.
+    pub lines: Vec<(Vec<[f64; 2]>, Color32)>,
+    /// Line width, in points.
+    pub line_width: f32,
+    /// `[min, max]` of the visible x range.
+    pub x_range: [f64; 2],
+    /// `[min, max]` of the visible y range.
+    pub y_range: [f64; 2],
+    /// Position and label of each x tick.
+    pub x_ticks: Vec<(f64, String)>,
+    /// Position and label of each y tick.
+    pub y_ticks: Vec<(f64, String)>,
+    pub x_label: String,
+    pub y_label: String,
+    /// Fill behind the plot; transparent if `None`.
+    pub background: Option<Color32>,
+    /// Colour of the frame, ticks and text.
+    pub foreground: Color32,
+}
+
+/// Render `plot` at `size` and write it to `path` as a PNG: the lines,
+/// antialiased by supersampling, in a frame with tick labels, the y axis
+/// title above it and the x axis title below.
+pub fn save_plot_png(path: &Path, plot: &PlotImage, size: RenderSize) -> Result<()> {
+    if !(1..=MAX_RENDER_SIDE).contains(&size.width) || !(1..=MAX_RENDER_SIDE).contains(&size.height)
+    {
+        bail!("image size must be between 1 and {MAX_RENDER_SIDE} pixels");
+    }
+    write_png(path, &render_plot(plot, size)?, Some(size.dpi))
+}
+
+/// Draw `plot` offscreen at `size`.
+fn render_plot(plot: &PlotImage, size: RenderSize) -> Result<ColorImage> {
+    let scale = size.pixels_per_point();
+    let fonts = Fonts::new(scale, 8192, FontDefinitions::default());
+    let layout = |text: &str| {
+        fonts.layout_no_wrap(
+            text.to_string(),
+            FontId::proportional(PLOT_FONT_SIZE),
+            plot.foreground,
+        )
+    };
+    let x_ticks: Vec<_> = plot.x_ticks.iter().map(|(v, t)| (*v, layout(t))).collect();
+    let y_ticks: Vec<_> = plot.y_ticks.iter().map(|(v, t)| (*v, layout(t))).collect();
+    let (x_title, y_title) = (layout(&plot.x_label), layout(&plot.y_label));
+    let x_tick_h = x_ticks
+        .iter()
+        .map(|(_, t)| t.size().y)
+        .fold(PLOT_FONT_SIZE, f32::max);
+    let y_tick_w = y_ticks.iter().map(|(_, t)| t.size().x).fold(0.0, f32::max);
+
+    // The frame, leaving room for the y title above it, the y tick labels
+    // left of it and the x tick labels and title below it.
+    let size_pts = vec2(size.width as f32, size.height as f32) / scale;
+    let edge = PLOT_MARGIN + TICK_LENGTH;
+    let area = Rect::from_min_max(
+        pos2(
+            PLOT_MARGIN + y_tick_w + edge,
+            PLOT_MARGIN + y_title.size().y + PLOT_MARGIN,
+        ),
+        pos2(
+            size_pts.x - 2.0 * PLOT_MARGIN,
+            size_pts.y - PLOT_MARGIN - x_title.size().y - PLOT_MARGIN - x_tick_h - edge,
+        ),
+    );
+    if !area.is_positive() {
+        bail!("image is too small for its labels at {} dpi", size.dpi);
+    }
+
+    let background = plot.background.unwrap_or(Color32::TRANSPARENT);
+    let mut canvas = Canvas {
+        image: ColorImage::new([size.width as usize, size.height as usize], background),
+        scale,
+    };
+    draw_lines(
+        &mut canvas.image,
+        plot,
+        area * scale,
+        scale,
+        size.supersample,
+    );
+
+    // Frame and tick marks, one pixel or more wide.
+    let stroke = 1.0f32.max(1.0 / scale);
+    let fg = plot.foreground;
+    let (l, r, t, b) = (area.left(), area.right(), area.top(), area.bottom());
+    canvas.fill(
+        Rect::from_min_max(pos2(l - stroke, t), pos2(r, t + stroke)),
+        fg,
+    );
+    canvas.fill(
+        Rect::from_min_max(pos2(l - stroke, b), pos2(r, b + stroke)),
+        fg,
+    );
+    canvas.fill(
+        Rect::from_min_max(pos2(l - stroke, t), pos2(l, b + stroke)),
+        fg,
+    );
+    canvas.fill(
+        Rect::from_min_max(pos2(r, t), pos2(r + stroke, b + stroke)),
+        fg,
+    );
+
+    let atlas = fonts.image();
+    let [x0, x1] = plot.x_range;
+    let [y0, y1] = plot.y_range;
+    for (value, label) in &x_ticks {
+        let x = l + ((value - x0) / (x1 - x0)) as f32 * area.width();
+        if !(l..=r).contains(&x) {
+            continue;
+        }
+        canvas.fill(
+            Rect::from_min_max(
+                pos2(x - stroke / 2.0, b),
+                pos2(x + stroke / 2.0, b + TICK_LENGTH),
+            ),
+            fg,
+        );
+        let pos = pos2(x - label.size().x / 2.0, b + edge);
+        canvas.text(&atlas, label, pos, fg);
+    }
+    for (value, label) in &y_ticks {
+        let y = b - ((value - y0) / (y1 - y0)) as f32 * area.height();
+        if !(t..=b).contains(&y) {
+            continue;
+        }
+        canvas.fill(
+            Rect::from_min_max(
+                pos2(l - TICK_LENGTH, y - stroke / 2.0),
+                pos2(l, y + stroke / 2.0),
+            ),
+            fg,
+        );
+        let pos = pos2(l - edge - label.size().x, y - label.size().y / 2.0);
+        canvas.text(&atlas, label, pos, fg);
+    }
+    canvas.text(&atlas, &y_title, pos2(PLOT_MARGIN, PLOT_MARGIN), fg);
+    let x_title_pos = pos2(
+        area.center().x - x_title.size().x / 2.0,
+        b + edge + x_tick_h + PLOT_MARGIN,
+    );
+    canvas.text(&atlas, &x_title, x_title_pos, fg);
+    Ok(canvas.image)
+}
+
+/// Draw the lines of `plot` into `image`, clipped to `area` (in pixels),
+/// with each output pixel the average of `supersample`² samples. Strips of
+/// rows are rendered at the larger size and box-filtered down one at a
+/// time, so memory stays bounded however large the image.
+fn draw_lines(
+    image: &mut ColorImage,
+    plot: &PlotImage,
+    area: Rect,
+    pixels_per_point: f32,
+    supersample: u32,
+) {
+    let ss = supersample.clamp(1, MAX_SUPERSAMPLE) as usize;
+    let s = ss as f32;
+    let [w, h] = image.size;
+    // Line widths scale with the supersampling so strokes keep their width.
+    let radius = (plot.line_width * pixels_per_point * s / 2.0).max(0.5);
+    let [x0, x1] = plot.x_range;
+    let [y0, y1] = plot.y_range;
+    let to_px = |&[x, y]: &[f64; 2]| {
+        pos2(
+            (area.left() + ((x - x0) / (x1 - x0)) as f32 * area.width()) * s,
+            (area.bottom() - ((y - y0) / (y1 - y0)) as f32 * area.height()) * s,
+        )
+    };
+    let lines: Vec<(Vec<Pos2>, Color32)> = plot
+        .lines
+        .iter()
+        .map(|(points, color)| {
+            let points = points
+                .iter()
+                .filter(|p| p[0].is_finite() && p[1].is_finite())
+                .map(to_px)
+                .collect();
+            (points, *color)
+        })
+        .collect();
+    let clip = Rect::from_min_max(area.min * s, area.max * s);
+
+    let first_row = (area.top().floor().max(0.0) as usize).min(h);
+    let last_row = (area.bottom().ceil().max(0.0) as usize).min(h);
+    for strip_top in (first_row..last_row).step_by(STRIP_ROWS) {
+        let rows = STRIP_ROWS.min(last_row - strip_top);
+        let (sw, sh) = (w * ss, rows * ss);
+        let top = strip_top * ss;
+        let strip_clip = clip.intersect(Rect::from_min_max(
+            pos2(0.0, top as f32),
+            pos2(sw as f32, (top + sh) as f32),
+        ));
+        if !strip_clip.is_positive() {
+            continue;
+        }
+        let mut strip = vec![Color32::TRANSPARENT; sw * sh];
+        for (points, color) in &lines {
+            for segment in points.windows(2) {
+                let (a, b) = (segment[0], segment[1]);
+                let bounds = Rect::from_two_pos(a, b)
+                    .expand(radius)
+                    .intersect(strip_clip);
+                if !bounds.is_positive() {
+                    continue;
+                }
+                for py in bounds.top() as usize..bounds.bottom().ceil() as usize {
+                    for px in bounds.left() as usize..bounds.right().ceil() as usize {
+                        let p = pos2(px as f32 + 0.5, py as f32 + 0.5);
+                        if strip_clip.contains(p) && distance_to_segment(p, a, b) <= radius {
+                            let pixel = &mut strip[(py - top) * sw + px];
+                            *pixel = blend(*pixel, *color, 1.0);
+                        }
+                    }
+                }
+            }
+        }
+
+        let bytes: Vec<u8> = strip.iter().flat_map(|c| c.to_array()).collect();
+        let Some(big) = image::RgbaImage::from_raw(sw as u32, sh as u32, bytes) else {
+            continue;
+        };
+        // Colours are premultiplied, so averaging them is exact.
+        let small = image::imageops::thumbnail(&big, w as u32, rows as u32);
+        for (i, pixel) in small.pixels().enumerate() {
+            let [r, g, b, a] = pixel.0;
+            let dst = &mut image.pixels[(strip_top + i / w) * w + i % w];
+            *dst = blend(*dst, Color32::from_rgba_premultiplied(r, g, b, a), 1.0);
+        }
+    }
+}
+
+/// Distance from `p` to the segment from `a` to `b`.
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_sq() > 0.0 {
+        ((p - a).dot(ab) / ab.length_sq()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + t * ab)
+}
+
+/// About `target` evenly spaced round values (1, 2 or 5 times a power of
+/// ten apart) within `[min, max]`, and their spacing.
+pub fn nice_ticks(min: f64, max: f64, target: usize) -> (Vec<f64>, f64) {
+    let span = max - min;
+    if !span.is_finite() || span <= 0.0 || target == 0 {
+        return (Vec::new(), 0.0);
+    }
+    let rough = span / target as f64;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|&step| step >= rough)
+        .unwrap_or(10.0 * magnitude);
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    ((first..=last).map(|i| i as f64 * step).collect(), step)
+}
+
+// ---------------------------------------------------------------------------
+// Legend image export
+// ---------------------------------------------------------------------------
+
+/// Pixels per point of exported legend images.
+const LEGEND_SCALE: f32 = 2.0;
+/// Label text size, in points.
+const LEGEND_FONT_SIZE: f32 = 14.0;
+/// Margin around the legend and gap between its parts, in points.
+const LEGEND_MARGIN: f32 = 8.0;
+/// Size of the colour bar of a continuous legend, in points.
+const LEGEND_BAR_SIZE: Vec2 = vec2(240.0, 16.0);
+
+/// Render the legend of `color_map` on a white background and write it to
+/// `path` as a PNG: the column name, then a swatch and label per value, or
+/// for continuous maps a colour bar with min/mid/max labels.
+pub fn save_legend_png(path: &Path, color_map: &ColorMap) -> Result<()> {
+    write_png(path, &render_legend(color_map), None)
+}
+
+/// Draw the legend offscreen, sized to fit its longest label.
+fn render_legend(color_map: &ColorMap) -> ColorImage {
+    let fonts = Fonts::new(LEGEND_SCALE, 8192, FontDefinitions::default());
+    let layout = |text: String| {
+        fonts.layout_no_wrap(text, FontId::proportional(LEGEND_FONT_SIZE), Color32::BLACK)
+    };
+    let title = layout(color_map.column.clone());
+    let top = LEGEND_MARGIN + title.size().y + LEGEND_MARGIN / 2.0;
+
+    if let ColorMode::Continuous { .. } = color_map.mode {
+        const STEPS: usize = 128;
+        let ticks: Vec<_> = [0.0, 0.5, 1.0]
+            .map(|t| layout(MetadataValue::Float(color_map.ramp_value(t)).to_string()))
+            .into();
+        let label_h = ticks.iter().map(|t| t.size().y).fold(0.0, f32::max);
+        let width = LEGEND_BAR_SIZE.x.max(title.size().x) + 2.0 * LEGEND_MARGIN;
+        let height = top + LEGEND_BAR_SIZE.y + LEGEND_MARGIN / 2.0 + label_h + LEGEND_MARGIN;
+        let mut canvas = Canvas::new(vec2(width, height), LEGEND_SCALE, Color32::WHITE);
+
+        let bar = Rect::from_min_size(pos2(LEGEND_MARGIN, top), LEGEND_BAR_SIZE);
+        let step_w = bar.width() / STEPS as f32;
+        for i in 0..STEPS {
+            let x0 = bar.left() + i as f32 * step_w;
+            let step = Rect::from_min_max(pos2(x0, bar.top()), pos2(x0 + step_w, bar.bottom()));
+            canvas.fill(step, ramp_color(i as f64 / (STEPS - 1) as f64));
+        }
+
+        let atlas = fonts.image();
+        canvas.text(&atlas, &title, pos2(LEGEND_MARGIN, LEGEND_MARGIN), Color32::BLACK);
+        let y = bar.bottom() + LEGEND_MARGIN / 2.0;
+        for (tick, align) in ticks.iter().zip([0.0, 0.5, 1.0]) {
+            let x = (bar.left() + align * bar.width() - align * tick.size().x)
+                .clamp(LEGEND_MARGIN, width - LEGEND_MARGIN - tick.size().x);
+            canvas.text(&atlas, tick, pos2(x, y), Color32::BLACK);
+        }
+        return canvas.image;
+    }
+
+    let rows: Vec<_> = color_map
+        .legend_entries()
+        .into_iter()
+        .map(|(label, color)| (layout(label), color))
+        .collect();
+    let row_h = rows
+        .iter()
+        .map(|(label, _)| label.size().y)
+        .fold(LEGEND_FONT_SIZE, f32::max);
+    let swatch = row_h * 0.7;
+    let label_w = rows
+        .iter()
+        .map(|(label, _)| label.size().x)
+        .fold(0.0, f32::max);
+    let width = (swatch + LEGEND_MARGIN + label_w).max(title.size().x) + 2.0 * LEGEND_MARGIN;
+    let height = top + rows.len() as f32 * row_h + LEGEND_MARGIN;
+    let mut canvas = Canvas::new(vec2(width, height), LEGEND_SCALE, Color32::WHITE);
+
+    let atlas = fonts.image();
+    canvas.text(
+        &atlas,
+        &title,
+        pos2(LEGEND_MARGIN, LEGEND_MARGIN),
+        Color32::BLACK,
+    );
+    for (i, (label, color)) in rows.iter().enumerate() {
+        let y = top + i as f32 * row_h;
+        let swatch_min = pos2(LEGEND_MARGIN, y + (row_h - swatch) / 2.0);
+        canvas.fill(Rect::from_min_size(swatch_min, Vec2::splat(swatch)), *color);
+        let label_y = y + (row_h - label.size().y) / 2.0;
+        canvas.text(
+            &atlas,
+            label,
+            pos2(LEGEND_MARGIN + swatch + LEGEND_MARGIN, label_y),
+            Color32::BLACK,
+        );
+    }
+    canvas.image
+}
+
+/// An offscreen image addressed in points, `scale` pixels each.
+struct Canvas {
+    image: ColorImage,
+    scale: f32,
+}
+
+impl Canvas {
+    /// A canvas of `size` points filled with `background`.
+    fn new(size: Vec2, scale: f32, background: Color32) -> Self {
+        let [w, h] = [size.x, size.y].map(|s| (s * scale).ceil() as usize);
+        Canvas {
+            image: ColorImage::new([w, h], background),
+            scale,
+        }
+    }
+
+    /// Fill `rect` (in points) with `color`.
+    fn fill(&mut self, rect: Rect, color: Color32) {
+        let [w, h] = self.image.size;
+        let px = |v: f32, max: usize| ((v * self.scale).round().max(0.0) as usize).min(max);
+        for y in px(rect.top(), h)..px(rect.bottom(), h) {
+            for x in px(rect.left(), w)..px(rect.right(), w) {
+                self.image.pixels[y * w + x] = color;
+            }
+        }
+    }
+
+    /// Draw `galley` in `color` with its top left corner at `pos` (in
+    /// points), taking glyph coverage from the font `atlas` the galley was
+    /// laid out with.
+    fn text(&mut self, atlas: &FontImage, galley: &Galley, pos: Pos2, color: Color32) {
+        let [w, h] = self.image.size;
+        for glyph in galley.rows.iter().flat_map(|row| &row.glyphs) {
+            let uv = glyph.uv_rect;
+            if uv.is_nothing() {
+                continue;
+            }
+            let left_top = (pos + glyph.pos.to_vec2() + uv.offset) * self.scale;
+            let (x0, y0) = (left_top.x.round() as isize, left_top.y.round() as isize);
+            for v in uv.min[1]..uv.max[1] {
+                for u in uv.min[0]..uv.max[0] {
+                    let x = x0 + (u - uv.min[0]) as isize;
+                    let y = y0 + (v - uv.min[1]) as isize;
+                    if x < 0 || y < 0 || x as usize >= w || y as usize >= h {
+                        continue;
+                    }
+                    let coverage = atlas.pixels[v as usize * atlas.size[0] + u as usize];
+                    let pixel = &mut self.image.pixels[y as usize * w + x as usize];
+                    *pixel = blend(*pixel, color, coverage);
+                }
+            }
+        }
+    }
+}
+
+/// `over` drawn on `under` with coverage `alpha`, both premultiplied.
+fn blend(under: Color32, over: Color32, alpha: f32) -> Color32 {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let keep = 1.0 - alpha * over.a() as f32 / 255.0;
+    let mix = |u: u8, o: u8| (u as f32 * keep + o as f32 * alpha).round() as u8;
+    Color32::from_rgba_premultiplied(
+        mix(under.r(), over.r()),
+        mix(under.g(), over.g()),
+        mix(under.b(), over.b()),
+        mix(under.a(), over.a()),
+    )
+}
+
+/// Write `image` to `path` as a PNG, recording `dpi` in it if given.
+fn write_png(path: &Path, image: &ColorImage, dpi: Option<u32>) -> Result<()> {
+    let [w, h] = image.size;
+    let bytes: Vec<u8> = image
+        .pixels
+        .iter()
+        .flat_map(|c| c.to_srgba_unmultiplied())
+        .collect();
+    let img =
+        image::RgbaImage::from_raw(w as u32, h as u32, bytes).context("building image buffer")?;
+    let mut png = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .context("encoding PNG")?;
+    if let Some(dpi) = dpi {
+        png = with_resolution(png, dpi);
+    }
+    std::fs::write(path, png).with_context(|| format!("writing {}", path.display()))
+}
+
+/// `png` with a `pHYs` chunk giving its resolution, inserted after the
+/// header chunk (8-byte signature, then IHDR: 4 + 4 + 13 + 4 bytes).
+fn with_resolution(mut png: Vec<u8>, dpi: u32) -> Vec<u8> {
+    const IHDR_END: usize = 8 + 25;
+    let pixels_per_metre = (dpi as f64 / 0.0254).round() as u32;
+    let mut data = b"pHYs".to_vec();
+    data.extend(pixels_per_metre.to_be_bytes());
+    data.extend(pixels_per_metre.to_be_bytes());
+    data.push(1); // unit: metre
+    let mut crc = flate2::Crc::new();
+    crc.update(&data);
+
+    let mut chunk = 9u32.to_be_bytes().to_vec();
+    chunk.extend(data);
+    chunk.extend(crc.sum().to_be_bytes());
+    png.splice(IHDR_END..IHDR_END, chunk);
+    png
+}
+
+// ---------------------------------------------------------------------------
+// Group mean table
+// ---------------------------------------------------------------------------
+
+/// Write the mean spectrum of each `color_column` group among `indices`,
+/// resampled onto `grid`, to `path` as a wide CSV: a `wavenumber` column
+/// followed by one column per group value. Grid points a group has no data
+/// at are left empty.
+pub fn export_group_means_csv(
+    path: &Path,
+    dataset: &SpectralDataset,
+    indices: &[usize],
+    color_column: &str,
+    grid: &[f64],
+) -> Result<()> {
+    let means: Vec<(String, Vec<f64>)> = group_indices(dataset, indices, Some(color_column))
+        .into_iter()
+        .map(|(value, members)| {
+            let series: Vec<Vec<f64>> = dataset
+                .spectra_at(&members)
+                .iter()
+                .map(|sp| resample_to_grid(&sp.x, &sp.y, grid))
+                .collect();
+            (value.to_string(), group_stats(&series).mean)
+        })
+        .collect();
+
+    let mut writer =
+        csv::Writer::from_path(path).with_context(|| format!("creating {}", path.display()))?;
+    let header = once("wavenumber".to_string()).chain(means.iter().map(|(name, _)| name.clone()));
+    writer.write_record(header).context("writing header")?;
+    for (i, x) in grid.iter().enumerate() {
+        let row = once(x.to_string()).chain(means.iter().map(|(_, mean)| {
+            if mean[i].is_finite() {
+                mean[i].to_string()
+            } else {
+                String::new()
+            }
+        }));
+        writer.write_record(row).context("writing row")?;
+    }
+    writer.flush().context("finishing CSV file")?;
+    Ok(())
+}
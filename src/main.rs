@@ -1,15 +1,76 @@
 mod app;
 mod color;
 mod data;
+mod export;
+mod recipe;
+mod session;
 mod state;
 mod ui;
 
+use std::io::Read;
+use std::path::PathBuf;
+
 use app::RustyPandaApp;
+use data::loader::{self, FileFormat};
 use eframe::egui;
+use state::AppState;
+
+/// Build the initial state from the command line.
+///
+/// Usage: `rusty-panda [FILE | -] [--format parquet|json|csv]`
+///
+/// `-` reads the dataset from stdin; without `--format` the format is
+/// guessed from the first bytes of the stream.
+fn startup_state() -> AppState {
+    let mut state = AppState::default();
+    let mut input: Option<String> = None;
+    let mut format: Option<FileFormat> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--format" {
+            let value = args.next().unwrap_or_default();
+            format = FileFormat::from_extension(&value);
+            if format.is_none() {
+                state.tab.status_message = Some(format!("Error: unknown format '{value}'"));
+            }
+        } else {
+            input = Some(arg);
+        }
+    }
+
+    match input.as_deref() {
+        None => {}
+        Some("-") => {
+            let mut buf = Vec::new();
+            let result = std::io::stdin()
+                .read_to_end(&mut buf)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| {
+                    let format = format.unwrap_or_else(|| FileFormat::sniff(&buf));
+                    loader::load_reader(buf.as_slice(), format, &state.load_options)
+                });
+            match result {
+                Ok(dataset) => {
+                    log::info!("Loaded {} spectra from stdin", dataset.len());
+                    state.set_dataset(dataset);
+                }
+                Err(e) => {
+                    log::error!("Failed to load stdin: {e:#}");
+                    state.tab.status_message = Some(format!("Error: {e:#}"));
+                }
+            }
+        }
+        Some(path) => ui::panels::load_path(&mut state, PathBuf::from(path)),
+    }
+    state
+}
 
 fn main() -> eframe::Result {
     env_logger::init();
 
+    let state = startup_state();
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1200.0, 800.0])
@@ -23,7 +84,7 @@ fn main() -> eframe::Result {
         Box::new(|cc| {
             // Install image loaders so egui can render png/jpg/etc.
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            Ok(Box::new(RustyPandaApp::default()))
+            Ok(Box::new(RustyPandaApp::new(state, cc.storage)))
         }),
     )
 }
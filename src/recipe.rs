@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::color::{ColorScale, PaletteKind};
+use crate::data::analysis::{DerivedMetric, PeakParams};
+use crate::data::filter::{FilterKind, FilterLogic, active_filters};
+use crate::data::loader::load_file;
+use crate::data::model::LoadOptions;
+use crate::data::preprocess::{AlsParams, Normalization, SavGolParams};
+use crate::state::{AppState, DatasetTab, LineStyle, PlotMode, PlotTheme, PlotView, ViewBounds};
+
+// ---------------------------------------------------------------------------
+// Recipe – a reproducible description of how a view was produced
+// ---------------------------------------------------------------------------
+
+/// Human-readable JSON description of a figure: which file, which filters,
+/// which transforms and colouring, and which part of the plot is shown.
+///
+/// Meant to be small enough to version alongside the data. A relative
+/// `source_path` is resolved against the recipe file's directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recipe {
+    /// Data file the recipe applies to.
+    pub source_path: PathBuf,
+    /// Options the data file is loaded with.
+    pub load_options: LoadOptions,
+    /// Filter for each column that excludes some of its values.
+    pub filters: BTreeMap<String, FilterKind>,
+    pub filter_logic: FilterLogic,
+    pub metric_filters: BTreeMap<DerivedMetric, RangeInclusive<f64>>,
+    /// Column used for colouring.
+    pub color_column: Option<String>,
+    pub color_from_hex: bool,
+    pub palette: PaletteKind,
+    pub cvd_safe: bool,
+    pub color_scale: ColorScale,
+    pub categorical_threshold: usize,
+    pub normalization: Normalization,
+    pub derivative_order: u8,
+    pub reverse_x: bool,
+    pub baseline: Option<AlsParams>,
+    pub smoothing: Option<SavGolParams>,
+    pub binning: Option<f64>,
+    pub baseline_point: Option<f64>,
+    pub baseline_anchors: Vec<f64>,
+    pub subtract_anchors: bool,
+    pub log_y: bool,
+    pub region: Option<(f64, f64)>,
+    pub show_peaks: bool,
+    pub peak_params: PeakParams,
+    pub plot_view: PlotView,
+    pub heatmap_palette: PaletteKind,
+    pub plot_mode: PlotMode,
+    pub waterfall_spacing: f64,
+    pub line_width: f32,
+    pub line_style: LineStyle,
+    pub plot_theme: PlotTheme,
+    pub darken_light_lines: bool,
+    pub show_group_means: bool,
+    pub mean_line_width: f32,
+    pub mean_line_style: LineStyle,
+    pub show_std_band: bool,
+    pub error_bars: bool,
+    pub error_bar_spacing: usize,
+    pub highlight_outliers: bool,
+    pub outlier_sigma: f64,
+    pub render_limit: usize,
+    pub value_precision: usize,
+    pub scientific_values: bool,
+    /// Visible plot area when the recipe was saved.
+    pub view_bounds: Option<ViewBounds>,
+    /// Side-panel column order and hidden columns.
+    pub column_order: Vec<String>,
+    pub hidden_columns: BTreeSet<String>,
+    /// Locked axis ranges, if any.
+    pub x_bounds: Option<[f64; 2]>,
+    pub y_bounds: Option<[f64; 2]>,
+}
+
+impl Default for Recipe {
+    fn default() -> Self {
+        Recipe::from_state(&AppState::default())
+    }
+}
+
+impl Recipe {
+    /// Capture the reproducible parts of `state`.
+    pub fn from_state(state: &AppState) -> Self {
+        let filters = state
+            .tab
+            .dataset
+            .as_ref()
+            .map(|ds| {
+                active_filters(ds, &state.tab.filters)
+                    .into_iter()
+                    .map(|(col, filter)| (col.to_string(), filter.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Recipe {
+            source_path: state.tab.source_path.clone().unwrap_or_default(),
+            load_options: state.load_options.clone(),
+            filters,
+            filter_logic: state.filter_logic,
+            metric_filters: state.tab.metric_filters.clone(),
+            color_column: state.tab.color_column.clone(),
+            color_from_hex: state.color_from_hex,
+            palette: state.palette,
+            cvd_safe: state.cvd_safe,
+            color_scale: state.color_scale,
+            categorical_threshold: state.categorical_threshold,
+            normalization: state.normalization,
+            derivative_order: state.derivative_order,
+            reverse_x: state.reverse_x,
+            baseline: state.baseline,
+            smoothing: state.smoothing,
+            binning: state.binning,
+            baseline_point: state.baseline_point,
+            baseline_anchors: state.baseline_anchors.clone(),
+            subtract_anchors: state.subtract_anchors,
+            log_y: state.log_y,
+            region: state.region,
+            show_peaks: state.show_peaks,
+            peak_params: state.peak_params,
+            plot_view: state.plot_view,
+            heatmap_palette: state.heatmap_palette,
+            plot_mode: state.plot_mode,
+            waterfall_spacing: state.waterfall_spacing,
+            line_width: state.line_width,
+            line_style: state.line_style,
+            plot_theme: state.plot_theme,
+            darken_light_lines: state.darken_light_lines,
+            show_group_means: state.show_group_means,
+            mean_line_width: state.mean_line_width,
+            mean_line_style: state.mean_line_style,
+            show_std_band: state.show_std_band,
+            error_bars: state.error_bars,
+            error_bar_spacing: state.error_bar_spacing,
+            highlight_outliers: state.highlight_outliers,
+            outlier_sigma: state.outlier_sigma,
+            render_limit: state.render_limit,
+            value_precision: state.value_precision,
+            scientific_values: state.scientific_values,
+            view_bounds: state.tab.view_bounds,
+            column_order: state.ordered_columns(),
+            hidden_columns: state.tab.hidden_columns.clone(),
+            x_bounds: state.x_bounds,
+            y_bounds: state.y_bounds,
+        }
+    }
+}
+
+/// Write the current view of `state` as a JSON recipe to `path`.
+pub fn save_recipe(path: &Path, state: &AppState) -> Result<()> {
+    let mut recipe = Recipe::from_state(state);
+
+    // Store the data path relative to the recipe when it lives below it.
+    if let Some(dir) = path.parent() {
+        if let Ok(rel) = recipe.source_path.strip_prefix(dir) {
+            recipe.source_path = rel.to_path_buf();
+        }
+    }
+
+    let text = serde_json::to_string_pretty(&recipe).context("serializing recipe")?;
+    std::fs::write(path, text).context("writing recipe file")?;
+    Ok(())
+}
+
+/// Load the data file referenced by the recipe at `path` and rebuild the
+/// state it describes. Filter columns or values that no longer exist in
+/// the data are skipped with a warning.
+pub fn apply_recipe(path: &Path) -> Result<AppState> {
+    let text = std::fs::read_to_string(path).context("reading recipe file")?;
+    let recipe: Recipe = serde_json::from_str(&text).context("parsing recipe")?;
+
+    let source_path = match path.parent() {
+        Some(dir) if recipe.source_path.is_relative() => dir.join(&recipe.source_path),
+        _ => recipe.source_path.clone(),
+    };
+    let dataset = load_file(&source_path, &recipe.load_options)
+        .with_context(|| format!("loading {}", source_path.display()))?;
+
+    let mut state = AppState {
+        tab: DatasetTab {
+            pending_view: recipe.view_bounds,
+            column_order: recipe.column_order,
+            hidden_columns: recipe.hidden_columns,
+            ..DatasetTab::default()
+        },
+        load_options: recipe.load_options,
+        filter_logic: recipe.filter_logic,
+        color_from_hex: recipe.color_from_hex,
+        palette: recipe.palette,
+        cvd_safe: recipe.cvd_safe,
+        color_scale: recipe.color_scale,
+        categorical_threshold: recipe.categorical_threshold,
+        normalization: recipe.normalization,
+        derivative_order: recipe.derivative_order,
+        baseline: recipe.baseline,
+        smoothing: recipe.smoothing,
+        binning: recipe.binning,
+        baseline_point: recipe.baseline_point,
+        baseline_anchors: recipe.baseline_anchors,
+        subtract_anchors: recipe.subtract_anchors,
+        log_y: recipe.log_y,
+        region: recipe.region,
+        show_peaks: recipe.show_peaks,
+        peak_params: recipe.peak_params,
+        plot_view: recipe.plot_view,
+        heatmap_palette: recipe.heatmap_palette,
+        plot_mode: recipe.plot_mode,
+        waterfall_spacing: recipe.waterfall_spacing,
+        line_width: recipe.line_width,
+        line_style: recipe.line_style,
+        plot_theme: recipe.plot_theme,
+        darken_light_lines: recipe.darken_light_lines,
+        show_group_means: recipe.show_group_means,
+        mean_line_width: recipe.mean_line_width,
+        mean_line_style: recipe.mean_line_style,
+        show_std_band: recipe.show_std_band,
+        error_bars: recipe.error_bars,
+        error_bar_spacing: recipe.error_bar_spacing,
+        highlight_outliers: recipe.highlight_outliers,
+        outlier_sigma: recipe.outlier_sigma,
+        render_limit: recipe.render_limit,
+        value_precision: recipe.value_precision,
+        scientific_values: recipe.scientific_values,
+        x_bounds: recipe.x_bounds,
+        y_bounds: recipe.y_bounds,
+        ..AppState::default()
+    };
+    state.set_dataset(dataset);
+    state.tab.source_path = Some(source_path);
+    state.tab.metric_filters = recipe.metric_filters;
+    state.reverse_x = recipe.reverse_x;
+
+    let mut skipped = state.restore_filters(recipe.filters);
+    if let Some(col) = recipe.color_column {
+        if !state.restore_color_column(col) {
+            skipped += 1;
+        }
+    }
+
+    state.refilter();
+    if skipped > 0 {
+        state.tab.status_message = Some(format!(
+            "Recipe applied; {skipped} filter/colour entries not found in data"
+        ));
+    }
+    Ok(state)
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use eframe::egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use crate::data::analysis::DerivedMetric;
+use crate::data::filter::{FilterKind, FilterLogic, active_filters};
+use crate::data::loader::load_file;
+use crate::data::model::{LoadOptions, MetadataValue};
+use crate::data::preprocess::Normalization;
+use crate::state::{AppState, DatasetTab};
+
+// ---------------------------------------------------------------------------
+// Session – what the user was looking at, restored on demand
+// ---------------------------------------------------------------------------
+
+/// Viewer session: the open file, its filters, colour column, manual
+/// colours and value orders, normalization, axis locks and shaded regions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    /// Last-opened data file.
+    pub source_path: Option<PathBuf>,
+    /// Options the data file was loaded with.
+    pub load_options: LoadOptions,
+    /// Filter for each column that excludes some of its values.
+    pub filters: BTreeMap<String, FilterKind>,
+    pub filter_logic: FilterLogic,
+    /// Range filters on derived spectrum properties.
+    pub metric_filters: BTreeMap<DerivedMetric, RangeInclusive<f64>>,
+    /// Column used for colouring.
+    pub color_column: Option<String>,
+    /// Manual colours per column, as value/colour pairs since values cannot
+    /// be JSON object keys.
+    pub color_overrides: BTreeMap<String, Vec<(MetadataValue, Color32)>>,
+    /// User-defined order of the values of each column.
+    pub value_orders: BTreeMap<String, Vec<MetadataValue>>,
+    pub normalization: Normalization,
+    /// Locked `[min, max]` of the x and y axes; `None` auto-fits.
+    pub x_bounds: Option<[f64; 2]>,
+    pub y_bounds: Option<[f64; 2]>,
+    /// Shaded regions: start, end, colour and label.
+    pub regions: Vec<(f64, f64, Color32, String)>,
+}
+
+impl AppState {
+    /// Capture the session-level parts of the state.
+    pub fn to_session(&self) -> SessionState {
+        let filters = self
+            .tab
+            .dataset
+            .as_ref()
+            .map(|ds| {
+                active_filters(ds, &self.tab.filters)
+                    .into_iter()
+                    .map(|(col, filter)| (col.to_string(), filter.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        SessionState {
+            source_path: self.tab.source_path.clone(),
+            load_options: self.load_options.clone(),
+            filters,
+            filter_logic: self.filter_logic,
+            metric_filters: self.tab.metric_filters.clone(),
+            color_column: self.tab.color_column.clone(),
+            color_overrides: self
+                .color_overrides
+                .iter()
+                .map(|(col, colors)| (col.clone(), colors.clone().into_iter().collect()))
+                .collect(),
+            value_orders: self.tab.value_orders.clone(),
+            normalization: self.normalization,
+            x_bounds: self.x_bounds,
+            y_bounds: self.y_bounds,
+            regions: self.tab.regions.clone(),
+        }
+    }
+
+    /// Re-open the session's data file and restore its filters and colour
+    /// column. Columns and values no longer in the data are skipped with a
+    /// warning.
+    pub fn from_session(session: SessionState) -> Result<AppState> {
+        let mut state = AppState {
+            tab: DatasetTab {
+                value_orders: session.value_orders,
+                regions: session.regions,
+                ..DatasetTab::default()
+            },
+            load_options: session.load_options,
+            filter_logic: session.filter_logic,
+            color_overrides: session
+                .color_overrides
+                .into_iter()
+                .map(|(col, colors)| (col, colors.into_iter().collect()))
+                .collect(),
+            normalization: session.normalization,
+            x_bounds: session.x_bounds,
+            y_bounds: session.y_bounds,
+            ..AppState::default()
+        };
+        let Some(path) = session.source_path else {
+            return Ok(state);
+        };
+
+        let dataset = load_file(&path, &state.load_options)
+            .with_context(|| format!("loading {}", path.display()))?;
+        state.set_dataset(dataset);
+        state.tab.source_path = Some(path);
+        state.tab.metric_filters = session.metric_filters;
+
+        let mut skipped = state.restore_filters(session.filters);
+        if let Some(col) = session.color_column {
+            if !state.restore_color_column(col) {
+                skipped += 1;
+            }
+        }
+        if skipped > 0 {
+            state.tab.status_message = Some(format!(
+                "Session restored; {skipped} filter/colour entries not found in data"
+            ));
+        }
+        Ok(state)
+    }
+}
+
+/// Write the session of `state` as JSON to `path`.
+pub fn save_session(path: &Path, state: &AppState) -> Result<()> {
+    let text = serde_json::to_string_pretty(&state.to_session()).context("serializing session")?;
+    std::fs::write(path, text).context("writing session file")?;
+    Ok(())
+}
+
+/// Read the session at `path` and rebuild the state it describes.
+pub fn load_session(path: &Path) -> Result<AppState> {
+    let text = std::fs::read_to_string(path).context("reading session file")?;
+    let session: SessionState = serde_json::from_str(&text).context("parsing session")?;
+    AppState::from_session(session)
+}
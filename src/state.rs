@@ -1,51 +1,599 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
 
-use crate::color::ColorMap;
-use crate::data::filter::{FilterState, filtered_indices, init_filter_state};
-use crate::data::model::{MetadataValue, SpectralDataset};
+use anyhow::Result;
+use eframe::egui::{Color32, Rect, Theme};
+use serde::{Deserialize, Serialize};
+
+use crate::color::{ColorMap, ColorScale, PaletteKind};
+use crate::data::analysis::{DerivedMetric, PeakParams};
+use crate::data::filter::{
+    CompareOp, FilterKind, FilterLogic, FilterState, MetricFilter, filtered_indices,
+    init_filter_state,
+};
+use crate::data::group::group_indices;
+use crate::data::lazy::LAZY_CACHE_SPECTRA;
+use crate::data::model::{CleanMode, LoadOptions, MetadataKind, MetadataValue, SpectralDataset};
+use crate::data::preprocess::{
+    AlsParams, Normalization, SavGolParams, als_correct, area_between, y_range,
+};
+use crate::export::RenderSize;
 
 // ---------------------------------------------------------------------------
 // Application state
 // ---------------------------------------------------------------------------
 
+/// Visible plot area in data coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ViewBounds {
+    /// `[min, max]` of the x axis.
+    pub x: [f64; 2],
+    /// `[min, max]` of the y axis.
+    pub y: [f64; 2],
+}
+
+/// A file load running on a background thread.
+pub struct PendingLoad {
+    /// File or directory being loaded; several files are combined into one
+    /// dataset.
+    pub paths: Vec<PathBuf>,
+    /// What to do with the dataset once loaded.
+    pub mode: LoadMode,
+    /// Fraction of the file parsed so far, as `f64` bits.
+    pub progress: Arc<AtomicU64>,
+    /// Delivers the loader's result when the thread finishes.
+    pub receiver: Receiver<Result<SpectralDataset>>,
+}
+
+/// How a freshly loaded dataset is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Replace the current dataset.
+    Open,
+    /// Replace the current dataset with a new copy of the same file,
+    /// keeping filters (see [`AppState::reload_dataset`]).
+    Reload,
+    /// Add the spectra to the current dataset (see
+    /// [`AppState::append_dataset`]).
+    Append,
+}
+
+impl PendingLoad {
+    /// Fraction of the file parsed so far, in `[0, 1]`.
+    pub fn progress(&self) -> f64 {
+        f64::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+}
+
+/// How visible spectra are laid out in the plot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlotMode {
+    /// All spectra drawn on the same baseline.
+    #[default]
+    Overlay,
+    /// Each successive spectrum shifted up by a constant offset.
+    Waterfall,
+}
+
+/// What the central panel shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlotView {
+    /// One line per spectrum.
+    #[default]
+    Lines,
+    /// One image row per spectrum, coloured by intensity.
+    Heatmap,
+    /// Pairwise correlation between spectra, as an image.
+    Correlation,
+    /// Scatter of principal component scores.
+    Pca,
+}
+
+impl PlotView {
+    pub const ALL: [PlotView; 4] = [
+        PlotView::Lines,
+        PlotView::Heatmap,
+        PlotView::Correlation,
+        PlotView::Pca,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PlotView::Lines => "Lines",
+            PlotView::Heatmap => "Heatmap",
+            PlotView::Correlation => "Correlation",
+            PlotView::Pca => "PCA",
+        }
+    }
+}
+
+/// Dash pattern of plotted lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl LineStyle {
+    pub const ALL: [LineStyle; 3] = [LineStyle::Solid, LineStyle::Dashed, LineStyle::Dotted];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LineStyle::Solid => "Solid",
+            LineStyle::Dashed => "Dashed",
+            LineStyle::Dotted => "Dotted",
+        }
+    }
+
+    /// The equivalent egui_plot style.
+    pub fn plot_style(self) -> egui_plot::LineStyle {
+        match self {
+            LineStyle::Solid => egui_plot::LineStyle::Solid,
+            LineStyle::Dashed => egui_plot::LineStyle::dashed_dense(),
+            LineStyle::Dotted => egui_plot::LineStyle::dotted_dense(),
+        }
+    }
+}
+
+/// Colour scheme of the UI and the plot background.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlotTheme {
+    #[default]
+    Dark,
+    /// Light UI with a white plot background, for print.
+    Light,
+    /// Light UI without a plot background; PNG exports of the plot keep
+    /// the background transparent.
+    Transparent,
+}
+
+impl PlotTheme {
+    pub const ALL: [PlotTheme; 3] = [PlotTheme::Dark, PlotTheme::Light, PlotTheme::Transparent];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PlotTheme::Dark => "Dark",
+            PlotTheme::Light => "Light",
+            PlotTheme::Transparent => "Transparent",
+        }
+    }
+
+    /// The egui theme of the UI.
+    pub fn egui_theme(self) -> Theme {
+        match self {
+            PlotTheme::Dark => Theme::Dark,
+            PlotTheme::Light | PlotTheme::Transparent => Theme::Light,
+        }
+    }
+
+    /// Whether lines are drawn on a light background.
+    pub fn is_light(self) -> bool {
+        self != PlotTheme::Dark
+    }
+}
+
+/// Where a requested viewport screenshot goes once it arrives.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScreenshotTarget {
+    /// Save the plot area as a PNG file.
+    File(PathBuf),
+    /// Put the plot area on the system clipboard.
+    Clipboard,
+}
+
 /// The full UI state, independent of rendering.
 pub struct AppState {
+    /// The active tab's dataset and everything tied to it.
+    pub tab: DatasetTab,
+
+    /// Options used when loading files.
+    pub load_options: LoadOptions,
+
+    /// Whether spectra must pass all active column filters or any of them.
+    pub filter_logic: FilterLogic,
+
+    /// Manual colours per colour column and value, applied over the
+    /// generated ones whenever that column is coloured by.
+    pub color_overrides: BTreeMap<String, BTreeMap<MetadataValue, Color32>>,
+
+    /// Interpret the colour column's values as hex colours instead of
+    /// assigning palette colours.
+    pub color_from_hex: bool,
+
+    /// Palette used for categorical colour maps.
+    pub palette: PaletteKind,
+
+    /// Use the colourblind-safe palette for categorical colour maps.
+    pub cvd_safe: bool,
+
+    /// How continuous colour maps place values on the ramp.
+    pub color_scale: ColorScale,
+
+    /// Numeric columns with more unique values than this are treated as
+    /// continuous (colour ramp, range filter) rather than categorical.
+    pub categorical_threshold: usize,
+
+    /// Short-lived confirmation shown next to the status message, with the
+    /// time (`egui::InputState::time`) at which it disappears.
+    pub status_flash: Option<(String, f64)>,
+
+    /// Whether a file loading operation is in progress.
+    pub loading: bool,
+
+    /// Background load in flight, if any.
+    pub pending_load: Option<PendingLoad>,
+
+    /// Asymmetric least squares baseline removed before any other
+    /// preprocessing, if enabled.
+    pub baseline: Option<AlsParams>,
+
+    /// Savitzky–Golay smoothing applied before display, if enabled.
+    pub smoothing: Option<SavGolParams>,
+
+    /// Width of the x bins displayed spectra are averaged into, if enabled.
+    pub binning: Option<f64>,
+
+    /// Wavenumber at which every spectrum is shifted to zero intensity, if
+    /// offset correction is enabled.
+    pub baseline_point: Option<f64>,
+
+    /// Wavenumbers of the linear baseline anchors, in increasing order.
+    pub baseline_anchors: Vec<f64>,
+
+    /// Subtract the linear baseline through the anchors; otherwise it is
+    /// only previewed on the highlighted spectrum.
+    pub subtract_anchors: bool,
+
+    /// Plot wavenumber decreasing left to right (IR/Raman convention).
+    pub reverse_x: bool,
+
+    /// Derivative displayed instead of the spectrum (0 = none, 1 or 2).
+    pub derivative_order: u8,
+
+    /// Per-spectrum normalization applied to the spectra.
+    pub normalization: Normalization,
+
+    /// Plot log10 of the displayed intensities; non-positive points are
+    /// left out.
+    pub log_y: bool,
+
+    /// Only points with x in this range are plotted and exported.
+    pub region: Option<(f64, f64)>,
+
+    /// Mark detected peaks on the highlighted spectrum.
+    pub show_peaks: bool,
+
+    /// Peak detection thresholds.
+    pub peak_params: PeakParams,
+
+    /// Line plot or heatmap in the central panel.
+    pub plot_view: PlotView,
+
+    /// Palette the heatmap's intensity scale is drawn from.
+    pub heatmap_palette: PaletteKind,
+
+    /// Overlay or waterfall layout of the traces.
+    pub plot_mode: PlotMode,
+
+    /// Waterfall offset between successive spectra, as a fraction of the
+    /// largest displayed y range.
+    pub waterfall_spacing: f64,
+
+    /// Width in points and dash pattern of the individual traces. Emphasised
+    /// and outlier traces are drawn wider.
+    pub line_width: f32,
+    pub line_style: LineStyle,
+
+    /// UI theme and plot background.
+    pub plot_theme: PlotTheme,
+
+    /// Darken light line colours so they stay legible on a light theme.
+    pub darken_light_lines: bool,
+
+    /// Draw each colour group's mean as a bold line over the traces.
+    pub show_group_means: bool,
+
+    /// Width in points and dash pattern of the group mean lines.
+    pub mean_line_width: f32,
+    pub mean_line_style: LineStyle,
+
+    /// Shade ±1 standard deviation around each colour group's mean.
+    pub show_std_band: bool,
+
+    /// Draw each colour group as its mean line with ±1σ error bars instead
+    /// of the individual traces.
+    pub error_bars: bool,
+
+    /// Draw an error bar at every n-th point of the common grid.
+    pub error_bar_spacing: usize,
+
+    /// Draw spectra far from their colour group's mean in red.
+    pub highlight_outliers: bool,
+
+    /// A spectrum is an outlier when its RMS distance from the group mean
+    /// exceeds the group's mean distance by this many standard deviations.
+    pub outlier_sigma: f64,
+
+    /// Most spectra drawn as lines; larger selections are drawn as a
+    /// random sample of this many (see [`AppState::render_sample`]).
+    pub render_limit: usize,
+
+    /// Decimals of float metadata values in the side panel, legend and
+    /// tooltips, and whether they are shown in scientific notation.
+    pub value_precision: usize,
+    pub scientific_values: bool,
+
+    /// Fixed `[min, max]` for the x axis; `None` lets it auto-fit.
+    pub x_bounds: Option<[f64; 2]>,
+
+    /// Fixed `[min, max]` for the y axis; `None` lets it auto-fit.
+    pub y_bounds: Option<[f64; 2]>,
+
+    /// Axes (x, y) whose lock was just cleared and should auto-fit again.
+    pub pending_auto_bounds: [bool; 2],
+
+    /// Fit the plot to the displayed spectra on the next frame.
+    pub pending_fit: bool,
+
+    /// Screen area of the plot in the last frame, for image export.
+    pub plot_rect: Option<Rect>,
+
+    /// Dragging on the plot draws a selection box instead of panning.
+    pub box_select: bool,
+
+    /// Plot coordinates where the current selection box drag started.
+    pub box_start: Option<[f64; 2]>,
+
+    /// What to do with the next viewport screenshot.
+    pub pending_screenshot: Option<ScreenshotTarget>,
+
+    /// Size and resolution of high-resolution PNG exports.
+    pub png_size: RenderSize,
+
+    /// Whether the high-resolution PNG export dialog is shown.
+    pub png_dialog_open: bool,
+
+    /// Where to render the plot at `png_size` when it is next drawn.
+    pub pending_render: Option<(PathBuf, RenderSize)>,
+
+    /// Recently opened files, most recent first; persisted across runs.
+    pub recent_files: Vec<PathBuf>,
+
+    /// Open datasets, one per tab. The active tab's contents live in
+    /// `tab`; its slot here stays empty until another tab is activated (see
+    /// [`AppState::switch_tab`]).
+    pub tabs: Vec<DatasetTab>,
+
+    /// Index of the active tab in `tabs`.
+    pub active_tab: usize,
+
+    /// Inactive tab drawn dashed and faded under the active one, for
+    /// before/after comparisons.
+    pub overlay_tab: Option<usize>,
+}
+
+/// A dataset together with everything derived from it or tied to its
+/// spectra and columns. Display and preprocessing settings are shared by
+/// all tabs.
+#[derive(Default)]
+pub struct DatasetTab {
     /// Loaded dataset (None until user loads a file).
     pub dataset: Option<SpectralDataset>,
 
+    /// Path of the file the dataset was loaded from.
+    pub source_path: Option<PathBuf>,
+
+    /// Status / error message shown in the UI.
+    pub status_message: Option<String>,
+
     /// Per-column filter selections.
     pub filters: FilterState,
 
+    /// Columns narrowed to a single value by clicking its swatch: the value
+    /// and the filter it replaced, restored by clicking it again.
+    pub isolated: BTreeMap<String, (MetadataValue, Option<FilterKind>)>,
+
+    /// Derived metrics of every spectrum's raw points, computed once per
+    /// dataset.
+    pub metric_values: BTreeMap<DerivedMetric, Vec<f64>>,
+
+    /// Range filters on derived metrics, combined with the column filters.
+    pub metric_filters: BTreeMap<DerivedMetric, RangeInclusive<f64>>,
+
     /// Indices of spectra passing the current filters (cached).
     pub visible_indices: Vec<usize>,
 
+    /// Incremented whenever `visible_indices` changes, so what is derived
+    /// from them can tell it is stale without comparing them.
+    pub visible_generation: u64,
+
     /// Which metadata column is used for colouring.
     pub color_column: Option<String>,
 
     /// Active colour map.
     pub color_map: Option<ColorMap>,
 
-    /// Status / error message shown in the UI.
-    pub status_message: Option<String>,
+    /// User-defined order of the values of categorical columns, such as
+    /// `low, medium, high`; colours, legends and value lists follow it.
+    /// Columns not listed are shown in sorted order.
+    pub value_orders: BTreeMap<String, Vec<MetadataValue>>,
 
-    /// Whether a file loading operation is in progress.
-    pub loading: bool,
+    /// Colour-column values hidden from the plot through the legend,
+    /// without touching the filters.
+    pub hidden_groups: BTreeSet<MetadataValue>,
+
+    /// Colour-column value whose spectra are highlighted, dimming the rest.
+    pub highlighted_group: Option<MetadataValue>,
+
+    /// Baseline-corrected intensities, computed on demand.
+    pub baseline_cache: BaselineCache,
+
+    /// Summary of the visible spectra for the statistics section.
+    pub selection_stats: SelectionStats,
+
+    /// Smallest and largest raw intensity of each spectrum, computed once
+    /// per dataset for min-max scaling.
+    pub y_ranges: Vec<(f64, f64)>,
+
+    /// Shaded x bands marking diagnostic regions: start, end, colour and
+    /// label.
+    pub regions: Vec<(f64, f64, Color32, String)>,
+
+    /// Draw every plotted spectrum, however many there are.
+    pub render_all: bool,
+
+    /// Plot bounds shown in the last frame.
+    pub view_bounds: Option<ViewBounds>,
+
+    /// Plot bounds to apply on the next frame (e.g. from a recipe).
+    pub pending_view: Option<ViewBounds>,
+
+    /// Spectrum pinned by clicking its line, shown in the detail panel.
+    pub selected_index: Option<usize>,
 
-    /// Whether min-max scaling is applied to the spectra.
-    pub minmax_scaling: bool,
+    /// Spectrum stepped to with the arrow keys, plotted alone while it is
+    /// among the visible ones (see [`AppState::step_focus`]).
+    pub focused_index: Option<usize>,
+
+    /// Spectra with a displayed point inside the last selection box.
+    pub box_selection: BTreeSet<usize>,
+
+    /// Show only the box-selected spectra among the filtered ones.
+    pub isolate_box_selection: bool,
+
+    /// User-defined order of metadata columns in the side panel. Columns
+    /// not listed follow in dataset order.
+    pub column_order: Vec<String>,
+
+    /// Columns hidden from the side panel (their filters still apply).
+    pub hidden_columns: BTreeSet<String>,
+
+    /// Per-column search text narrowing the checkboxes shown for string
+    /// columns. Does not change the selection.
+    pub column_search: BTreeMap<String, String>,
+}
+
+impl DatasetTab {
+    pub fn dataset(&self) -> Option<&SpectralDataset> {
+        self.dataset.as_ref()
+    }
+
+    /// Spectra passing the tab's filters when it was last active.
+    pub fn visible_indices(&self) -> &[usize] {
+        &self.visible_indices
+    }
+
+    pub fn color_column(&self) -> Option<&str> {
+        self.color_column.as_deref()
+    }
+
+    pub fn color_map(&self) -> Option<&ColorMap> {
+        self.color_map.as_ref()
+    }
+
+    /// Baseline-corrected intensities of spectrum `idx`, if baseline
+    /// correction is enabled and the spectrum has been cached.
+    pub fn baseline_corrected(&self, idx: usize) -> Option<&[f64]> {
+        self.baseline_cache.params?;
+        self.baseline_cache.corrected.get(&idx).map(Vec::as_slice)
+    }
+}
+
+/// Number of entries kept in [`AppState::recent_files`].
+pub const MAX_RECENT_FILES: usize = 10;
+
+/// Baseline-corrected y per spectrum index, valid for one set of
+/// [`AlsParams`]. ALS is too slow to recompute every frame.
+#[derive(Debug, Clone, Default)]
+pub struct BaselineCache {
+    params: Option<AlsParams>,
+    corrected: HashMap<usize, Vec<f64>>,
+    /// Range of each corrected spectrum, for min-max scaling.
+    ranges: HashMap<usize, (f64, f64)>,
+}
+
+/// Counts and peak positions of the visible spectra, valid for one
+/// [`DatasetTab::visible_generation`] and colour column. Filters are
+/// re-applied every frame, so this is only recomputed when their result
+/// changes.
+#[derive(Debug, Clone, Default)]
+pub struct SelectionStats {
+    key: Option<(u64, Option<String>)>,
+    pub n_visible: usize,
+    /// Number of visible spectra per colour-column value.
+    pub group_counts: Vec<(MetadataValue, usize)>,
+    /// Mean, min and max wavenumber of the visible spectra's highest point.
+    pub peak_wavenumber: Option<(f64, f64, f64)>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
-            dataset: None,
-            filters: FilterState::default(),
-            visible_indices: Vec::new(),
-            color_column: None,
-            color_map: None,
-            status_message: None,
+            tab: DatasetTab::default(),
+            load_options: LoadOptions::default(),
+            filter_logic: FilterLogic::And,
+            color_overrides: BTreeMap::new(),
+            color_from_hex: false,
+            palette: PaletteKind::default(),
+            cvd_safe: false,
+            color_scale: ColorScale::default(),
+            categorical_threshold: 20,
+            status_flash: None,
             loading: false,
-            minmax_scaling: false,
+            pending_load: None,
+            normalization: Normalization::None,
+            derivative_order: 0,
+            reverse_x: false,
+            baseline: None,
+            smoothing: None,
+            binning: None,
+            baseline_point: None,
+            baseline_anchors: Vec::new(),
+            subtract_anchors: false,
+            log_y: false,
+            region: None,
+            show_peaks: false,
+            peak_params: PeakParams::default(),
+            plot_view: PlotView::Lines,
+            heatmap_palette: PaletteKind::Viridis,
+            plot_mode: PlotMode::Overlay,
+            waterfall_spacing: 0.2,
+            line_width: 1.5,
+            line_style: LineStyle::Solid,
+            plot_theme: PlotTheme::Dark,
+            darken_light_lines: true,
+            show_group_means: false,
+            mean_line_width: 3.5,
+            mean_line_style: LineStyle::Solid,
+            show_std_band: false,
+            error_bars: false,
+            error_bar_spacing: 20,
+            highlight_outliers: false,
+            outlier_sigma: 2.0,
+            render_limit: 5000,
+            value_precision: 4,
+            scientific_values: false,
+            x_bounds: None,
+            y_bounds: None,
+            pending_auto_bounds: [false; 2],
+            pending_fit: false,
+            plot_rect: None,
+            box_select: false,
+            box_start: None,
+            pending_screenshot: None,
+            png_size: RenderSize::default(),
+            png_dialog_open: false,
+            pending_render: None,
+            recent_files: Vec::new(),
+            tabs: vec![DatasetTab::default()],
+            active_tab: 0,
+            overlay_tab: None,
         }
     }
 }
@@ -53,47 +601,752 @@ impl Default for AppState {
 impl AppState {
     /// Ingest a newly loaded dataset, initialise filters and colour.
     pub fn set_dataset(&mut self, dataset: SpectralDataset) {
-        self.filters = init_filter_state(&dataset);
-        self.visible_indices = (0..dataset.len()).collect();
+        self.tab.filters = init_filter_state(&dataset);
+        self.tab.isolated.clear();
+        self.tab.visible_indices = (0..dataset.len()).collect();
+        self.tab.visible_generation += 1;
+
+        // Follow the data's own x direction by default.
+        self.reverse_x = dataset.x_decreasing();
 
         // Default colour column: first metadata column (if any).
-        self.color_column = dataset.column_names.first().cloned();
+        self.tab.color_column = dataset.column_names.first().cloned();
+        self.clear_group_toggles();
+        self.rebuild_color_map(&dataset);
+
+        self.tab.status_message = load_warning(&dataset);
+        self.tab.y_ranges = y_ranges(&dataset);
+        self.tab.metric_values = metric_values(&dataset);
+        self.tab.selection_stats = SelectionStats::default();
+        self.tab.metric_filters.clear();
+        self.tab.selected_index = None;
+        self.tab.focused_index = None;
+        self.tab.box_selection.clear();
+        self.tab.isolate_box_selection = false;
+        self.tab.render_all = false;
+        self.tab.dataset = Some(dataset);
+        self.tab.baseline_cache = BaselineCache::default();
+        self.loading = false;
+    }
+
+    /// Move `path` to the front of the recent files, dropping the oldest
+    /// entries beyond [`MAX_RECENT_FILES`].
+    pub fn add_recent_file(&mut self, path: PathBuf) {
+        self.recent_files.retain(|p| *p != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    /// Sort the points of every spectrum by x, e.g. after loading a file
+    /// with unordered x values.
+    pub fn fix_axis_ordering(&mut self) {
+        if let Some(ds) = &mut self.tab.dataset {
+            ds.sort_by_x();
+            self.tab.metric_values = metric_values(ds);
+            self.tab.selection_stats = SelectionStats::default();
+            self.tab.baseline_cache = BaselineCache::default();
+            self.tab.status_message = None;
+            self.refilter();
+        }
+    }
+
+    /// Remove NaN/infinite values from every spectrum, dropping the points
+    /// or interpolating over them according to `mode`.
+    pub fn clean_invalid(&mut self, mode: CleanMode) {
+        if let Some(ds) = &mut self.tab.dataset {
+            ds.clean_invalid(mode);
+            self.tab.y_ranges = y_ranges(ds);
+            self.tab.metric_values = metric_values(ds);
+            self.tab.selection_stats = SelectionStats::default();
+            self.tab.baseline_cache = BaselineCache::default();
+            self.tab.status_message = None;
+            self.refilter();
+        }
+    }
+
+    /// Drop spectra that exactly duplicate an earlier one. Spectrum indices
+    /// shift, so per-spectrum caches and the selection are reset.
+    pub fn remove_duplicates(&mut self) {
+        if let Some(ds) = &mut self.tab.dataset {
+            let removed = ds.remove_duplicates();
+            log::info!("Removed {removed} duplicate spectra");
+            self.tab.y_ranges = y_ranges(ds);
+            self.tab.metric_values = metric_values(ds);
+            self.tab.selection_stats = SelectionStats::default();
+            self.tab.baseline_cache = BaselineCache::default();
+            self.tab.selected_index = None;
+            self.tab.focused_index = None;
+            self.tab.box_selection.clear();
+            self.tab.isolate_box_selection = false;
+            self.tab.status_message = None;
+            self.refilter();
+        }
+    }
+
+    /// Add the spectra of `other` to the current dataset (or load it if
+    /// there is none), then reset filters and colours as for a new dataset.
+    /// The combination has no single source file to reload.
+    pub fn append_dataset(&mut self, other: SpectralDataset) {
+        let dataset = match self.tab.dataset.take() {
+            Some(mut dataset) => {
+                dataset.append(other);
+                dataset
+            }
+            None => other,
+        };
+        self.set_dataset(dataset);
+        self.tab.source_path = None;
+    }
+
+    /// Replace the dataset with a freshly re-loaded copy of the same file,
+    /// keeping the current filters and colour column where columns match.
+    pub fn reload_dataset(&mut self, dataset: SpectralDataset) {
+        let old_filters = std::mem::take(&mut self.tab.filters);
+        let old_unique = self.tab.dataset.take().map(|ds| ds.unique_values);
+        let old_color_column = self.tab.color_column.take();
+
+        let mut filters = init_filter_state(&dataset);
+        for (col, filter) in filters.iter_mut() {
+            let (Some(prev), Some(prev_all)) = (
+                old_filters.get(col),
+                old_unique.as_ref().and_then(|u| u.get(col)),
+            ) else {
+                continue;
+            };
+            // A fully selected column stays fully selected so new values show up.
+            if !prev.is_active(prev_all) {
+                continue;
+            }
+            match (prev, filter) {
+                (FilterKind::Values(prev), FilterKind::Values(selected)) => {
+                    selected.retain(|v| prev.contains(v));
+                }
+                (range, filter) => *filter = range.clone(),
+            }
+        }
+
+        self.tab.color_column = old_color_column
+            .filter(|c| dataset.column_names.contains(c))
+            .or_else(|| dataset.column_names.first().cloned());
         self.rebuild_color_map(&dataset);
+        self.tab.filters = filters;
+        self.tab.isolated.clear();
 
-        self.dataset = Some(dataset);
-        self.status_message = None;
+        self.tab.status_message = load_warning(&dataset);
+        self.tab.y_ranges = y_ranges(&dataset);
+        self.tab.metric_values = metric_values(&dataset);
+        self.tab.selection_stats = SelectionStats::default();
+        self.tab.selected_index = None;
+        self.tab.focused_index = None;
+        self.tab.box_selection.clear();
+        self.tab.isolate_box_selection = false;
+        self.tab.dataset = Some(dataset);
+        self.tab.baseline_cache = BaselineCache::default();
         self.loading = false;
+        self.refilter();
+    }
+
+    /// Compute the baseline-corrected intensities of visible spectra that
+    /// are not cached yet, discarding the cache if the parameters changed.
+    pub fn update_baseline_cache(&mut self) {
+        update_baseline_cache(
+            &mut self.tab.baseline_cache,
+            self.baseline,
+            self.tab.dataset.as_ref(),
+            &self.tab.visible_indices,
+        );
+        if let Some(tab) = self.overlay_tab.and_then(|i| self.tabs.get_mut(i)) {
+            update_baseline_cache(
+                &mut tab.baseline_cache,
+                self.baseline,
+                tab.dataset.as_ref(),
+                &tab.visible_indices,
+            );
+        }
+    }
+
+    /// Range of the intensities of spectrum `idx` as they reach
+    /// normalization, if known without rescanning them: the raw or
+    /// baseline-corrected range, unless smoothing, a linear baseline or
+    /// offset correction changes the values.
+    pub fn cached_y_range(&self, idx: usize) -> Option<(f64, f64)> {
+        if self.smoothing.is_some() || self.baseline_point.is_some() || self.subtract_anchors {
+            return None;
+        }
+        match self.baseline {
+            Some(_) => self.tab.baseline_cache.ranges.get(&idx).copied(),
+            None => self.tab.y_ranges.get(idx).copied(),
+        }
+    }
+
+    /// Baseline-corrected intensities of spectrum `idx`, if baseline
+    /// correction is enabled and the spectrum has been cached.
+    pub fn baseline_corrected(&self, idx: usize) -> Option<&[f64]> {
+        self.baseline?;
+        self.tab
+            .baseline_cache
+            .corrected
+            .get(&idx)
+            .map(Vec::as_slice)
+    }
+
+    /// Log and show how many visible spectra area normalization leaves
+    /// unchanged because their area over the region is zero or negative.
+    /// A lazily loaded dataset is not checked: its spectra are not in
+    /// memory.
+    pub fn warn_non_positive_areas(&mut self) {
+        let Some(ds) = self.tab.dataset.as_ref().filter(|ds| ds.lazy.is_none()) else {
+            return;
+        };
+        let (lo, hi) = self.region.unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+        let n = self
+            .tab
+            .visible_indices
+            .iter()
+            .zip(ds.spectra_at(&self.tab.visible_indices))
+            .filter(|(&idx, sp)| {
+                let y = self.baseline_corrected(idx).unwrap_or(&sp.y);
+                area_between(&sp.x, y, lo, hi) <= 0.0
+            })
+            .count();
+        if n > 0 {
+            log::warn!("{n} spectra have zero or negative area; not area-normalized");
+            self.tab.status_message = Some(format!(
+                "Warning: {n} spectra have zero or negative area and are shown unnormalized"
+            ));
+        }
+    }
+
+    /// Log and show how many visible spectra are irregularly sampled (see
+    /// [`Spectrum::x_irregular`]) when `transform`, which depends on the x
+    /// spacing, is switched on.
+    /// Skipped for a lazily loaded dataset, like
+    /// [`AppState::warn_non_positive_areas`].
+    pub fn warn_irregular_sampling(&mut self, transform: &str) {
+        let Some(ds) = self.tab.dataset.as_ref().filter(|ds| ds.lazy.is_none()) else {
+            return;
+        };
+        let n = ds
+            .spectra_at(&self.tab.visible_indices)
+            .iter()
+            .filter(|sp| sp.x_irregular())
+            .count();
+        if n > 0 {
+            log::warn!("{n} spectra are irregularly sampled; {transform} follows their spacing");
+            self.tab.status_message = Some(format!(
+                "Warning: {n} spectra are irregularly sampled, so {transform} varies along x"
+            ));
+        }
+    }
+
+    /// File name of the loaded dataset, for display.
+    pub fn source_name(&self) -> Option<String> {
+        self.tab
+            .source_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+    }
+
+    /// Contents of tab `i`, whether it is the active one or not.
+    fn tab_at(&self, i: usize) -> &DatasetTab {
+        if i == self.active_tab {
+            &self.tab
+        } else {
+            &self.tabs[i]
+        }
+    }
+
+    /// File tab `i` was loaded from, if it came from a single file.
+    pub fn tab_path(&self, i: usize) -> Option<&PathBuf> {
+        self.tab_at(i).source_path.as_ref()
+    }
+
+    /// Label of tab `i`: its file name, or what it holds if it has none.
+    pub fn tab_name(&self, i: usize) -> String {
+        let loaded = self.tab_at(i).dataset.is_some();
+        match self.tab_path(i).and_then(|p| p.file_name()) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None if loaded => "Combined files".to_string(),
+            None => "Empty".to_string(),
+        }
+    }
+
+    /// Add an empty tab after the others and make it active; the next
+    /// dataset installed goes there.
+    pub fn new_tab(&mut self) {
+        self.store_active_tab();
+        self.tabs.push(DatasetTab::default());
+        self.active_tab = self.tabs.len() - 1;
+    }
+
+    /// Make tab `i` the active one, keeping the current tab's filters and
+    /// colouring for when it is activated again. Activating the overlay tab
+    /// swaps the two roles.
+    pub fn switch_tab(&mut self, i: usize) {
+        if i == self.active_tab || i >= self.tabs.len() {
+            return;
+        }
+        if self.overlay_tab == Some(i) {
+            self.overlay_tab = Some(self.active_tab);
+        }
+        self.store_active_tab();
+        self.load_tab(i);
+    }
+
+    /// Close tab `i`, activating its neighbour if it was active. The last
+    /// tab is emptied rather than removed.
+    pub fn close_tab(&mut self, i: usize) {
+        if i >= self.tabs.len() {
+            return;
+        }
+        self.overlay_tab = match self.overlay_tab {
+            Some(overlay) if overlay == i => None,
+            Some(overlay) if overlay > i => Some(overlay - 1),
+            overlay => overlay,
+        };
+        if i != self.active_tab {
+            self.tabs.remove(i);
+            if i < self.active_tab {
+                self.active_tab -= 1;
+            }
+            return;
+        }
+        self.tab = DatasetTab::default();
+        if self.tabs.len() > 1 {
+            self.tabs.remove(i);
+            self.load_tab(i.min(self.tabs.len() - 1));
+        }
+    }
+
+    /// Move the active tab's contents into its slot, leaving `tab` empty.
+    fn store_active_tab(&mut self) {
+        self.tabs[self.active_tab] = std::mem::take(&mut self.tab);
+    }
+
+    /// Move tab `i`'s contents into (the empty) `tab` and bring them up to
+    /// date with settings changed while it was inactive.
+    fn load_tab(&mut self, i: usize) {
+        self.active_tab = i;
+        if self.overlay_tab == Some(i) {
+            self.overlay_tab = None;
+        }
+        self.tab = std::mem::take(&mut self.tabs[i]);
+
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+        self.refilter();
+        self.tab.pending_view = self.tab.view_bounds;
+        self.pending_fit = self.tab.view_bounds.is_none();
+    }
+
+    /// The tab drawn under the active one, if any.
+    pub fn overlay(&self) -> Option<&DatasetTab> {
+        self.overlay_tab.and_then(|i| self.tabs.get(i))
     }
 
     /// Rebuild the colour map from the current `color_column`.
     pub fn rebuild_color_map(&mut self, dataset: &SpectralDataset) {
-        self.color_map = self.color_column.as_ref().and_then(|col| {
-            dataset
-                .unique_values
-                .get(col)
-                .map(|vals| ColorMap::new(col, vals))
+        let from_hex = self.color_from_hex;
+        let threshold = self.categorical_threshold;
+        let palette = self.palette;
+        let cvd_safe = self.cvd_safe;
+        let scale = self.color_scale;
+        self.tab.color_map = self.tab.color_column.as_ref().and_then(|col| {
+            dataset.unique_values.get(col).map(|vals| {
+                let order = self.value_order(col);
+                let mut cm = if from_hex {
+                    ColorMap::from_hex(col, vals, order)
+                } else if dataset.is_continuous(col, threshold) {
+                    match scale {
+                        ColorScale::Linear => ColorMap::continuous(col, vals),
+                        ColorScale::Quantile => {
+                            let values = dataset
+                                .spectra
+                                .iter()
+                                .filter_map(|sp| sp.metadata.get(col)?.as_f64())
+                                .collect();
+                            ColorMap::quantile(col, vals, values)
+                        }
+                    }
+                } else if cvd_safe {
+                    ColorMap::cvd_safe(col, vals, order)
+                } else {
+                    ColorMap::new(col, vals, palette, order)
+                };
+                for (value, color) in self.color_overrides.get(col).into_iter().flatten() {
+                    cm.set_override(value.clone(), *color);
+                }
+                cm
+            })
         });
     }
 
+    /// Colour `value` of the colour column with `color` from now on.
+    pub fn set_color_override(&mut self, value: MetadataValue, color: Color32) {
+        let Some(col) = &self.tab.color_column else {
+            return;
+        };
+        self.color_overrides
+            .entry(col.clone())
+            .or_default()
+            .insert(value.clone(), color);
+        if let Some(cm) = &mut self.tab.color_map {
+            cm.set_override(value, color);
+        }
+    }
+
+    /// Whether the colour column has any manual colours.
+    pub fn has_color_overrides(&self) -> bool {
+        self.tab
+            .color_column
+            .as_ref()
+            .is_some_and(|col| self.color_overrides.contains_key(col))
+    }
+
+    /// Forget the manual colours of the colour column.
+    pub fn reset_color_overrides(&mut self) {
+        if let Some(col) = &self.tab.color_column {
+            self.color_overrides.remove(col);
+        }
+        if let Some(cm) = &mut self.tab.color_map {
+            cm.clear_overrides();
+        }
+    }
+
+    /// Change the categorical/continuous threshold and rebuild the colour map.
+    pub fn set_categorical_threshold(&mut self, threshold: usize) {
+        self.categorical_threshold = threshold;
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+    }
+
+    /// Change the categorical palette and rebuild the colour map.
+    pub fn set_palette(&mut self, palette: PaletteKind) {
+        self.palette = palette;
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+    }
+
+    /// Change how continuous colour maps scale values and rebuild the
+    /// colour map.
+    pub fn set_color_scale(&mut self, scale: ColorScale) {
+        self.color_scale = scale;
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+    }
+
+    /// Toggle the colourblind-safe palette and rebuild the colour map.
+    pub fn set_cvd_safe(&mut self, cvd_safe: bool) {
+        self.cvd_safe = cvd_safe;
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+    }
+
+    /// Switch between palette colours and hex colours from the column.
+    pub fn set_color_from_hex(&mut self, from_hex: bool) {
+        self.color_from_hex = from_hex;
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+    }
+
     /// Recompute `visible_indices` after filter change.
     pub fn refilter(&mut self) {
-        if let Some(ds) = &self.dataset {
-            self.visible_indices = filtered_indices(ds, &self.filters);
+        if let Some(ds) = &self.tab.dataset {
+            let metrics: Vec<MetricFilter> = self
+                .tab
+                .metric_filters
+                .iter()
+                .map(|(metric, range)| MetricFilter {
+                    values: self
+                        .tab
+                        .metric_values
+                        .get(metric)
+                        .map_or(&[], Vec::as_slice),
+                    range,
+                })
+                .collect();
+            let mut visible = filtered_indices(ds, &self.tab.filters, self.filter_logic, &metrics);
+            if self.tab.isolate_box_selection {
+                let selection = &self.tab.box_selection;
+                visible.retain(|i| selection.contains(i));
+            }
+            if visible != self.tab.visible_indices {
+                self.tab.visible_indices = visible;
+                self.tab.visible_generation += 1;
+            }
+        }
+    }
+
+    /// `value` as text, with floats formatted per the display settings.
+    pub fn format_value(&self, value: &MetadataValue) -> String {
+        value.format_value(self.value_precision, self.scientific_values)
+    }
+
+    /// Replace the box selection. An empty selection clears it, so an
+    /// isolated selection never leaves the plot blank.
+    pub fn set_box_selection(&mut self, indices: BTreeSet<usize>) {
+        if indices.is_empty() {
+            self.clear_box_selection();
+            return;
+        }
+        self.tab.box_selection = indices;
+        if self.tab.isolate_box_selection {
+            self.refilter();
         }
     }
 
+    /// Show only the box-selected spectra, or every filtered one again.
+    pub fn set_isolate_box_selection(&mut self, isolate: bool) {
+        self.tab.isolate_box_selection = isolate;
+        self.refilter();
+    }
+
+    /// Forget the box selection.
+    pub fn clear_box_selection(&mut self) {
+        self.tab.box_selection.clear();
+        if self.tab.isolate_box_selection {
+            self.set_isolate_box_selection(false);
+        }
+    }
+
+    /// Statistics of the visible spectra, recomputed if the visible
+    /// spectra or the colour column changed.
+    pub fn selection_stats(&mut self) -> &SelectionStats {
+        let generation = self.tab.visible_generation;
+        let current = self
+            .tab
+            .selection_stats
+            .key
+            .as_ref()
+            .is_some_and(|(g, col)| *g == generation && *col == self.tab.color_column);
+        if !current {
+            let key = (generation, self.tab.color_column.clone());
+            if let Some(ds) = &self.tab.dataset {
+                let groups = group_indices(ds, &self.tab.visible_indices, key.1.as_deref());
+                let peaks: Vec<f64> = self
+                    .tab
+                    .metric_values
+                    .get(&DerivedMetric::PeakWavenumber)
+                    .map(|values| {
+                        self.tab
+                            .visible_indices
+                            .iter()
+                            .filter_map(|&i| values.get(i).copied())
+                            .filter(|v| v.is_finite())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let peak_wavenumber = (!peaks.is_empty()).then(|| {
+                    let mean = peaks.iter().sum::<f64>() / peaks.len() as f64;
+                    let min = peaks.iter().copied().fold(f64::INFINITY, f64::min);
+                    let max = peaks.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                    (mean, min, max)
+                });
+                let mut group_counts: Vec<(MetadataValue, usize)> =
+                    groups.into_iter().map(|(v, idx)| (v, idx.len())).collect();
+                let order = self
+                    .tab
+                    .color_column
+                    .as_deref()
+                    .map_or(&[][..], |c| self.value_order(c));
+                group_counts.sort_by(|a, b| a.0.custom_cmp(&b.0, order));
+                self.tab.selection_stats = SelectionStats {
+                    key: None,
+                    n_visible: self.tab.visible_indices.len(),
+                    group_counts,
+                    peak_wavenumber,
+                };
+            }
+            self.tab.selection_stats.key = Some(key);
+        }
+        &self.tab.selection_stats
+    }
+
     /// Set colour column and rebuild the map.
     pub fn set_color_column(&mut self, col: String) {
-        self.color_column = Some(col);
-        if let Some(ds) = &self.dataset {
+        if self.tab.color_column.as_ref() != Some(&col) {
+            self.clear_group_toggles();
+        }
+        self.tab.color_column = Some(col);
+        if let Some(ds) = &self.tab.dataset {
             let ds_clone = ds.clone();
             self.rebuild_color_map(&ds_clone);
         }
     }
 
-    /// Toggle a single metadata value in a column's filter.
+    /// Colour group of spectrum `idx`: its colour-column value, `Null` if it
+    /// has none.
+    pub fn color_group(&self, idx: usize) -> MetadataValue {
+        self.tab
+            .color_column
+            .as_ref()
+            .zip(self.tab.dataset.as_ref())
+            .and_then(|(col, ds)| ds.spectra[idx].metadata.get(col))
+            .cloned()
+            .unwrap_or(MetadataValue::Null)
+    }
+
+    /// Visible spectra outside the groups hidden in the legend, or only the
+    /// focused spectrum if it is visible.
+    pub fn plotted_indices(&self) -> Vec<usize> {
+        if let Some((pos, _)) = self.focus_position() {
+            return vec![self.tab.visible_indices[pos]];
+        }
+        if self.tab.hidden_groups.is_empty() {
+            return self.tab.visible_indices.clone();
+        }
+        self.tab
+            .visible_indices
+            .iter()
+            .copied()
+            .filter(|&idx| !self.tab.hidden_groups.contains(&self.color_group(idx)))
+            .collect()
+    }
+
+    /// Position of the focused spectrum among the visible ones, and how
+    /// many are visible; `None` if there is no focus or it is filtered out.
+    pub fn focus_position(&self) -> Option<(usize, usize)> {
+        let idx = self.tab.focused_index?;
+        let pos = self.tab.visible_indices.iter().position(|&i| i == idx)?;
+        Some((pos, self.tab.visible_indices.len()))
+    }
+
+    /// Move the focus `step` places through the visible spectra, wrapping
+    /// around at the ends, and show its metadata in the detail panel.
+    /// Without a visible focus, start from the first (or, stepping back,
+    /// the last) visible spectrum.
+    pub fn step_focus(&mut self, step: isize) {
+        let n = self.tab.visible_indices.len();
+        if n == 0 {
+            self.tab.focused_index = None;
+            return;
+        }
+        let pos = match self.focus_position() {
+            Some((pos, _)) => (pos as isize + step).rem_euclid(n as isize) as usize,
+            None if step < 0 => n - 1,
+            None => 0,
+        };
+        self.tab.focused_index = Some(self.tab.visible_indices[pos]);
+        self.tab.selected_index = self.tab.focused_index;
+    }
+
+    /// Stop stepping through spectra, plotting all of them again.
+    pub fn clear_focus(&mut self) {
+        self.tab.focused_index = None;
+    }
+
+    /// The plotted spectra, or a uniform random sample of `limit` of them
+    /// if there are more. Spectra are ranked by a seeded hash of their
+    /// index, so the sample is the same every frame and a spectrum keeps
+    /// its place in it as the filters change.
+    pub fn render_sample(&self, limit: usize) -> Vec<usize> {
+        sample_indices(self.plotted_indices(), limit)
+    }
+
+    /// The spectra drawn as lines: all plotted ones, or a sample of
+    /// [`AppState::line_limit`] of them.
+    pub fn rendered_indices(&self) -> Vec<usize> {
+        match self.tab.dataset.as_ref().and_then(|ds| self.line_limit(ds)) {
+            Some(limit) => self.render_sample(limit),
+            None => self.plotted_indices(),
+        }
+    }
+
+    /// Most spectra of `dataset` drawn as lines, `None` for all of them:
+    /// [`AppState::render_limit`] unless told to render all. A lazy dataset
+    /// is capped at its cache size, beyond which every frame would re-read
+    /// the file.
+    pub fn line_limit(&self, dataset: &SpectralDataset) -> Option<usize> {
+        if dataset.lazy.is_some() {
+            Some(self.render_limit.min(LAZY_CACHE_SPECTRA))
+        } else {
+            (!self.tab.render_all).then_some(self.render_limit)
+        }
+    }
+
+    /// Hide a colour group from the plot, or show it again.
+    pub fn toggle_group_hidden(&mut self, value: &MetadataValue) {
+        if !self.tab.hidden_groups.remove(value) {
+            self.tab.hidden_groups.insert(value.clone());
+        }
+    }
+
+    /// Highlight a colour group, or stop highlighting it.
+    pub fn toggle_group_highlight(&mut self, value: &MetadataValue) {
+        self.tab.highlighted_group = match &self.tab.highlighted_group {
+            Some(current) if current == value => None,
+            _ => Some(value.clone()),
+        };
+    }
+
+    /// Forget legend visibility and highlighting, e.g. when the colour
+    /// column changes.
+    fn clear_group_toggles(&mut self) {
+        self.tab.hidden_groups.clear();
+        self.tab.highlighted_group = None;
+    }
+
+    /// Change the type of column `col`, resetting its filter and, if it is
+    /// the colour column, the colours. Values that cannot be converted
+    /// become null, with a warning.
+    pub fn recast_column(&mut self, col: &str, to: MetadataKind) {
+        let Some(ds) = &mut self.tab.dataset else {
+            return;
+        };
+        let failed = ds.recast_column(col, to);
+        let all_vals = ds.unique_values.get(col).cloned().unwrap_or_default();
+        self.tab
+            .filters
+            .insert(col.to_string(), FilterKind::Values(all_vals));
+        self.tab.isolated.remove(col);
+        self.color_overrides.remove(col);
+        self.tab.value_orders.remove(col);
+        if self.tab.color_column.as_deref() == Some(col) {
+            self.clear_group_toggles();
+            if let Some(ds) = self.tab.dataset.take() {
+                self.rebuild_color_map(&ds);
+                self.tab.dataset = Some(ds);
+            }
+        }
+        self.refilter();
+
+        self.tab.status_message = (failed > 0).then(|| {
+            log::warn!("{failed} values of '{col}' are not valid as {}", to.label());
+            format!(
+                "Warning: {failed} values of '{col}' could not be read as {} and are now null",
+                to.label()
+            )
+        });
+    }
+
+    /// Toggle a single metadata value in a column's filter. A range filter
+    /// is first turned into the set of values it currently selects.
     pub fn toggle_filter_value(&mut self, column: &str, value: &MetadataValue) {
-        let selected = self.filters.entry(column.to_string()).or_default();
+        let all_vals = self
+            .tab
+            .dataset
+            .as_ref()
+            .and_then(|ds| ds.unique_values.get(column));
+        let filter = self.tab.filters.entry(column.to_string()).or_default();
+        if let Some(all_vals) = all_vals.filter(|_| !matches!(filter, FilterKind::Values(_))) {
+            *filter = FilterKind::Values(filter.selected_values(all_vals));
+        }
+        let FilterKind::Values(selected) = filter else {
+            return;
+        };
         if selected.contains(value) {
             selected.remove(value);
         } else {
@@ -102,19 +1355,427 @@ impl AppState {
         self.refilter();
     }
 
+    /// Select only `value` in a column, remembering the previous selection.
+    /// Clicking the isolated value again restores it; isolating another
+    /// value instead keeps the original selection to return to. Once the
+    /// selection has been changed by other means, isolating starts afresh.
+    pub fn isolate_filter_value(&mut self, column: &str, value: &MetadataValue) {
+        let only = |v: &MetadataValue| FilterKind::Values(BTreeSet::from([v.clone()]));
+        let current = self.tab.filters.get(column);
+        let intact = self
+            .tab
+            .isolated
+            .get(column)
+            .filter(|(isolated, _)| current == Some(&only(isolated)))
+            .cloned();
+        let prior = match intact {
+            Some((isolated, prior)) if isolated == *value => {
+                self.tab.isolated.remove(column);
+                match prior {
+                    Some(filter) => self.tab.filters.insert(column.to_string(), filter),
+                    None => self.tab.filters.remove(column),
+                };
+                self.refilter();
+                return;
+            }
+            Some((_, prior)) => prior,
+            None => current.cloned(),
+        };
+        self.tab
+            .isolated
+            .insert(column.to_string(), (value.clone(), prior));
+        self.tab.filters.insert(column.to_string(), only(value));
+        self.refilter();
+    }
+
     /// Select all values in a column.
     pub fn select_all(&mut self, column: &str) {
-        if let Some(ds) = &self.dataset {
+        if let Some(ds) = &self.tab.dataset {
             if let Some(all_vals) = ds.unique_values.get(column) {
-                self.filters.insert(column.to_string(), all_vals.clone());
+                self.tab
+                    .filters
+                    .insert(column.to_string(), FilterKind::Values(all_vals.clone()));
                 self.refilter();
             }
         }
     }
 
+    /// Select exactly the values of a column that are currently deselected.
+    /// A range filter is inverted as the set of values it selects.
+    pub fn invert_filter(&mut self, column: &str) {
+        let Some(all_vals) = self
+            .tab
+            .dataset
+            .as_ref()
+            .and_then(|ds| ds.unique_values.get(column))
+        else {
+            return;
+        };
+        let selected = self
+            .tab
+            .filters
+            .get(column)
+            .map(|f| f.selected_values(all_vals))
+            .unwrap_or_else(|| all_vals.clone());
+        let inverted = all_vals.difference(&selected).cloned().collect();
+        self.tab
+            .filters
+            .insert(column.to_string(), FilterKind::Values(inverted));
+        self.refilter();
+    }
+
+    /// Apply saved column filters to the current dataset, matching on column
+    /// name and value. Columns and values not in the data are skipped with a
+    /// warning; returns how many were skipped.
+    pub fn restore_filters(&mut self, saved: BTreeMap<String, FilterKind>) -> usize {
+        let Some(ds) = &self.tab.dataset else {
+            return 0;
+        };
+        let mut skipped = 0;
+        for (col, filter) in saved {
+            let Some(all_vals) = ds.unique_values.get(&col) else {
+                log::warn!("Saved filter column '{col}' not found in data; skipped");
+                skipped += 1;
+                continue;
+            };
+            let FilterKind::Values(values) = filter else {
+                self.tab.filters.insert(col, filter);
+                continue;
+            };
+            let mut selected = BTreeSet::new();
+            for v in values {
+                if all_vals.contains(&v) {
+                    selected.insert(v);
+                } else {
+                    log::warn!("Saved filter value '{v}' not found in column '{col}'; skipped");
+                    skipped += 1;
+                }
+            }
+            self.tab.filters.insert(col, FilterKind::Values(selected));
+        }
+        self.refilter();
+        skipped
+    }
+
+    /// Colour by a saved column if the dataset has it; otherwise log a
+    /// warning and return `false`.
+    pub fn restore_color_column(&mut self, column: String) -> bool {
+        let known = self
+            .tab
+            .dataset
+            .as_ref()
+            .is_some_and(|ds| ds.column_names.contains(&column));
+        if known {
+            self.set_color_column(column);
+        } else {
+            log::warn!("Saved colour column '{column}' not found in data; skipped");
+        }
+        known
+    }
+
+    /// Metadata columns in side-panel order: the user's order first, then
+    /// any remaining dataset columns.
+    pub fn ordered_columns(&self) -> Vec<String> {
+        let Some(ds) = &self.tab.dataset else {
+            return Vec::new();
+        };
+        let mut ordered: Vec<String> = self
+            .tab
+            .column_order
+            .iter()
+            .filter(|c| ds.column_names.contains(c))
+            .cloned()
+            .collect();
+        for col in &ds.column_names {
+            if !ordered.contains(col) {
+                ordered.push(col.clone());
+            }
+        }
+        ordered
+    }
+
+    /// Move `column` to just before `before` in the side-panel order.
+    pub fn move_column(&mut self, column: &str, before: &str) {
+        if column == before {
+            return;
+        }
+        let mut order = self.ordered_columns();
+        order.retain(|c| c != column);
+        let pos = order
+            .iter()
+            .position(|c| c == before)
+            .unwrap_or(order.len());
+        order.insert(pos, column.to_string());
+        self.tab.column_order = order;
+    }
+
+    /// The user's order of the values of `column`; empty if none.
+    pub fn value_order(&self, column: &str) -> &[MetadataValue] {
+        self.tab.value_orders.get(column).map_or(&[], Vec::as_slice)
+    }
+
+    /// Move `value` of `column` to just before `before` in the column's
+    /// value order, rebuilding the colour map if it is the colour column.
+    pub fn move_value(&mut self, column: &str, value: &MetadataValue, before: &MetadataValue) {
+        if value == before {
+            return;
+        }
+        let Some(values) = self
+            .tab
+            .dataset
+            .as_ref()
+            .and_then(|ds| ds.unique_values.get(column))
+        else {
+            return;
+        };
+        let mut order: Vec<MetadataValue> = values.iter().cloned().collect();
+        order.sort_by(|a, b| a.custom_cmp(b, self.value_order(column)));
+        order.retain(|v| v != value);
+        let pos = order
+            .iter()
+            .position(|v| v == before)
+            .unwrap_or(order.len());
+        order.insert(pos, value.clone());
+        self.tab.value_orders.insert(column.to_string(), order);
+        self.value_order_changed(column);
+    }
+
+    /// Return `column`'s values to sorted order.
+    pub fn reset_value_order(&mut self, column: &str) {
+        if self.tab.value_orders.remove(column).is_some() {
+            self.value_order_changed(column);
+        }
+    }
+
+    /// Recolour and re-sort the group counts after the value order of
+    /// `column` changed, if it is the colour column.
+    fn value_order_changed(&mut self, column: &str) {
+        if self.tab.color_column.as_deref() != Some(column) {
+            return;
+        }
+        self.tab.selection_stats = SelectionStats::default();
+        if let Some(ds) = self.tab.dataset.take() {
+            self.rebuild_color_map(&ds);
+            self.tab.dataset = Some(ds);
+        }
+    }
+
+    /// Hide or show a column in the side panel.
+    pub fn set_column_hidden(&mut self, column: &str, hidden: bool) {
+        if hidden {
+            self.tab.hidden_columns.insert(column.to_string());
+        } else {
+            self.tab.hidden_columns.remove(column);
+        }
+    }
+
+    /// Flip the x axis direction, keeping the visible wavenumber range.
+    pub fn set_reverse_x(&mut self, reverse_x: bool) {
+        self.reverse_x = reverse_x;
+        self.tab.pending_view = self.tab.view_bounds;
+    }
+
+    /// Add a linear baseline anchor at wavenumber `x`, keeping the anchors
+    /// sorted.
+    pub fn add_baseline_anchor(&mut self, x: f64) {
+        let i = self.baseline_anchors.partition_point(|&a| a < x);
+        self.baseline_anchors.insert(i, x);
+    }
+
+    /// Zoom the plot out to fit the displayed spectra; locked axes keep
+    /// their range.
+    pub fn reset_view(&mut self) {
+        self.pending_fit = true;
+    }
+
+    /// Lock (`Some`) or unlock (`None`) the x axis range.
+    pub fn set_x_bounds(&mut self, bounds: Option<[f64; 2]>) {
+        if bounds.is_none() && self.x_bounds.is_some() {
+            self.pending_auto_bounds[0] = true;
+        }
+        self.x_bounds = bounds;
+    }
+
+    /// Lock (`Some`) or unlock (`None`) the y axis range.
+    pub fn set_y_bounds(&mut self, bounds: Option<[f64; 2]>) {
+        if bounds.is_none() && self.y_bounds.is_some() {
+            self.pending_auto_bounds[1] = true;
+        }
+        self.y_bounds = bounds;
+    }
+
+    /// Select spectra whose numeric value in `column` lies within `[lo, hi]`.
+    pub fn set_range_filter(&mut self, column: &str, lo: f64, hi: f64) {
+        self.tab
+            .filters
+            .insert(column.to_string(), FilterKind::NumericRange(lo..=hi));
+        self.refilter();
+    }
+
+    /// Select spectra whose numeric value in `column` satisfies `op value`.
+    pub fn set_comparison_filter(&mut self, column: &str, op: CompareOp, value: f64) {
+        self.tab
+            .filters
+            .insert(column.to_string(), FilterKind::Comparison { op, value });
+        self.refilter();
+    }
+
+    /// Select spectra whose date in `column` falls between the days `start`
+    /// and `end` (`YYYY-MM-DD`), inclusive.
+    pub fn set_date_range_filter(&mut self, column: &str, start: String, end: String) {
+        self.tab
+            .filters
+            .insert(column.to_string(), FilterKind::DateRange(start..=end));
+        self.refilter();
+    }
+
+    /// Finite range of `metric` over the dataset, if any spectrum has it.
+    pub fn metric_range(&self, metric: DerivedMetric) -> Option<(f64, f64)> {
+        let values = self.tab.metric_values.get(&metric)?;
+        let finite = values.iter().copied().filter(|v| v.is_finite());
+        let lo = finite.clone().min_by(f64::total_cmp)?;
+        let hi = finite.max_by(f64::total_cmp)?;
+        Some((lo, hi))
+    }
+
+    /// Show only spectra whose `metric` lies within `[lo, hi]`.
+    pub fn set_metric_filter(&mut self, metric: DerivedMetric, lo: f64, hi: f64) {
+        self.tab.metric_filters.insert(metric, lo..=hi);
+        self.refilter();
+    }
+
+    /// Stop filtering on `metric`.
+    pub fn remove_metric_filter(&mut self, metric: DerivedMetric) {
+        self.tab.metric_filters.remove(&metric);
+        self.refilter();
+    }
+
     /// Deselect all values in a column.
     pub fn select_none(&mut self, column: &str) {
-        self.filters.insert(column.to_string(), BTreeSet::new());
+        self.tab
+            .filters
+            .insert(column.to_string(), FilterKind::Values(BTreeSet::new()));
         self.refilter();
     }
 }
+
+/// Reset `cache` if the baseline parameters changed, then correct the
+/// spectra at `indices` that it does not hold yet.
+fn update_baseline_cache(
+    cache: &mut BaselineCache,
+    params: Option<AlsParams>,
+    dataset: Option<&SpectralDataset>,
+    indices: &[usize],
+) {
+    if cache.params != params {
+        *cache = BaselineCache {
+            params,
+            ..BaselineCache::default()
+        };
+    }
+    let (Some(params), Some(ds)) = (params, dataset) else {
+        return;
+    };
+    let missing: Vec<usize> = indices
+        .iter()
+        .copied()
+        .filter(|idx| !cache.corrected.contains_key(idx))
+        .collect();
+    for (idx, sp) in missing.iter().copied().zip(ds.spectra_at(&missing)) {
+        let corrected = als_correct(&sp.y, params);
+        cache.ranges.insert(idx, y_range(&corrected));
+        cache.corrected.insert(idx, corrected);
+    }
+}
+
+/// `indices`, or the `limit` of them ranked first by [`sample_rank`] if
+/// there are more.
+pub fn sample_indices(mut indices: Vec<usize>, limit: usize) -> Vec<usize> {
+    if indices.len() <= limit {
+        return indices;
+    }
+    let mut ranks: Vec<u64> = indices.iter().map(|&idx| sample_rank(idx)).collect();
+    let cutoff = *ranks.select_nth_unstable(limit).1;
+    indices.retain(|&idx| sample_rank(idx) < cutoff);
+    indices
+}
+
+/// Seed of the sample drawn when there are too many spectra to render.
+const SAMPLE_SEED: u64 = 0x5EED_5BEC_7A00_0001;
+
+/// Pseudo-random rank of spectrum `idx` when sampling (SplitMix64). Distinct
+/// indices get distinct ranks.
+fn sample_rank(idx: usize) -> u64 {
+    let mut z = (idx as u64 ^ SAMPLE_SEED).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Raw intensity range of every spectrum in `dataset`; none for a lazily
+/// loaded one, whose spectra are not in memory to scan.
+fn y_ranges(dataset: &SpectralDataset) -> Vec<(f64, f64)> {
+    if dataset.lazy.is_some() {
+        return Vec::new();
+    }
+    dataset.spectra.iter().map(|sp| y_range(&sp.y)).collect()
+}
+
+/// Every derived metric of every spectrum in `dataset`; none for a lazily
+/// loaded one, so metric filters are not offered.
+fn metric_values(dataset: &SpectralDataset) -> BTreeMap<DerivedMetric, Vec<f64>> {
+    if dataset.lazy.is_some() {
+        return BTreeMap::new();
+    }
+    DerivedMetric::ALL
+        .into_iter()
+        .map(|metric| {
+            let values = dataset
+                .spectra
+                .iter()
+                .map(|sp| metric.compute(sp))
+                .collect();
+            (metric, values)
+        })
+        .collect()
+}
+
+/// Status message warning about rows skipped at load and spectra that are
+/// out of order, invalid or duplicated.
+fn load_warning(dataset: &SpectralDataset) -> Option<String> {
+    let mut warnings = Vec::new();
+    let skipped = dataset.n_skipped_rows;
+    if skipped > 0 {
+        warnings.push(format!("{skipped} rows skipped (null or malformed x/y)"));
+    }
+    match dataset.load_errors.as_slice() {
+        [] => {}
+        [error] => warnings.push(error.clone()),
+        [first, rest @ ..] => warnings.push(format!("{first} (and {} more)", rest.len())),
+    }
+    let n = dataset.n_unordered_x;
+    if n > 0 {
+        log::warn!("{n} spectra have non-monotonic x values");
+        warnings.push(format!(
+            "{n} spectra have unordered x values (Preprocess → Fix axis ordering)"
+        ));
+    }
+    let n = dataset.n_invalid;
+    if n > 0 {
+        log::warn!("{n} spectra contain NaN or infinite values");
+        warnings.push(format!(
+            "{n} spectra contain NaN/Inf values (Preprocess → Clean invalid values)"
+        ));
+    }
+    let n = dataset.n_duplicates;
+    if n > 0 {
+        log::warn!("{n} spectra duplicate an earlier one");
+        warnings.push(format!(
+            "{n} duplicate spectra (Preprocess → Remove duplicates)"
+        ));
+    }
+    if warnings.is_empty() {
+        return None;
+    }
+    Some(format!("Warning: {}", warnings.join("; ")))
+}
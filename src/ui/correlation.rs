@@ -0,0 +1,171 @@
+use eframe::egui::{self, Color32, ColorImage, RichText, TextureHandle, TextureOptions, Ui};
+
+use crate::data::analysis::correlation_matrix;
+use crate::data::group::common_grid;
+use crate::data::model::Spectrum;
+use crate::state::AppState;
+use crate::ui::heatmap::{
+    COLOR_BAR_SPACE, SCALE_STEPS, color_bar, heatmap_key, ordered_rows, scale_color,
+};
+use crate::ui::plot::display_spectrum;
+
+// ---------------------------------------------------------------------------
+// Correlation matrix view (central panel)
+// ---------------------------------------------------------------------------
+
+/// Most spectra compared; larger selections are sampled down, since the
+/// matrix costs O(n² · grid points).
+const MAX_SPECTRA: usize = 500;
+
+/// Pairwise Pearson correlation of the plotted spectra as a square image,
+/// rows and columns ordered by the colour column. Outliers show up as rows
+/// that correlate poorly with the rest.
+///
+/// The image is only rebuilt when the plotted spectra or the display
+/// settings change.
+#[derive(Default)]
+pub struct CorrelationView {
+    /// Hash of the inputs the current image was built from.
+    key: u64,
+    image: Option<CorrelationImage>,
+}
+
+/// A built correlation image and what its pixels stand for.
+struct CorrelationImage {
+    texture: TextureHandle,
+    /// Spectrum index of each row (and column), in order.
+    rows: Vec<usize>,
+    /// Correlations, row-major; NaN where a pair has no overlap.
+    values: Vec<f64>,
+    /// Correlation at the two ends of the colour scale.
+    range: (f64, f64),
+    /// Number of plotted spectra before sampling down.
+    n_plotted: usize,
+}
+
+impl CorrelationView {
+    /// Render the correlation matrix, with a colour bar, in the central
+    /// panel.
+    pub fn show(&mut self, ui: &mut Ui, state: &mut AppState) {
+        let key = heatmap_key(state);
+        if self.key != key || self.image.is_none() {
+            self.key = key;
+            self.image = build_image(ui.ctx(), state);
+        }
+        let Some(image) = &self.image else {
+            ui.centered_and_justified(|ui: &mut Ui| {
+                ui.heading("No spectra to compare");
+            });
+            return;
+        };
+
+        if image.rows.len() < image.n_plotted {
+            ui.label(
+                RichText::new(format!(
+                    "Showing {} of {} spectra; filter the selection to compare them all",
+                    image.rows.len(),
+                    image.n_plotted
+                ))
+                .color(ui.visuals().warn_fg_color),
+            );
+        }
+        let size = ui.available_size();
+        let response = ui.horizontal(|ui: &mut Ui| {
+            let side = (size.x - COLOR_BAR_SPACE).min(size.y).max(0.0);
+            let response = ui.add(
+                egui::Image::new((image.texture.id(), egui::vec2(side, side)))
+                    .sense(egui::Sense::hover()),
+            );
+            if let Some(pos) = response.hover_pos() {
+                hover_label(&response, pos, image, state);
+            }
+            let (lo, hi) = image.range;
+            color_bar(
+                ui,
+                state.heatmap_palette,
+                (format!("{lo:.3}"), format!("{hi:.3}")),
+                side,
+            );
+        });
+        state.plot_rect = Some(response.response.rect);
+    }
+}
+
+/// Correlate the plotted spectra on a common grid and colour the matrix.
+/// `None` if there is nothing to draw.
+fn build_image(ctx: &egui::Context, state: &AppState) -> Option<CorrelationImage> {
+    let ds = state.tab.dataset.as_ref()?;
+    let n_plotted = state.plotted_indices().len();
+    let rows = ordered_rows(state, ds, MAX_SPECTRA);
+    if rows.is_empty() {
+        return None;
+    }
+
+    let shown: Vec<Spectrum> = rows
+        .iter()
+        .zip(ds.spectra_at(&rows))
+        .map(|(&i, sp)| display_spectrum(state, i, &sp))
+        .collect();
+    let members: Vec<&Spectrum> = shown.iter().collect();
+    let grid = common_grid(&members);
+    let values: Vec<f64> = correlation_matrix(&members, &grid)
+        .into_iter()
+        .flatten()
+        .collect();
+    let (lo, hi) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    if lo > hi {
+        return None;
+    }
+
+    let scale = state.heatmap_palette.generate(SCALE_STEPS);
+    let pixels: Vec<Color32> = values
+        .iter()
+        .map(|&v| scale_color(&scale, v, (lo, hi)))
+        .collect();
+    let color_image = ColorImage {
+        size: [rows.len(), rows.len()],
+        pixels,
+    };
+    let texture = ctx.load_texture("correlation", color_image, TextureOptions::NEAREST);
+
+    Some(CorrelationImage {
+        texture,
+        rows,
+        values,
+        range: (lo, hi),
+        n_plotted,
+    })
+}
+
+/// Tooltip naming the two spectra under the pointer and their correlation.
+fn hover_label(
+    response: &egui::Response,
+    pos: egui::Pos2,
+    image: &CorrelationImage,
+    state: &AppState,
+) {
+    let rect = response.rect;
+    let n = image.rows.len();
+    let fraction = |v: f32, lo: f32, len: f32| ((v - lo) / len).clamp(0.0, 0.999_999);
+    let row = (fraction(pos.y, rect.top(), rect.height()) * n as f32) as usize;
+    let col = (fraction(pos.x, rect.left(), rect.width()) * n as f32) as usize;
+    let (a, b) = (image.rows[row], image.rows[col]);
+    let r = image.values[row * n + col];
+
+    response.clone().on_hover_ui_at_pointer(|ui: &mut Ui| {
+        ui.strong(format!("Spectrum {a} vs {b}"));
+        if let Some(col) = state.tab.color_column.as_deref() {
+            ui.label(format!(
+                "{col} = {} / {}",
+                state.color_group(a),
+                state.color_group(b)
+            ));
+        }
+        ui.label(format!("r = {r:.4}"));
+    });
+}
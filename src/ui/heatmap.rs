@@ -0,0 +1,262 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use eframe::egui::{self, Color32, ColorImage, TextureHandle, TextureOptions, Ui};
+
+use crate::color::PaletteKind;
+use crate::data::group::{common_grid, resample_to_grid};
+use crate::data::model::{SpectralDataset, Spectrum};
+use crate::state::AppState;
+use crate::ui::plot::display_spectrum;
+
+// ---------------------------------------------------------------------------
+// Heatmap view (central panel)
+// ---------------------------------------------------------------------------
+
+/// Largest image drawn; bigger datasets are sampled down to fit.
+const MAX_ROWS: usize = 4096;
+const MAX_COLUMNS: usize = 2048;
+
+/// Number of colours the intensity scale is sampled at.
+pub(super) const SCALE_STEPS: usize = 256;
+
+/// Width of the colour bar beside the image.
+const COLOR_BAR_WIDTH: f32 = 16.0;
+
+/// Room beside the image for the colour bar and its labels.
+pub(super) const COLOR_BAR_SPACE: f32 = 96.0;
+
+/// Heatmap of the plotted spectra: one row per spectrum, ordered by the
+/// colour column, one column per wavenumber of a common grid, coloured by
+/// displayed intensity.
+///
+/// The image is only rebuilt when the plotted spectra or the display
+/// settings change.
+#[derive(Default)]
+pub struct HeatmapView {
+    /// Hash of the inputs the current image was built from.
+    key: u64,
+    image: Option<HeatmapImage>,
+}
+
+/// A built heatmap and what its pixels stand for.
+struct HeatmapImage {
+    texture: TextureHandle,
+    /// Spectrum index of each row, top to bottom.
+    rows: Vec<usize>,
+    /// Wavenumber of each column, left to right.
+    columns: Vec<f64>,
+    /// Intensities, row-major; NaN where a spectrum has no data.
+    values: Vec<f64>,
+    /// Intensity at the two ends of the colour scale.
+    range: (f64, f64),
+}
+
+impl HeatmapView {
+    /// Render the heatmap, with a colour bar, in the central panel.
+    pub fn show(&mut self, ui: &mut Ui, state: &mut AppState) {
+        let key = heatmap_key(state);
+        if self.key != key || self.image.is_none() {
+            self.key = key;
+            self.image = build_image(ui.ctx(), state);
+        }
+        let Some(image) = &self.image else {
+            ui.centered_and_justified(|ui: &mut Ui| {
+                ui.heading("No spectra to show");
+            });
+            return;
+        };
+
+        let size = ui.available_size();
+        let response = ui.horizontal(|ui: &mut Ui| {
+            let image_size = egui::vec2(size.x - COLOR_BAR_SPACE, size.y).max(egui::Vec2::ZERO);
+            let response = ui.add(
+                egui::Image::new((image.texture.id(), image_size)).sense(egui::Sense::hover()),
+            );
+            if let Some(pos) = response.hover_pos() {
+                hover_label(&response, pos, image, state);
+            }
+            let (lo, hi) = image.range;
+            let labels = (intensity_label(state, lo), intensity_label(state, hi));
+            color_bar(ui, state.heatmap_palette, labels, size.y);
+        });
+        state.plot_rect = Some(response.response.rect);
+    }
+}
+
+/// Hash of everything the heatmap image depends on: the plotted spectra
+/// and how they are displayed.
+pub(super) fn heatmap_key(state: &AppState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.plotted_indices().hash(&mut hasher);
+    let settings = format!(
+        "{:?} {:?}",
+        (
+            state.tab.dataset.as_ref().map(|ds| ds.len()),
+            (state.active_tab, &state.tab.source_path),
+            &state.tab.color_column,
+            state.heatmap_palette,
+            state.log_y,
+            state.region,
+            state.reverse_x,
+        ),
+        (
+            state.normalization,
+            state.derivative_order,
+            state.smoothing,
+            state.binning,
+            state.baseline,
+            state.baseline_point,
+            &state.baseline_anchors,
+            state.subtract_anchors,
+        )
+    );
+    settings.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Resample the plotted spectra onto a common grid and colour them by
+/// intensity. `None` if there is nothing to draw.
+fn build_image(ctx: &egui::Context, state: &AppState) -> Option<HeatmapImage> {
+    let ds = state.tab.dataset.as_ref()?;
+    let rows = ordered_rows(state, ds, MAX_ROWS);
+
+    let shown: Vec<Spectrum> = rows
+        .iter()
+        .zip(ds.spectra_at(&rows))
+        .map(|(&i, sp)| display_spectrum(state, i, &sp))
+        .collect();
+    let members: Vec<&Spectrum> = shown.iter().collect();
+    let mut columns = common_grid(&members);
+    if columns.len() > MAX_COLUMNS {
+        let (lo, hi) = (columns[0], columns[columns.len() - 1]);
+        let step = (hi - lo) / (MAX_COLUMNS - 1) as f64;
+        columns = (0..MAX_COLUMNS).map(|i| lo + step * i as f64).collect();
+    }
+    if state.reverse_x {
+        columns.reverse();
+    }
+    if rows.is_empty() || columns.is_empty() {
+        return None;
+    }
+
+    let values: Vec<f64> = shown
+        .iter()
+        .flat_map(|sp| resample_to_grid(&sp.x, &sp.y, &columns))
+        .collect();
+    let (lo, hi) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    if lo > hi {
+        return None;
+    }
+
+    let scale = state.heatmap_palette.generate(SCALE_STEPS);
+    let pixels: Vec<Color32> = values
+        .iter()
+        .map(|&v| scale_color(&scale, v, (lo, hi)))
+        .collect();
+    let color_image = ColorImage {
+        size: [columns.len(), rows.len()],
+        pixels,
+    };
+    let texture = ctx.load_texture("heatmap", color_image, TextureOptions::NEAREST);
+
+    Some(HeatmapImage {
+        texture,
+        rows,
+        columns,
+        values,
+        range: (lo, hi),
+    })
+}
+
+/// The plotted spectra ordered by the colour column, taking every k-th one
+/// if there are more than `max`.
+pub(super) fn ordered_rows(state: &AppState, ds: &SpectralDataset, max: usize) -> Vec<usize> {
+    let mut rows = state.plotted_indices();
+    if let Some(col) = state.tab.color_column.as_deref() {
+        rows.sort_by(|&a, &b| {
+            let value = |i: usize| ds.spectra[i].metadata.get(col);
+            value(a).cmp(&value(b))
+        });
+    }
+    if rows.len() > max {
+        let step = rows.len().div_ceil(max);
+        rows = rows.into_iter().step_by(step).collect();
+    }
+    rows
+}
+
+/// Colour of intensity `v` on `scale` spanning `range`; transparent for NaN.
+pub(super) fn scale_color(scale: &[Color32], v: f64, (lo, hi): (f64, f64)) -> Color32 {
+    if !v.is_finite() {
+        return Color32::TRANSPARENT;
+    }
+    let t = if hi > lo { (v - lo) / (hi - lo) } else { 0.5 };
+    let i = (t * (scale.len() - 1) as f64).round() as usize;
+    scale[i.min(scale.len() - 1)]
+}
+
+/// Intensity as shown on the scale: the original magnitude on a log axis.
+fn intensity_label(state: &AppState, v: f64) -> String {
+    if state.log_y {
+        format!("{:.3e}", 10f64.powf(v))
+    } else {
+        format!("{v:.4}")
+    }
+}
+
+/// Tooltip naming the spectrum, wavenumber and intensity under the pointer.
+fn hover_label(response: &egui::Response, pos: egui::Pos2, image: &HeatmapImage, state: &AppState) {
+    let rect = response.rect;
+    let fraction = |v: f32, lo: f32, len: f32| ((v - lo) / len).clamp(0.0, 0.999_999);
+    let row = (fraction(pos.y, rect.top(), rect.height()) * image.rows.len() as f32) as usize;
+    let col = (fraction(pos.x, rect.left(), rect.width()) * image.columns.len() as f32) as usize;
+    let idx = image.rows[row];
+    let value = image.values[row * image.columns.len() + col];
+
+    response.clone().on_hover_ui_at_pointer(|ui: &mut Ui| {
+        ui.strong(format!("Spectrum {idx}"));
+        if let Some(col) = state.tab.color_column.as_deref() {
+            let group = state.color_group(idx);
+            ui.label(format!("{col} = {group}"));
+        }
+        ui.label(format!("x = {:.2}", image.columns[col]));
+        ui.label(format!("y = {}", intensity_label(state, value)));
+    });
+}
+
+/// Vertical colour scale of `palette`, labelled with the values at its ends.
+pub(super) fn color_bar(
+    ui: &mut Ui,
+    palette: PaletteKind,
+    (lo, hi): (String, String),
+    height: f32,
+) {
+    const STEPS: usize = 64;
+    let scale = palette.generate(STEPS);
+    ui.vertical(|ui: &mut Ui| {
+        ui.small(hi);
+        let bar_height =
+            (height - 2.0 * ui.text_style_height(&egui::TextStyle::Small) - 8.0).max(0.0);
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(COLOR_BAR_WIDTH, bar_height),
+            egui::Sense::hover(),
+        );
+        let step_h = rect.height() / STEPS as f32;
+        for (i, color) in scale.iter().enumerate() {
+            // Highest intensity at the top.
+            let y0 = rect.bottom() - (i + 1) as f32 * step_h;
+            let step = egui::Rect::from_min_max(
+                egui::pos2(rect.left(), y0 - 0.5),
+                egui::pos2(rect.right(), y0 + step_h),
+            );
+            ui.painter().rect_filled(step, 0.0, *color);
+        }
+        ui.small(lo);
+    });
+}
@@ -1,3 +1,6 @@
 /// UI modules.
+pub mod correlation;
+pub mod heatmap;
 pub mod panels;
+pub mod pca;
 pub mod plot;
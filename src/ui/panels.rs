@@ -1,6 +1,28 @@
-use eframe::egui::{self, Color32, ScrollArea, Ui, RichText};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, mpsc};
 
-use crate::state::AppState;
+use chrono::NaiveDate;
+use eframe::egui::collapsing_header::CollapsingState;
+use eframe::egui::{self, Color32, RichText, ScrollArea, Ui};
+use egui_extras::DatePickerButton;
+
+use crate::color::{ColorMap, ColorMode, ColorScale, PaletteKind, ramp_color};
+use crate::data::analysis::DerivedMetric;
+use crate::data::filter::{CompareOp, FilterKind, FilterLogic, active_filters};
+use crate::data::group::{common_grid, group_indices};
+use crate::data::lazy::LAZY_CACHE_SPECTRA;
+use crate::data::loader::{load_file_with_progress, load_files};
+use crate::data::model::{
+    CleanMode, CsvDialect, CsvLayout, IRREGULAR_X_TOLERANCE, MetadataKind, MetadataValue, Spectrum,
+    TableLayout, date_range, histogram, numeric_range,
+};
+use crate::data::preprocess::{AlsParams, Normalization, SavGolParams, trim_region};
+use crate::export::{MAX_RENDER_SIDE, MAX_SUPERSAMPLE};
+use crate::state::{
+    AppState, LineStyle, LoadMode, PendingLoad, PlotMode, PlotTheme, PlotView, ScreenshotTarget,
+};
 
 // ---------------------------------------------------------------------------
 // Left side panel – filter widgets
@@ -15,7 +37,7 @@ pub fn side_panel(ui: &mut Ui, state: &mut AppState) {
             egui::Image::new(logo)
                 .max_width(ui.available_width() * 0.8)
                 .max_height(120.0)
-                .rounding(4.0),
+                .corner_radius(4.0),
         );
     });
     ui.add_space(4.0);
@@ -23,7 +45,7 @@ pub fn side_panel(ui: &mut Ui, state: &mut AppState) {
     ui.heading("Filters");
     ui.separator();
 
-    let dataset = match &state.dataset {
+    let dataset = match &state.tab.dataset {
         Some(ds) => ds,
         None => {
             ui.label("No dataset loaded.");
@@ -34,13 +56,19 @@ pub fn side_panel(ui: &mut Ui, state: &mut AppState) {
     // Clone what we need so we can mutate state inside the loop.
     let columns = dataset.column_names.clone();
     let unique = dataset.unique_values.clone();
+    let profiles = dataset.profiles.clone();
+    let continuous: BTreeSet<String> = columns
+        .iter()
+        .filter(|col| dataset.is_continuous(col, state.categorical_threshold))
+        .cloned()
+        .collect();
 
     ScrollArea::vertical()
         .auto_shrink([false, false])
         .show(ui, |ui: &mut Ui| {
             // ---- Colour-by selector ----
             ui.strong("Color by");
-            let current_color_col = state.color_column.clone().unwrap_or_default();
+            let current_color_col = state.tab.color_column.clone().unwrap_or_default();
             egui::ComboBox::from_id_salt("color_by")
                 .selected_text(&current_color_col)
                 .show_ui(ui, |ui: &mut Ui| {
@@ -53,72 +81,807 @@ pub fn side_panel(ui: &mut Ui, state: &mut AppState) {
                         }
                     }
                 });
+            ui.horizontal(|ui: &mut Ui| {
+                ui.label("Palette");
+                egui::ComboBox::from_id_salt("palette")
+                    .selected_text(state.palette.label())
+                    .show_ui(ui, |ui: &mut Ui| {
+                        for kind in PaletteKind::ALL {
+                            if ui
+                                .selectable_label(state.palette == kind, kind.label())
+                                .clicked()
+                            {
+                                state.set_palette(kind);
+                            }
+                        }
+                    });
+                let mut cvd_safe = state.cvd_safe;
+                if ui
+                    .checkbox(&mut cvd_safe, "Colourblind-safe")
+                    .on_hover_text("Use the Okabe-Ito palette for up to 8 categories")
+                    .changed()
+                {
+                    state.set_cvd_safe(cvd_safe);
+                }
+            });
+            let mut from_hex = state.color_from_hex;
+            ui.horizontal(|ui: &mut Ui| {
+                if ui
+                    .checkbox(&mut from_hex, "From column (hex)")
+                    .on_hover_text("Use the column's values (e.g. \"#ff8800\") as colours")
+                    .changed()
+                {
+                    state.set_color_from_hex(from_hex);
+                }
+                if ui
+                    .add_enabled(
+                        state.has_color_overrides(),
+                        egui::Button::new("Reset colours").small(),
+                    )
+                    .on_hover_text("Forget the colours chosen for this column's values")
+                    .clicked()
+                {
+                    state.reset_color_overrides();
+                }
+            });
+            if let Some(cm) = state
+                .tab
+                .color_map
+                .as_ref()
+                .filter(|cm| cm.mode == ColorMode::FromHex)
+            {
+                let n_total = unique.get(&current_color_col).map_or(0, |v| v.len());
+                let n_invalid = n_total.saturating_sub(cm.n_mapped());
+                if n_invalid > 0 {
+                    ui.label(
+                        RichText::new(format!("{n_invalid} value(s) are not hex colours"))
+                            .small()
+                            .color(Color32::YELLOW),
+                    );
+                }
+            }
+            let mut scale = state.color_scale;
+            if let Some(cm) = &state.tab.color_map {
+                if matches!(cm.mode, ColorMode::Continuous { .. }) {
+                    color_bar(ui, cm);
+                    ui.horizontal(|ui: &mut Ui| {
+                        ui.label("Scale");
+                        for s in ColorScale::ALL {
+                            ui.selectable_value(&mut scale, s, s.label())
+                                .on_hover_text(match s {
+                                    ColorScale::Linear => "Place values by their distance from the minimum",
+                                    ColorScale::Quantile => {
+                                        "Place values by percentile, for skewed columns"
+                                    }
+                                });
+                        }
+                    });
+                }
+            }
+            if scale != state.color_scale {
+                state.set_color_scale(scale);
+            }
+            let mut threshold = state.categorical_threshold;
+            ui.horizontal(|ui: &mut Ui| {
+                ui.label("Categorical up to");
+                if ui
+                    .add(egui::DragValue::new(&mut threshold).range(1..=1000))
+                    .on_hover_text(
+                        "Numeric columns with more unique values use a colour ramp and range filter",
+                    )
+                    .changed()
+                {
+                    state.set_categorical_threshold(threshold);
+                }
+                ui.label("values");
+            });
             ui.separator();
 
-            // ---- Per-column filter widgets (collapsible) ----
-            for col in &columns {
+            // ---- How column filters combine ----
+            ui.horizontal(|ui: &mut Ui| {
+                ui.label("Match");
+                for logic in FilterLogic::ALL {
+                    ui.selectable_value(&mut state.filter_logic, logic, logic.label())
+                        .on_hover_text(match logic {
+                            FilterLogic::And => "Show spectra passing every filtered column",
+                            FilterLogic::Or => "Show spectra passing at least one filtered column",
+                        });
+                }
+            });
+
+            // ---- Per-column filter widgets (collapsible, user-ordered) ----
+            let mut moved: Option<(String, String)> = None;
+            let mut hide: Option<String> = None;
+            let mut recast: Option<(String, MetadataKind)> = None;
+            for col in &state.ordered_columns() {
+                if state.tab.hidden_columns.contains(col) {
+                    continue;
+                }
                 let Some(all_values) = unique.get(col) else {
                     continue;
                 };
 
-                let selected = state
+                // Show count of selected / total in the header
+                let n_selected = state
+                    .tab
                     .filters
-                    .entry(col.clone())
-                    .or_default();
+                    .get(col)
+                    .map_or(0, |f| f.selected_values(all_values).len());
+                let header_text = match profiles.get(col).filter(|p| p.continuous) {
+                    // Only the range of a capped column is indexed.
+                    Some(profile) => format!("{col}  ({} values)", profile.cardinality),
+                    None => format!("{col}  ({n_selected}/{})", all_values.len()),
+                };
 
-                // Show count of selected / total in the header
-                let n_selected = selected.len();
-                let n_total = all_values.len();
-                let header_text = format!("{col}  ({n_selected}/{n_total})");
-
-                egui::CollapsingHeader::new(RichText::new(header_text).strong())
-                    .id_salt(col)
-                    .default_open(false)
-                    .show(ui, |ui: &mut Ui| {
-                        // Select all / none buttons
-                        ui.horizontal(|ui: &mut Ui| {
-                            if ui.small_button("All").clicked() {
-                                state.select_all(col);
-                            }
-                            if ui.small_button("None").clicked() {
-                                state.select_none(col);
+                let id = ui.make_persistent_id(("column", col));
+                let (_, header, _) =
+                    CollapsingState::load_with_default_open(ui.ctx(), id, false)
+                        .show_header(ui, |ui: &mut Ui| {
+                            ui.dnd_drag_source(id.with("drag"), col.clone(), |ui: &mut Ui| {
+                                ui.label("☰");
+                            })
+                            .response
+                            .on_hover_text("Drag to reorder");
+                            let current = all_values.iter().find_map(MetadataValue::kind);
+                            ui.add(
+                                egui::Label::new(RichText::new(header_text).strong())
+                                    .sense(egui::Sense::click()),
+                            )
+                            .on_hover_text("Right-click to change the column type")
+                            .context_menu(|ui: &mut Ui| {
+                                ui.label("Treat as");
+                                for kind in MetadataKind::ALL {
+                                    if ui
+                                        .selectable_label(current == Some(kind), kind.label())
+                                        .clicked()
+                                    {
+                                        recast = Some((col.clone(), kind));
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                            if ui.small_button("Hide").clicked() {
+                                hide = Some(col.clone());
                             }
+                        })
+                        .body(|ui: &mut Ui| {
+                            column_filter(ui, state, col, all_values, continuous.contains(col));
                         });
 
-                        // Re-borrow after potential mutation from All/None
-                        let selected = state
-                            .filters
-                            .entry(col.clone())
-                            .or_default();
-
-                        for val in all_values {
-                            let is_selected = selected.contains(val);
-                            let label = val.to_string();
-
-                            // Show colour swatch if this is the colour column
-                            let mut text = RichText::new(&label);
-                            if state.color_column.as_deref() == Some(col) {
-                                if let Some(cm) = &state.color_map {
-                                    let c = cm.color_for(val);
-                                    text = text.color(c);
-                                }
-                            }
+                // Dropping a dragged column on this header moves it here.
+                let rect = header.response.rect;
+                if header.response.dnd_hover_payload::<String>().is_some() {
+                    let stroke = ui.visuals().selection.stroke;
+                    ui.painter().hline(rect.x_range(), rect.top(), stroke);
+                }
+                if let Some(dragged) = header.response.dnd_release_payload::<String>() {
+                    moved = Some(((*dragged).clone(), col.clone()));
+                }
+            }
 
-                            let mut checked = is_selected;
-                            if ui.checkbox(&mut checked, text).changed() {
-                                if checked {
-                                    selected.insert(val.clone());
-                                } else {
-                                    selected.remove(val);
-                                }
+            // ---- Filters on computed spectrum properties ----
+            ui.separator();
+            metric_filters(ui, state);
+
+            // ---- Hidden columns ----
+            if !state.tab.hidden_columns.is_empty() {
+                ui.separator();
+                ui.collapsing("Hidden columns", |ui: &mut Ui| {
+                    for col in state.tab.hidden_columns.clone() {
+                        ui.horizontal(|ui: &mut Ui| {
+                            if ui.small_button("Show").clicked() {
+                                state.set_column_hidden(&col, false);
                             }
-                        }
-                    });
+                            ui.label(&col);
+                        });
+                    }
+                });
+            }
+
+            if let Some(col) = hide {
+                state.set_column_hidden(&col, true);
+            }
+            if let Some((col, kind)) = recast {
+                state.recast_column(&col, kind);
+            }
+            if let Some((col, before)) = moved {
+                state.move_column(&col, &before);
+            }
+
+            // Recompute visible indices after any checkbox changes.
+            state.refilter();
+
+            // ---- Shaded spectral regions ----
+            ui.separator();
+            ui.collapsing("Regions", |ui: &mut Ui| {
+                region_editor(ui, state);
+            });
+
+            // ---- Summary of the visible spectra ----
+            ui.separator();
+            ui.collapsing("Statistics", |ui: &mut Ui| {
+                statistics(ui, state);
+            });
+        });
+}
+
+/// Colour of a newly added region.
+const DEFAULT_REGION_COLOR: Color32 = Color32::from_rgb(255, 200, 60);
+
+/// One row per shaded region (bounds, colour, label, remove) and a button
+/// adding a new one in the middle of the view.
+fn region_editor(ui: &mut Ui, state: &mut AppState) {
+    let mut remove = None;
+    for (i, (start, end, color, label)) in state.tab.regions.iter_mut().enumerate() {
+        ui.horizontal(|ui: &mut Ui| {
+            ui.add(egui::DragValue::new(start).speed(1.0));
+            ui.label("–");
+            ui.add(egui::DragValue::new(end).speed(1.0));
+            ui.color_edit_button_srgba(color);
+            ui.add(
+                egui::TextEdit::singleline(label)
+                    .hint_text("label")
+                    .desired_width(80.0),
+            );
+            if ui
+                .small_button("×")
+                .on_hover_text("Remove region")
+                .clicked()
+            {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        state.tab.regions.remove(i);
+    }
+    if ui
+        .button("Add region")
+        .on_hover_text("Shade an x range of the plot, e.g. a diagnostic band")
+        .clicked()
+    {
+        let [lo, hi] = state.tab.view_bounds.map_or([800.0, 1800.0], |v| v.x);
+        let (mid, half) = ((lo + hi) / 2.0, (hi - lo) / 10.0);
+        state
+            .tab
+            .regions
+            .push((mid - half, mid + half, DEFAULT_REGION_COLOR, String::new()));
+    }
+}
+
+/// Visible spectrum count, per colour group counts and the spread of peak
+/// wavenumbers.
+fn statistics(ui: &mut Ui, state: &mut AppState) {
+    let color_column = state.tab.color_column.clone();
+    let (precision, scientific) = (state.value_precision, state.scientific_values);
+    let stats = state.selection_stats();
+    egui::Grid::new("statistics")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui: &mut Ui| {
+            ui.label("Visible spectra");
+            ui.label(stats.n_visible.to_string());
+            ui.end_row();
+            if let Some((mean, min, max)) = stats.peak_wavenumber {
+                ui.label("Peak wavenumber");
+                ui.label(format!("{mean:.1}"))
+                    .on_hover_text("Mean x of each spectrum's highest point");
+                ui.end_row();
+                ui.label("  range");
+                ui.label(format!("{min:.1} – {max:.1}"));
+                ui.end_row();
+            }
+        });
+    let Some(col) = color_column else {
+        return;
+    };
+    ui.add_space(4.0);
+    ui.strong(format!("Per {col}"));
+    egui::Grid::new("statistics_groups")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui: &mut Ui| {
+            for (value, n) in &stats.group_counts {
+                ui.label(value.format_value(precision, scientific));
+                ui.label(n.to_string());
+                ui.end_row();
+            }
+        });
+}
+
+/// Horizontal gradient for a continuous colour map, with tick labels.
+fn color_bar(ui: &mut Ui, cm: &ColorMap) {
+    const STEPS: usize = 64;
+    let width = ui.available_width();
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(width, 12.0), egui::Sense::hover());
+    let painter = ui.painter();
+    let step_w = rect.width() / STEPS as f32;
+    for i in 0..STEPS {
+        let x0 = rect.left() + i as f32 * step_w;
+        let step = egui::Rect::from_min_max(
+            egui::pos2(x0, rect.top()),
+            egui::pos2(x0 + step_w + 0.5, rect.bottom()),
+        );
+        painter.rect_filled(step, 0.0, ramp_color(i as f64 / (STEPS - 1) as f64));
+    }
+
+    let entries = cm.legend_entries();
+    if let (Some(first), Some(last)) = (entries.first(), entries.last()) {
+        ui.horizontal(|ui: &mut Ui| {
+            ui.small(&first.0);
+            ui.with_layout(
+                egui::Layout::right_to_left(egui::Align::Center),
+                |ui: &mut Ui| {
+                    ui.small(&last.0);
+                },
+            );
+        });
+    }
+}
+
+/// Filter widgets for one column: All/None buttons plus either a range
+/// input (continuous columns) or one checkbox per value.
+fn column_filter(
+    ui: &mut Ui,
+    state: &mut AppState,
+    col: &str,
+    all_values: &BTreeSet<MetadataValue>,
+    continuous: bool,
+) {
+    // Select all / none / invert buttons
+    ui.horizontal(|ui: &mut Ui| {
+        if ui.small_button("All").clicked() {
+            state.select_all(col);
+        }
+        if ui.small_button("None").clicked() {
+            state.select_none(col);
+        }
+        let known = state
+            .tab
+            .dataset
+            .as_ref()
+            .and_then(|ds| ds.profiles.get(col))
+            .is_some_and(|profile| !profile.continuous);
+        if ui
+            .add_enabled(known, egui::Button::new("Invert").small())
+            .on_hover_text("Select exactly the values that are not selected")
+            .clicked()
+        {
+            state.invert_filter(col);
+        }
+        if !state.value_order(col).is_empty()
+            && ui
+                .small_button("Reset order")
+                .on_hover_text("List the values in sorted order again")
+                .clicked()
+        {
+            state.reset_value_order(col);
+        }
+    });
+
+    if continuous {
+        range_filter(ui, state, col, all_values);
+        return;
+    }
+    if all_values
+        .iter()
+        .all(|v| matches!(v, MetadataValue::Date(_) | MetadataValue::Null))
+    {
+        date_range_filter(ui, state, col, all_values);
+        return;
+    }
+
+    // Threshold alternative to ticking values for numeric columns.
+    let numeric = all_values
+        .iter()
+        .all(|v| v.as_f64().is_some() || *v == MetadataValue::Null);
+    if let Some(bounds) = numeric_range(all_values).filter(|_| numeric) {
+        comparison_filter(ui, state, col, bounds);
+    }
+
+    // Search box for string columns; only narrows the list shown.
+    let mut query = String::new();
+    if all_values
+        .iter()
+        .any(|v| matches!(v, MetadataValue::String(_)))
+    {
+        let search = state.tab.column_search.entry(col.to_string()).or_default();
+        ui.add(egui::TextEdit::singleline(search).hint_text("Search…"));
+        query = search.to_lowercase();
+    }
+
+    let mut values: Vec<&MetadataValue> = all_values.iter().collect();
+    values.sort_by(|a, b| a.custom_cmp(b, state.value_order(col)));
+    let mut moved: Option<(MetadataValue, MetadataValue)> = None;
+    for val in values {
+        let label = state.format_value(val);
+        if !query.is_empty() && !label.to_lowercase().contains(&query) {
+            continue;
+        }
+        let is_selected = state
+            .tab
+            .filters
+            .get(col)
+            .is_some_and(|f| f.matches(Some(val)));
+
+        // Show colour swatch if this is the colour column
+        let mut text = RichText::new(&label);
+        let mut swatch = None;
+        if state.tab.color_column.as_deref() == Some(col) {
+            if let Some(cm) = &state.tab.color_map {
+                let c = cm.color_for(val);
+                text = text.color(c);
+                swatch = Some(c);
+            }
+        }
+
+        let row = ui.horizontal(|ui: &mut Ui| {
+            let id = ui.make_persistent_id(("value", col, val));
+            ui.dnd_drag_source(id, (col.to_string(), val.clone()), |ui: &mut Ui| {
+                ui.label("☰");
+            })
+            .response
+            .on_hover_text("Drag to reorder; colours and the legend follow this order");
+            if let Some(mut color) = swatch {
+                let size = egui::Vec2::splat(ui.spacing().interact_size.y * 0.8);
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+                ui.painter().rect_filled(rect, 2.0, color);
+                let response = response.on_hover_text(
+                    "Click to show only this value, again to restore the selection; \
+                     right-click to choose its colour",
+                );
+                if response.clicked() {
+                    state.isolate_filter_value(col, val);
+                }
+                response.context_menu(|ui: &mut Ui| {
+                    if egui::color_picker::color_picker_color32(
+                        ui,
+                        &mut color,
+                        egui::color_picker::Alpha::Opaque,
+                    ) {
+                        state.set_color_override(val.clone(), color);
+                    }
+                });
+            }
+            let mut checked = is_selected;
+            if ui.checkbox(&mut checked, text).changed() {
+                state.toggle_filter_value(col, val);
+            }
+        });
+
+        // Dropping a dragged value of this column on a row moves it there.
+        let rect = row.response.rect;
+        let payload = row.response.dnd_hover_payload::<(String, MetadataValue)>();
+        if payload.is_some_and(|p| p.0 == col) {
+            let stroke = ui.visuals().selection.stroke;
+            ui.painter().hline(rect.x_range(), rect.top(), stroke);
+        }
+        let payload = row
+            .response
+            .dnd_release_payload::<(String, MetadataValue)>();
+        if let Some(dragged) = payload.filter(|p| p.0 == col) {
+            moved = Some((dragged.1.clone(), val.clone()));
+        }
+    }
+    if let Some((value, before)) = moved {
+        state.move_value(col, &value, &before);
+    }
+}
+
+/// Range slider plus min/max inputs restricting a continuous column to a range.
+fn range_filter(
+    ui: &mut Ui,
+    state: &mut AppState,
+    col: &str,
+    all_values: &BTreeSet<MetadataValue>,
+) {
+    let Some((data_lo, data_hi)) = numeric_range(all_values) else {
+        return;
+    };
+    if comparison_filter(ui, state, col, (data_lo, data_hi)) {
+        return;
+    }
+    let (mut lo, mut hi) = state
+        .tab
+        .filters
+        .get(col)
+        .and_then(FilterKind::numeric_bounds)
+        .unwrap_or((data_lo, data_hi));
+    let speed = (data_hi - data_lo) / 200.0;
+
+    // Sample the colour ramp at the bounds when this is the colour column.
+    let swatch = |v: f64| {
+        state
+            .tab
+            .color_map
+            .as_ref()
+            .filter(|cm| cm.column == col)
+            .map(|cm| cm.color_for(&MetadataValue::Float(v)))
+    };
+    let (lo_color, hi_color) = (swatch(lo), swatch(hi));
+
+    // Distribution over the whole dataset, whatever the filters.
+    if let Some(ds) = &state.tab.dataset {
+        let values: Vec<f64> = ds
+            .spectra
+            .iter()
+            .filter_map(|sp| sp.metadata.get(col)?.as_f64())
+            .collect();
+        histogram_bars(
+            ui,
+            &histogram(&values, HISTOGRAM_BINS),
+            (lo, hi),
+            (data_lo, data_hi),
+        );
+    }
+    let mut changed = range_slider(ui, &mut lo, &mut hi, data_lo, data_hi);
+    ui.horizontal(|ui: &mut Ui| {
+        if let Some(c) = lo_color {
+            ui.label(RichText::new("■").color(c));
+        }
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut lo)
+                    .range(data_lo..=hi)
+                    .speed(speed),
+            )
+            .changed();
+        ui.label("–");
+        if let Some(c) = hi_color {
+            ui.label(RichText::new("■").color(c));
+        }
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut hi)
+                    .range(lo..=data_hi)
+                    .speed(speed),
+            )
+            .changed();
+    });
+    if changed {
+        state.set_range_filter(col, lo, hi);
+    }
+}
+
+/// Range filters on derived spectrum properties, plus a menu adding one.
+fn metric_filters(ui: &mut Ui, state: &mut AppState) {
+    ui.strong("Spectrum properties");
+    let mut removed = None;
+    for (metric, range) in state.tab.metric_filters.clone() {
+        let Some((data_lo, data_hi)) = state.metric_range(metric) else {
+            continue;
+        };
+        let (mut lo, mut hi) = range.into_inner();
+        let speed = (data_hi - data_lo) / 200.0;
+
+        ui.horizontal(|ui: &mut Ui| {
+            ui.label(metric.label());
+            if ui
+                .small_button("×")
+                .on_hover_text("Remove filter")
+                .clicked()
+            {
+                removed = Some(metric);
             }
         });
+        let mut changed = range_slider(ui, &mut lo, &mut hi, data_lo, data_hi);
+        ui.horizontal(|ui: &mut Ui| {
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut lo)
+                        .range(data_lo..=hi)
+                        .speed(speed),
+                )
+                .changed();
+            ui.label("–");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut hi)
+                        .range(lo..=data_hi)
+                        .speed(speed),
+                )
+                .changed();
+        });
+        if changed {
+            state.set_metric_filter(metric, lo, hi);
+        }
+    }
+    if let Some(metric) = removed {
+        state.remove_metric_filter(metric);
+    }
+
+    let available: Vec<DerivedMetric> = DerivedMetric::ALL
+        .into_iter()
+        .filter(|m| !state.tab.metric_filters.contains_key(m))
+        .collect();
+    if available.is_empty() {
+        return;
+    }
+    egui::ComboBox::from_id_salt("add_metric_filter")
+        .selected_text("Add filter…")
+        .show_ui(ui, |ui: &mut Ui| {
+            for metric in available {
+                if ui.selectable_label(false, metric.label()).clicked() {
+                    if let Some((lo, hi)) = state.metric_range(metric) {
+                        state.set_metric_filter(metric, lo, hi);
+                    }
+                }
+            }
+        })
+        .response
+        .on_hover_text("Filter on a value computed from each spectrum's raw intensities");
+}
+
+/// Operator dropdown plus value input for a one-sided threshold on a
+/// numeric column; "Any" hands over to the column's other widgets.
+/// Returns whether a comparison is in use.
+fn comparison_filter(
+    ui: &mut Ui,
+    state: &mut AppState,
+    col: &str,
+    (data_lo, data_hi): (f64, f64),
+) -> bool {
+    let current = match state.tab.filters.get(col) {
+        Some(FilterKind::Comparison { op, value }) => Some((*op, *value)),
+        _ => None,
+    };
+    let mut op = current.map(|(op, _)| op);
+    let mut value = current.map_or(data_lo, |(_, v)| v);
+    let speed = ((data_hi - data_lo) / 200.0).max(1e-3);
 
-    // Recompute visible indices after any checkbox changes.
-    state.refilter();
+    let mut changed = false;
+    ui.horizontal(|ui: &mut Ui| {
+        ui.label("Value");
+        egui::ComboBox::from_id_salt(("compare_op", col))
+            .width(48.0)
+            .selected_text(op.map_or("any", CompareOp::label))
+            .show_ui(ui, |ui: &mut Ui| {
+                changed |= ui.selectable_value(&mut op, None, "any").changed();
+                for candidate in CompareOp::ALL {
+                    changed |= ui
+                        .selectable_value(&mut op, Some(candidate), candidate.label())
+                        .changed();
+                }
+            });
+        if op.is_some() {
+            changed |= ui
+                .add(egui::DragValue::new(&mut value).speed(speed))
+                .changed();
+        }
+    });
+    if changed {
+        match op {
+            Some(op) => state.set_comparison_filter(col, op, value),
+            None => state.select_all(col),
+        }
+    }
+    op.is_some()
+}
+
+/// Two date pickers restricting a date column to a range of days.
+fn date_range_filter(
+    ui: &mut Ui,
+    state: &mut AppState,
+    col: &str,
+    all_values: &BTreeSet<MetadataValue>,
+) {
+    let parse = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+    let Some((data_start, data_end)) = date_range(all_values) else {
+        return;
+    };
+    let (Some(data_start), Some(data_end)) = (parse(data_start), parse(data_end)) else {
+        return;
+    };
+    let (mut start, mut end) = state
+        .tab
+        .filters
+        .get(col)
+        .and_then(FilterKind::date_bounds)
+        .and_then(|(s, e)| Some((parse(s)?, parse(e)?)))
+        .unwrap_or((data_start, data_end));
+
+    let mut changed = false;
+    ui.horizontal(|ui: &mut Ui| {
+        changed |= ui
+            .add(DatePickerButton::new(&mut start).id_salt(&format!("{col}_start")))
+            .changed();
+        ui.label("–");
+        changed |= ui
+            .add(DatePickerButton::new(&mut end).id_salt(&format!("{col}_end")))
+            .changed();
+    });
+    if changed {
+        if start > end {
+            std::mem::swap(&mut start, &mut end);
+        }
+        let fmt = |d: NaiveDate| d.format("%Y-%m-%d").to_string();
+        state.set_date_range_filter(col, fmt(start), fmt(end));
+    }
+}
+
+/// Double-ended slider over `[min, max]`; dragging moves whichever handle
+/// was closest when the pointer was pressed. Returns whether a bound changed.
+/// Number of bins in a numeric column's histogram.
+const HISTOGRAM_BINS: usize = 30;
+
+/// Histogram of a numeric column spanning `[min, max]`, drawn to line up
+/// with the [`range_slider`] below it. Bars inside the selected `[lo, hi]`
+/// are highlighted.
+fn histogram_bars(ui: &mut Ui, counts: &[u32], (lo, hi): (f64, f64), (min, max): (f64, f64)) {
+    let size = egui::vec2(ui.available_width().max(60.0), 32.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let rect = rect.shrink2(egui::vec2(6.0, 0.0));
+    let Some(&peak) = counts.iter().max().filter(|&&n| n > 0) else {
+        return;
+    };
+    let bar_width = rect.width() / counts.len() as f32;
+    let bin_width = (max - min) / counts.len() as f64;
+    let painter = ui.painter();
+    for (i, &n) in counts.iter().enumerate() {
+        let left = rect.left() + i as f32 * bar_width;
+        let top = rect.bottom() - rect.height() * n as f32 / peak as f32;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left, top),
+            egui::pos2(left + bar_width - 1.0, rect.bottom()),
+        );
+        let (bin_lo, bin_hi) = (min + bin_width * i as f64, min + bin_width * (i + 1) as f64);
+        let color = if bin_hi >= lo && bin_lo <= hi {
+            ui.visuals().selection.bg_fill
+        } else {
+            ui.visuals().widgets.inactive.bg_fill
+        };
+        painter.rect_filled(bar, 0.0, color);
+    }
+}
+
+fn range_slider(ui: &mut Ui, lo: &mut f64, hi: &mut f64, min: f64, max: f64) -> bool {
+    let size = egui::vec2(ui.available_width().max(60.0), 18.0);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+    let rect = rect.shrink2(egui::vec2(6.0, 0.0));
+    let span = (max - min).max(f64::MIN_POSITIVE);
+    let to_x = |v: f64| rect.left() + ((v - min) / span) as f32 * rect.width();
+    let to_value = |x: f32| min + ((x - rect.left()) / rect.width()).clamp(0.0, 1.0) as f64 * span;
+
+    let mut changed = false;
+    if let Some(pos) = response.interact_pointer_pos() {
+        let v = to_value(pos.x);
+        let id = response.id;
+        let grab_hi = if ui.input(|i| i.pointer.any_pressed()) {
+            let grab_hi = (v - *hi).abs() < (v - *lo).abs() || (*lo == *hi && v > *hi);
+            ui.data_mut(|d| d.insert_temp(id, grab_hi));
+            grab_hi
+        } else {
+            ui.data(|d| d.get_temp(id)).unwrap_or(false)
+        };
+        let (old_lo, old_hi) = (*lo, *hi);
+        if grab_hi {
+            *hi = v.max(*lo);
+        } else {
+            *lo = v.min(*hi);
+        }
+        changed = *lo != old_lo || *hi != old_hi;
+    }
+
+    let visuals = ui.style().interact(&response);
+    let y = rect.center().y;
+    let painter = ui.painter();
+    painter.line_segment(
+        [egui::pos2(rect.left(), y), egui::pos2(rect.right(), y)],
+        egui::Stroke::new(4.0, ui.visuals().widgets.inactive.bg_fill),
+    );
+    painter.line_segment(
+        [egui::pos2(to_x(*lo), y), egui::pos2(to_x(*hi), y)],
+        egui::Stroke::new(4.0, ui.visuals().selection.bg_fill),
+    );
+    for v in [*lo, *hi] {
+        painter.circle(
+            egui::pos2(to_x(v), y),
+            6.0,
+            visuals.bg_fill,
+            visuals.fg_stroke,
+        );
+    }
+    changed
 }
 
 // ---------------------------------------------------------------------------
@@ -133,61 +896,1722 @@ pub fn top_bar(ui: &mut Ui, state: &mut AppState) {
                 open_file_dialog(state);
                 ui.close_menu();
             }
+            if ui
+                .add_enabled(
+                    state.tab.dataset.is_some(),
+                    egui::Button::new("Open and append…"),
+                )
+                .clicked()
+            {
+                append_file_dialog(state);
+                ui.close_menu();
+            }
+            if ui.button("Open folder…").clicked() {
+                open_folder_dialog(state);
+                ui.close_menu();
+            }
+            ui.add_enabled_ui(!state.recent_files.is_empty(), |ui: &mut Ui| {
+                ui.menu_button("Recent", |ui: &mut Ui| {
+                    recent_files_menu(ui, state);
+                });
+            });
+            let reload = egui::Button::new("Reload").shortcut_text("Ctrl+R");
+            if ui
+                .add_enabled(state.tab.source_path.is_some(), reload)
+                .clicked()
+            {
+                reload_file(state);
+                ui.close_menu();
+            }
+            ui.separator();
+            if ui.button("Open recipe…").clicked() {
+                open_recipe_dialog(state);
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    state.tab.dataset.is_some(),
+                    egui::Button::new("Save recipe…"),
+                )
+                .clicked()
+            {
+                save_recipe_dialog(state);
+                ui.close_menu();
+            }
+            if ui.button("Load session…").clicked() {
+                load_session_dialog(state);
+                ui.close_menu();
+            }
+            if ui.button("Save session…").clicked() {
+                save_session_dialog(state);
+                ui.close_menu();
+            }
+            ui.separator();
+            if ui
+                .checkbox(
+                    &mut state.load_options.unify_numeric,
+                    "Treat 1 and 1.0 as equal",
+                )
+                .on_hover_text("Store integers as floats in columns that mix both")
+                .changed()
+            {
+                reload_file(state);
+            }
+            if ui
+                .checkbox(
+                    &mut state.load_options.compute_quality,
+                    "Compute quality flags",
+                )
+                .on_hover_text("Add __is_flat__, __has_nan__, __saturated__ and __snr__ columns")
+                .changed()
+            {
+                reload_file(state);
+            }
+            if ui
+                .checkbox(
+                    &mut state.load_options.missing_as_null,
+                    "Filter on missing values",
+                )
+                .on_hover_text(
+                    "Add a <null> checkbox to columns that some spectra lack, \
+                     to show or hide those spectra",
+                )
+                .changed()
+            {
+                reload_file(state);
+            }
+            if ui
+                .checkbox(&mut state.load_options.lazy_parquet, "Load Parquet lazily")
+                .on_hover_text(
+                    "Read only the metadata of a Parquet file when opening it, and each \
+                     spectrum when it is plotted, for files larger than memory. \
+                     Statistics over every spectrum are then unavailable.",
+                )
+                .changed()
+            {
+                reload_file(state);
+            }
+            ui.separator();
+            if layout_options(ui, &mut state.load_options.layout) {
+                reload_file(state);
+            }
+            ui.label("CSV layout");
+            for layout in CsvLayout::ALL {
+                if ui
+                    .radio_value(&mut state.load_options.csv_layout, layout, layout.label())
+                    .changed()
+                {
+                    reload_file(state);
+                }
+            }
+            if csv_dialect_options(ui, &mut state.load_options.csv_dialect) {
+                reload_file(state);
+            }
+        });
+
+        ui.menu_button("Export", |ui: &mut Ui| {
+            if ui
+                .add_enabled(
+                    state.tab.dataset.is_some(),
+                    egui::Button::new("Save plot as PNG…"),
+                )
+                .clicked()
+            {
+                save_png_dialog(ui.ctx(), state);
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    state.tab.dataset.is_some()
+                        && state.plot_view == PlotView::Lines
+                        && !state.error_bars,
+                    egui::Button::new("Save high-resolution PNG…"),
+                )
+                .on_hover_text("Render the spectra at a chosen size and resolution")
+                .clicked()
+            {
+                state.png_dialog_open = true;
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    state.tab.dataset.is_some(),
+                    egui::Button::new("Copy plot").shortcut_text("Ctrl+C"),
+                )
+                .on_hover_text("Copy the plot to the clipboard as an image")
+                .clicked()
+            {
+                copy_plot(ui.ctx(), state);
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    state.tab.dataset.is_some(),
+                    egui::Button::new("Export visible to Parquet…"),
+                )
+                .clicked()
+            {
+                export_parquet_dialog(state);
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    !state.tab.box_selection.is_empty(),
+                    egui::Button::new("Export selection to Parquet…"),
+                )
+                .on_disabled_hover_text("Select spectra with Box select first")
+                .clicked()
+            {
+                export_selection_dialog(state);
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    state
+                        .tab
+                        .dataset
+                        .as_ref()
+                        .is_some_and(|ds| ds.lazy.is_none())
+                        && state.tab.color_column.is_some(),
+                    egui::Button::new("Export group means…"),
+                )
+                .on_disabled_hover_text(
+                    "Choose a colour column to group by; not available for a lazily loaded file",
+                )
+                .clicked()
+            {
+                export_group_means_dialog(state);
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(
+                    state.tab.color_map.is_some(),
+                    egui::Button::new("Export legend…"),
+                )
+                .on_hover_text("Save the colour legend on its own as a PNG")
+                .on_disabled_hover_text("Choose a colour column first")
+                .clicked()
+            {
+                export_legend_dialog(state);
+                ui.close_menu();
+            }
+        });
+
+        ui.menu_button("View", |ui: &mut Ui| {
+            let mut reverse_x = state.reverse_x;
+            if ui
+                .checkbox(&mut reverse_x, "Reverse x axis")
+                .on_hover_text("Wavenumber decreasing left to right")
+                .changed()
+            {
+                state.set_reverse_x(reverse_x);
+            }
+            ui.separator();
+            ui.label("Axis ranges (unchecked = auto)");
+            let view = state.tab.view_bounds;
+            if let Some(b) = axis_lock_row(ui, "x", state.x_bounds, view.map(|v| v.x)) {
+                state.set_x_bounds(b);
+            }
+            if let Some(b) = axis_lock_row(ui, "y", state.y_bounds, view.map(|v| v.y)) {
+                state.set_y_bounds(b);
+            }
+            ui.separator();
+            ui.checkbox(&mut state.show_peaks, "Mark peaks")
+                .on_hover_text("Label the peaks of the spectrum under the pointer");
+            ui.add_enabled_ui(state.show_peaks, |ui: &mut Ui| {
+                let params = &mut state.peak_params;
+                ui.add(
+                    egui::Slider::new(&mut params.min_prominence, 0.0..=1.0)
+                        .text("min prominence")
+                        .custom_formatter(|v, _| format!("{:.0}%", v * 100.0)),
+                )
+                .on_hover_text("Fraction of the spectrum's y range a peak must rise above its surroundings");
+                ui.add(
+                    egui::DragValue::new(&mut params.min_distance)
+                        .range(1..=1000)
+                        .prefix("min spacing ")
+                        .suffix(" pts"),
+                );
+            });
+            ui.separator();
+            ui.add(
+                egui::DragValue::new(&mut state.render_limit)
+                    .range(100..=1_000_000)
+                    .speed(50)
+                    .prefix("Draw at most ")
+                    .suffix(" lines"),
+            )
+            .on_hover_text("Larger selections are drawn as a random sample of this many spectra");
+            ui.separator();
+            ui.horizontal(|ui: &mut Ui| {
+                ui.add(
+                    egui::DragValue::new(&mut state.value_precision)
+                        .range(0..=12)
+                        .suffix(" decimals"),
+                )
+                .on_hover_text("Decimals of float metadata values in the panels and tooltips");
+                ui.checkbox(&mut state.scientific_values, "Scientific");
+            });
+        });
+
+        ui.menu_button("Preprocess", |ui: &mut Ui| {
+            preprocess_menu(ui, state);
         });
 
         ui.separator();
 
-        if let Some(ds) = &state.dataset {
+        if let Some(pending) = &state.pending_load {
+            ui.spinner();
+            ui.add(
+                egui::ProgressBar::new(pending.progress() as f32)
+                    .desired_width(120.0)
+                    .show_percentage(),
+            );
+        } else if let Some(name) = state.source_name() {
+            ui.strong(name);
+        }
+
+        if let Some(ds) = &state.tab.dataset {
             ui.label(format!(
                 "{} spectra loaded, {} visible",
                 ds.len(),
-                state.visible_indices.len()
+                state.tab.visible_indices.len()
             ));
         }
 
         ui.separator();
 
+        for view in PlotView::ALL {
+            ui.selectable_value(&mut state.plot_view, view, view.label());
+        }
+        if state.plot_view == PlotView::Lines
+            && ui
+                .add_enabled(state.tab.dataset.is_some(), egui::Button::new("Reset view"))
+                .on_hover_text("Fit the plot to the visible spectra (R)")
+                .clicked()
+        {
+            state.reset_view();
+        }
+        if state.plot_view == PlotView::Lines {
+            axis_lock_inputs(ui, state);
+            render_limit_inputs(ui, state);
+        }
+        if matches!(state.plot_view, PlotView::Heatmap | PlotView::Correlation) {
+            egui::ComboBox::from_id_salt("heatmap_palette")
+                .selected_text(state.heatmap_palette.label())
+                .show_ui(ui, |ui: &mut Ui| {
+                    for kind in PaletteKind::ALL {
+                        ui.selectable_value(&mut state.heatmap_palette, kind, kind.label());
+                    }
+                })
+                .response
+                .on_hover_text("Colour scale of the image");
+        }
+
+        ui.separator();
+
+        ui.label("Normalization");
+        egui::ComboBox::from_id_salt("normalization")
+            .selected_text(state.normalization.label())
+            .show_ui(ui, |ui: &mut Ui| {
+                for mode in Normalization::ALL {
+                    let picked = ui
+                        .selectable_value(&mut state.normalization, mode, mode.label())
+                        .changed();
+                    if picked && mode == Normalization::Area {
+                        state.warn_non_positive_areas();
+                    }
+                }
+            });
+
+        ui.label("Derivative");
+        egui::ComboBox::from_id_salt("derivative")
+            .selected_text(derivative_label(state.derivative_order))
+            .show_ui(ui, |ui: &mut Ui| {
+                for order in 0..=2 {
+                    let picked = ui
+                        .selectable_value(
+                            &mut state.derivative_order,
+                            order,
+                            derivative_label(order),
+                        )
+                        .changed();
+                    if picked && order > 0 {
+                        state.warn_irregular_sampling("the derivative's stencil");
+                    }
+                }
+            });
+
+        let mut waterfall = state.plot_mode == PlotMode::Waterfall;
         if ui
-            .selectable_label(state.minmax_scaling, "Min-Max Scaling")
-            .clicked()
+            .checkbox(&mut waterfall, "Waterfall")
+            .on_hover_text("Offset successive spectra vertically, ordered by the colour column")
+            .changed()
         {
-            state.minmax_scaling = !state.minmax_scaling;
+            state.plot_mode = if waterfall {
+                PlotMode::Waterfall
+            } else {
+                PlotMode::Overlay
+            };
+        }
+        if state.plot_mode == PlotMode::Waterfall {
+            ui.add(egui::Slider::new(&mut state.waterfall_spacing, 0.0..=2.0).text("offset"));
+        }
+
+        region_inputs(ui, state);
+        box_select_inputs(ui, state);
+
+        ui.checkbox(&mut state.log_y, "Log y").on_hover_text(
+            "Plot log10 of the intensities, taken after normalization. \
+             Values ≤ 0 (e.g. each spectrum's minimum under min-max scaling) are left out",
+        );
+
+        line_inputs(
+            ui,
+            "line_style",
+            &mut state.line_width,
+            &mut state.line_style,
+        )
+        .on_hover_text("Width and style of the individual traces");
+
+        egui::ComboBox::from_id_salt("plot_theme")
+            .width(90.0)
+            .selected_text(state.plot_theme.label())
+            .show_ui(ui, |ui: &mut Ui| {
+                for theme in PlotTheme::ALL {
+                    ui.selectable_value(&mut state.plot_theme, theme, theme.label());
+                }
+            })
+            .response
+            .on_hover_text("Theme and plot background; Transparent exports PNGs with alpha");
+        if state.plot_theme.is_light() {
+            ui.checkbox(&mut state.darken_light_lines, "Darken light lines")
+                .on_hover_text("Draw pale line colours darker so they show on white");
+        }
+
+        ui.checkbox(&mut state.show_group_means, "Group means")
+            .on_hover_text("Draw each colour group's mean spectrum over the traces");
+        if state.show_group_means {
+            line_inputs(
+                ui,
+                "mean_line_style",
+                &mut state.mean_line_width,
+                &mut state.mean_line_style,
+            )
+            .on_hover_text("Width and style of the mean lines");
+        }
+
+        ui.checkbox(&mut state.show_std_band, "±1σ band")
+            .on_hover_text("Shade one standard deviation around each colour group's mean");
+
+        ui.checkbox(&mut state.error_bars, "Error bars")
+            .on_hover_text("Show each colour group as its mean with ±1σ error bars");
+        if state.error_bars {
+            ui.add(
+                egui::DragValue::new(&mut state.error_bar_spacing)
+                    .range(1..=500)
+                    .prefix("every ")
+                    .suffix(" pts"),
+            );
         }
 
-        if let Some(msg) = &state.status_message {
+        ui.checkbox(&mut state.highlight_outliers, "Outliers")
+            .on_hover_text(
+                "Draw in red the spectra whose RMS distance from their colour group's mean \
+                 is unusually large for the group",
+            );
+        if state.highlight_outliers {
+            ui.add(
+                egui::DragValue::new(&mut state.outlier_sigma)
+                    .range(0.0..=10.0)
+                    .speed(0.05)
+                    .prefix("> mean + ")
+                    .suffix(" σ"),
+            )
+            .on_hover_text("Threshold on the distance, within each group");
+        }
+
+        if let Some(msg) = &state.tab.status_message {
             ui.label(RichText::new(msg).color(Color32::RED));
         }
+        if let Some((msg, _)) = &state.status_flash {
+            ui.label(msg);
+        }
+        if let Some((pos, n)) = state.focus_position() {
+            ui.label(format!("{} / {n}", pos + 1)).on_hover_text(
+                "Stepping through the visible spectra: ← and → move, Esc shows them all",
+            );
+        }
     });
+
+    if state.tabs.len() > 1 || state.tab.dataset.is_some() {
+        tab_strip(ui, state);
+    }
 }
 
-// ---------------------------------------------------------------------------
-// File dialog
-// ---------------------------------------------------------------------------
+/// One tab per open dataset, with a button closing it. Tabs stay put while
+/// a file loads, as the load is installed into the active tab.
+fn tab_strip(ui: &mut Ui, state: &mut AppState) {
+    let mut activate = None;
+    let mut close = None;
+    ui.add_enabled_ui(!state.loading, |ui: &mut Ui| {
+        ui.horizontal_wrapped(|ui: &mut Ui| {
+            for i in 0..state.tabs.len() {
+                let mut tab = ui.selectable_label(i == state.active_tab, state.tab_name(i));
+                if let Some(path) = state.tab_path(i) {
+                    tab = tab.on_hover_text(path.display().to_string());
+                }
+                if tab.clicked() {
+                    activate = Some(i);
+                }
+                if ui
+                    .small_button("×")
+                    .on_hover_text("Close this tab")
+                    .clicked()
+                {
+                    close = Some(i);
+                }
+                ui.separator();
+            }
+            if state.tabs.len() > 1 {
+                overlay_selector(ui, state);
+            }
+        });
+    });
+    if let Some(i) = activate {
+        state.switch_tab(i);
+    }
+    if let Some(i) = close {
+        state.close_tab(i);
+    }
+}
 
-pub fn open_file_dialog(state: &mut AppState) {
-    let file = rfd::FileDialog::new()
-        .set_title("Open spectral data")
-        .add_filter("Supported files", &["parquet", "pq", "json", "csv"])
-        .add_filter("Parquet", &["parquet", "pq"])
-        .add_filter("JSON", &["json"])
+/// Choose another tab to draw dashed under the active one.
+fn overlay_selector(ui: &mut Ui, state: &mut AppState) {
+    ui.label("Overlay");
+    let selected = match state.overlay_tab {
+        Some(i) => state.tab_name(i),
+        None => "None".to_string(),
+    };
+    let others: Vec<(usize, String)> = (0..state.tabs.len())
+        .filter(|&i| i != state.active_tab)
+        .map(|i| (i, state.tab_name(i)))
+        .collect();
+    egui::ComboBox::from_id_salt("overlay_tab")
+        .selected_text(selected)
+        .show_ui(ui, |ui: &mut Ui| {
+            ui.selectable_value(&mut state.overlay_tab, None, "None");
+            for (i, name) in others {
+                ui.selectable_value(&mut state.overlay_tab, Some(i), name);
+            }
+        })
+        .response
+        .on_hover_text("Draw another tab's filtered spectra dashed under this one");
+}
+
+/// Width and dash pattern of one kind of plotted line.
+fn line_inputs(ui: &mut Ui, id: &str, width: &mut f32, style: &mut LineStyle) -> egui::Response {
+    ui.horizontal(|ui: &mut Ui| {
+        ui.add(
+            egui::DragValue::new(width)
+                .range(0.1..=10.0)
+                .speed(0.05)
+                .max_decimals(1)
+                .suffix(" px"),
+        );
+        egui::ComboBox::from_id_salt(id)
+            .width(70.0)
+            .selected_text(style.label())
+            .show_ui(ui, |ui: &mut Ui| {
+                for option in LineStyle::ALL {
+                    ui.selectable_value(style, option, option.label());
+                }
+            });
+    })
+    .response
+}
+
+/// Toggle for box select mode plus, once spectra are selected, their
+/// count and what to do with them.
+fn box_select_inputs(ui: &mut Ui, state: &mut AppState) {
+    ui.toggle_value(&mut state.box_select, "Box select")
+        .on_hover_text("Drag on the plot to select the spectra passing through a box");
+    if state.tab.box_selection.is_empty() {
+        return;
+    }
+    ui.label(format!("{} selected", state.tab.box_selection.len()));
+    let mut isolate = state.tab.isolate_box_selection;
+    if ui
+        .checkbox(&mut isolate, "Isolate")
+        .on_hover_text("Show only the selected spectra")
+        .changed()
+    {
+        state.set_isolate_box_selection(isolate);
+    }
+    if ui
+        .small_button("Export…")
+        .on_hover_text("Write the selected spectra to Parquet")
+        .clicked()
+    {
+        export_selection_dialog(state);
+    }
+    if ui.small_button("Clear").clicked() {
+        state.clear_box_selection();
+    }
+}
+
+/// Checkbox enabling the x region plus its bounds.
+fn region_inputs(ui: &mut Ui, state: &mut AppState) {
+    let mut enabled = state.region.is_some();
+    if ui
+        .checkbox(&mut enabled, "Region")
+        .on_hover_text("Only plot and export points with x in this range")
+        .changed()
+    {
+        // Start from the full x extent of the data, or of the plotted
+        // spectra if the file is loaded lazily.
+        let extent = state.tab.dataset.as_ref().and_then(|ds| {
+            let indices: Vec<usize> = match ds.lazy {
+                Some(_) => state.rendered_indices(),
+                None => (0..ds.len()).collect(),
+            };
+            let spectra = ds.spectra_at(&indices);
+            let x = spectra.iter().flat_map(|sp| sp.x.iter().copied());
+            let (lo, hi) = x.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            });
+            (lo <= hi).then_some((lo, hi))
+        });
+        state.region = enabled.then(|| extent.unwrap_or((800.0, 1800.0)));
+    }
+    if let Some((lo, hi)) = &mut state.region {
+        ui.add(egui::DragValue::new(lo).speed(1.0));
+        ui.label("–");
+        ui.add(egui::DragValue::new(hi).speed(1.0));
+    }
+}
+
+/// Lock both plot axes to typed-in ranges, e.g. to compare datasets at the
+/// same scale. View → Axis ranges locks each axis on its own.
+fn axis_lock_inputs(ui: &mut Ui, state: &mut AppState) {
+    let view = state.tab.view_bounds;
+    let ranges = state
+        .x_bounds
+        .or(view.map(|v| v.x))
+        .zip(state.y_bounds.or(view.map(|v| v.y)));
+    let mut locked = state.x_bounds.is_some() || state.y_bounds.is_some();
+    let toggled = ui
+        .add_enabled(
+            ranges.is_some(),
+            egui::Checkbox::new(&mut locked, "Lock axes"),
+        )
+        .on_hover_text("Keep the plot at these x and y ranges instead of auto-fitting")
+        .changed();
+    let Some((mut x, mut y)) = ranges else {
+        return;
+    };
+    if toggled {
+        state.set_x_bounds(locked.then_some(x));
+        state.set_y_bounds(locked.then_some(y));
+    }
+    if !locked {
+        return;
+    }
+
+    let mut changed = false;
+    for (range, axis) in [(&mut x, "x"), (&mut y, "y")] {
+        let speed = ((range[1] - range[0]).abs() / 200.0).max(1e-6);
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut range[0])
+                    .speed(speed)
+                    .prefix(format!("{axis} ")),
+            )
+            .changed();
+        ui.label("–");
+        changed |= ui
+            .add(egui::DragValue::new(&mut range[1]).speed(speed))
+            .changed();
+    }
+    if changed {
+        state.set_x_bounds(Some([x[0].min(x[1]), x[0].max(x[1])]));
+        state.set_y_bounds(Some([y[0].min(y[1]), y[0].max(y[1])]));
+    }
+}
+
+/// Say when only a sample of the plotted spectra is drawn, with a button to
+/// draw them all (or go back to the sample).
+fn render_limit_inputs(ui: &mut Ui, state: &mut AppState) {
+    let n_plotted = state.plotted_indices().len();
+    let lazy = state
+        .tab
+        .dataset
+        .as_ref()
+        .is_some_and(|ds| ds.lazy.is_some());
+    let limit = if lazy {
+        state.render_limit.min(LAZY_CACHE_SPECTRA)
+    } else {
+        state.render_limit
+    };
+    if n_plotted <= limit {
+        return;
+    }
+    if lazy {
+        ui.label(
+            RichText::new(format!("Showing {limit} of {n_plotted}"))
+                .color(ui.visuals().warn_fg_color),
+        )
+        .on_hover_text(
+            "A random sample of the plotted spectra; a lazily loaded file is never drawn in full",
+        );
+        return;
+    }
+    if state.tab.render_all {
+        if ui
+            .small_button("Sample")
+            .on_hover_text(format!(
+                "Draw a random sample of {} spectra",
+                state.render_limit
+            ))
+            .clicked()
+        {
+            state.tab.render_all = false;
+        }
+        return;
+    }
+    ui.label(
+        RichText::new(format!("Showing {} of {n_plotted}", state.render_limit))
+            .color(ui.visuals().warn_fg_color),
+    )
+    .on_hover_text("A random sample of the plotted spectra; set the limit under View");
+    if ui
+        .small_button("Render all")
+        .on_hover_text("Draw every plotted spectrum; may be slow")
+        .clicked()
+    {
+        state.tab.render_all = true;
+    }
+}
+
+/// Combo box text for a derivative order.
+fn derivative_label(order: u8) -> &'static str {
+    match order {
+        1 => "1st",
+        2 => "2nd",
+        _ => "None",
+    }
+}
+
+/// Parquet table layout choice, with column names for the long layout.
+/// Returns whether the layout changed.
+fn layout_options(ui: &mut Ui, layout: &mut TableLayout) -> bool {
+    ui.label("Parquet layout");
+    let mut long = matches!(layout, TableLayout::Long { .. });
+    let mut changed = false;
+    if ui
+        .radio_value(&mut long, false, "Wide (list columns)")
+        .changed()
+        | ui.radio_value(&mut long, true, "Long (one point per row)")
+            .changed()
+    {
+        *layout = if long {
+            TableLayout::default_long()
+        } else {
+            TableLayout::Wide
+        };
+        changed = true;
+    }
+    if let TableLayout::Long {
+        id_col,
+        x_col,
+        y_col,
+    } = layout
+    {
+        egui::Grid::new("long_layout_columns")
+            .num_columns(2)
+            .show(ui, |ui: &mut Ui| {
+                for (label, name) in [("Id", id_col), ("x", x_col), ("y", y_col)] {
+                    ui.label(label);
+                    changed |= ui
+                        .add(egui::TextEdit::singleline(name).desired_width(120.0))
+                        .lost_focus();
+                    ui.end_row();
+                }
+            });
+    }
+    changed
+}
+
+/// Field, list and decimal separator pickers for CSV files; true if one
+/// changed.
+fn csv_dialect_options(ui: &mut Ui, dialect: &mut CsvDialect) -> bool {
+    ui.label("CSV dialect");
+    let mut changed = false;
+    egui::Grid::new("csv_dialect")
+        .num_columns(2)
+        .show(ui, |ui: &mut Ui| {
+            ui.label("Fields");
+            let selected = dialect.field_delim.map_or_else(
+                || "Auto".to_string(),
+                |c| CsvDialect::name(&CsvDialect::FIELD_DELIMS, c),
+            );
+            egui::ComboBox::from_id_salt("csv_field_delim")
+                .selected_text(selected)
+                .show_ui(ui, |ui: &mut Ui| {
+                    changed |= ui
+                        .selectable_value(&mut dialect.field_delim, None, "Auto")
+                        .on_hover_text("Most frequent separator in the header line")
+                        .changed();
+                    for (c, name) in CsvDialect::FIELD_DELIMS {
+                        changed |= ui
+                            .selectable_value(&mut dialect.field_delim, Some(c), name)
+                            .changed();
+                    }
+                });
+            ui.end_row();
+            for (label, value, options) in [
+                (
+                    "x/y lists",
+                    &mut dialect.list_delim,
+                    &CsvDialect::LIST_DELIMS[..],
+                ),
+                ("Decimal", &mut dialect.decimal, &CsvDialect::DECIMALS[..]),
+            ] {
+                ui.label(label);
+                egui::ComboBox::from_id_salt(label)
+                    .selected_text(CsvDialect::name(options, *value))
+                    .show_ui(ui, |ui: &mut Ui| {
+                        for &(c, name) in options {
+                            changed |= ui.selectable_value(value, c, name).changed();
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+    changed
+}
+
+/// Bin width, in x units, when binning is first switched on.
+const DEFAULT_BIN_WIDTH: f64 = 4.0;
+
+/// Controls for the preprocessing applied to spectra before display.
+fn preprocess_menu(ui: &mut Ui, state: &mut AppState) {
+    let n_unordered = state.tab.dataset.as_ref().map_or(0, |ds| ds.n_unordered_x);
+    if ui
+        .add_enabled(n_unordered > 0, egui::Button::new("Fix axis ordering"))
+        .on_hover_text("Sort every spectrum's points by x")
+        .on_disabled_hover_text("All spectra have ordered x values")
+        .clicked()
+    {
+        state.fix_axis_ordering();
+        ui.close_menu();
+    }
+    let n_invalid = state.tab.dataset.as_ref().map_or(0, |ds| ds.n_invalid);
+    ui.add_enabled_ui(n_invalid > 0, |ui: &mut Ui| {
+        ui.menu_button("Clean invalid values", |ui: &mut Ui| {
+            for mode in CleanMode::ALL {
+                if ui.button(mode.label()).clicked() {
+                    state.clean_invalid(mode);
+                    ui.close_menu();
+                }
+            }
+        })
+        .response
+        .on_hover_text(format!(
+            "{n_invalid} spectra contain NaN or infinite values"
+        ))
+        .on_disabled_hover_text("No spectra contain NaN or infinite values");
+    });
+    let n_duplicates = state.tab.dataset.as_ref().map_or(0, |ds| ds.n_duplicates);
+    if ui
+        .add_enabled(n_duplicates > 0, egui::Button::new("Remove duplicates"))
+        .on_hover_text(format!(
+            "Drop {n_duplicates} spectra identical to an earlier one in x, y and metadata"
+        ))
+        .on_disabled_hover_text("No spectra are exact duplicates")
+        .clicked()
+    {
+        state.remove_duplicates();
+        ui.close_menu();
+    }
+    ui.separator();
+    let mut baseline = state.baseline.is_some();
+    if ui
+        .checkbox(&mut baseline, "ALS baseline correction")
+        .on_hover_text("Subtract an asymmetric least squares baseline")
+        .changed()
+    {
+        state.baseline = baseline.then(AlsParams::default);
+    }
+    if let Some(p) = &mut state.baseline {
+        ui.horizontal(|ui: &mut Ui| {
+            ui.label("λ");
+            ui.add(egui::Slider::new(&mut p.lambda, 1.0..=1e9).logarithmic(true));
+            ui.label("p");
+            ui.add(
+                egui::DragValue::new(&mut p.p)
+                    .range(0.0001..=0.5)
+                    .speed(0.001),
+            );
+            ui.label("Iterations");
+            ui.add(egui::DragValue::new(&mut p.iters).range(1..=50));
+        });
+    }
+    ui.separator();
+
+    anchor_baseline_options(ui, state);
+    ui.separator();
+
+    let mut offset = state.baseline_point.is_some();
+    if ui
+        .checkbox(&mut offset, "Zero at reference point")
+        .on_hover_text("Subtract each spectrum's intensity at the point nearest this x (Shift+click the plot to set it)")
+        .changed()
+    {
+        // Start from the middle of the visible x range.
+        let centre = state.tab.view_bounds.map_or(0.0, |v| (v.x[0] + v.x[1]) / 2.0);
+        state.baseline_point = offset.then_some(centre);
+    }
+    if let Some(ref_x) = &mut state.baseline_point {
+        ui.horizontal(|ui: &mut Ui| {
+            ui.label("x");
+            ui.add(egui::DragValue::new(ref_x).speed(1.0));
+        });
+    }
+    ui.separator();
+
+    let mut smooth = state.smoothing.is_some();
+    if ui
+        .checkbox(&mut smooth, "Savitzky–Golay smoothing")
+        .changed()
+    {
+        state.smoothing = smooth.then(SavGolParams::default);
+    }
+    if let Some(p) = &mut state.smoothing {
+        ui.horizontal(|ui: &mut Ui| {
+            ui.label("Window");
+            ui.add(egui::DragValue::new(&mut p.window).range(3..=201));
+            ui.label("Order");
+            ui.add(egui::DragValue::new(&mut p.poly_order).range(0..=10));
+        });
+        // Keep the window odd and larger than the polynomial order.
+        p.window = p.window.max(p.poly_order + 1) | 1;
+    }
+    ui.separator();
+
+    let mut binned = state.binning.is_some();
+    if ui
+        .checkbox(&mut binned, "Bin spectra")
+        .on_hover_text("Average the points of each fixed-width x bin into one")
+        .changed()
+    {
+        state.binning = binned.then_some(DEFAULT_BIN_WIDTH);
+        if binned {
+            state.warn_irregular_sampling("the number of points per bin");
+        }
+    }
+    if let Some(width) = &mut state.binning {
+        ui.horizontal(|ui: &mut Ui| {
+            ui.label("Bin width");
+            ui.add(
+                egui::DragValue::new(width)
+                    .range(1e-6..=f64::MAX)
+                    .speed(0.1),
+            );
+        });
+    }
+}
+
+/// Anchor list and subtraction toggle for the linear baseline.
+fn anchor_baseline_options(ui: &mut Ui, state: &mut AppState) {
+    let enough = state.baseline_anchors.len() >= 2;
+    ui.add_enabled(
+        enough,
+        egui::Checkbox::new(&mut state.subtract_anchors, "Linear baseline"),
+    )
+    .on_hover_text("Subtract the piecewise-linear baseline through the anchors")
+    .on_disabled_hover_text("Ctrl+click the plot to add at least two anchors");
+    if !enough {
+        state.subtract_anchors = false;
+    }
+
+    let mut removed = None;
+    ui.horizontal_wrapped(|ui: &mut Ui| {
+        ui.label("Anchors");
+        for (i, x) in state.baseline_anchors.iter().enumerate() {
+            if ui
+                .small_button(format!("{x:.1} ×"))
+                .on_hover_text("Remove anchor")
+                .clicked()
+            {
+                removed = Some(i);
+            }
+        }
+        if state.baseline_anchors.is_empty() {
+            ui.weak("Ctrl+click the plot");
+        } else if ui.small_button("Clear").clicked() {
+            state.baseline_anchors.clear();
+        }
+    });
+    if let Some(i) = removed {
+        state.baseline_anchors.remove(i);
+    }
+}
+
+/// Lock checkbox plus min/max inputs for one axis.
+/// Returns the new lock when the user changed it.
+fn axis_lock_row(
+    ui: &mut Ui,
+    axis: &str,
+    current: Option<[f64; 2]>,
+    view: Option<[f64; 2]>,
+) -> Option<Option<[f64; 2]>> {
+    let mut locked = current.is_some();
+    let [mut lo, mut hi] = current.or(view).unwrap_or([0.0, 1.0]);
+    let mut changed = false;
+
+    ui.horizontal(|ui: &mut Ui| {
+        changed |= ui.checkbox(&mut locked, format!("Lock {axis}")).changed();
+        ui.add_enabled_ui(locked, |ui: &mut Ui| {
+            let speed = ((hi - lo).abs() / 200.0).max(1e-6);
+            changed |= ui.add(egui::DragValue::new(&mut lo).speed(speed)).changed();
+            ui.label("–");
+            changed |= ui.add(egui::DragValue::new(&mut hi).speed(speed)).changed();
+        });
+    });
+
+    if !changed {
+        return None;
+    }
+    Some(locked.then(|| [lo.min(hi), lo.max(hi)]))
+}
+
+// ---------------------------------------------------------------------------
+// Active filter chips
+// ---------------------------------------------------------------------------
+
+/// Maximum number of values listed on a chip before summarising the rest.
+const CHIP_MAX_VALUES: usize = 3;
+
+/// Render one removable chip per column whose filter is not "all selected".
+pub fn filter_chips(ui: &mut Ui, state: &mut AppState) {
+    let Some(ds) = &state.tab.dataset else {
+        return;
+    };
+
+    let chips: Vec<(String, String)> = active_filters(ds, &state.tab.filters)
+        .into_iter()
+        .map(|(col, filter)| {
+            let FilterKind::Values(selected) = filter else {
+                return (col.to_string(), filter.to_string());
+            };
+            let mut values: Vec<String> = selected
+                .iter()
+                .take(CHIP_MAX_VALUES)
+                .map(|v| state.format_value(v))
+                .collect();
+            if selected.len() > CHIP_MAX_VALUES {
+                values.push(format!("+{} more", selected.len() - CHIP_MAX_VALUES));
+            }
+            if values.is_empty() {
+                values.push("none".to_string());
+            }
+            (col.to_string(), values.join(", "))
+        })
+        .collect();
+
+    let metric_chips: Vec<(DerivedMetric, String)> = state
+        .tab
+        .metric_filters
+        .iter()
+        .map(|(metric, range)| {
+            let bounds = FilterKind::NumericRange(range.clone()).to_string();
+            (*metric, format!("{}: {bounds}", metric.label()))
+        })
+        .collect();
+
+    let mut cleared = None;
+    let mut cleared_metric = None;
+    ui.horizontal_wrapped(|ui: &mut Ui| {
+        for (col, values) in &chips {
+            egui::Frame::group(ui.style())
+                .inner_margin(egui::Margin::symmetric(6, 2))
+                .show(ui, |ui: &mut Ui| {
+                    ui.label(format!("{col}: {values}"));
+                    if ui.small_button("×").on_hover_text("Clear filter").clicked() {
+                        cleared = Some(col.clone());
+                    }
+                });
+        }
+        for (metric, text) in &metric_chips {
+            egui::Frame::group(ui.style())
+                .inner_margin(egui::Margin::symmetric(6, 2))
+                .show(ui, |ui: &mut Ui| {
+                    ui.label(text);
+                    if ui.small_button("×").on_hover_text("Clear filter").clicked() {
+                        cleared_metric = Some(*metric);
+                    }
+                });
+        }
+    });
+
+    if let Some(col) = cleared {
+        state.select_all(&col);
+    }
+    if let Some(metric) = cleared_metric {
+        state.remove_metric_filter(metric);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Legend (right panel)
+// ---------------------------------------------------------------------------
+
+/// List each colour group with its swatch and number of visible spectra.
+/// The checkbox hides the group from the plot without filtering it out;
+/// clicking the label highlights the group.
+pub fn legend_panel(ui: &mut Ui, state: &mut AppState) {
+    let (Some(ds), Some(col), Some(cm)) = (
+        &state.tab.dataset,
+        &state.tab.color_column,
+        &state.tab.color_map,
+    ) else {
+        return;
+    };
+    let counts = group_indices(ds, &state.tab.visible_indices, Some(col));
+    let known = ds.unique_values.get(col).cloned().unwrap_or_default();
+    let mut values = known.clone();
+    values.extend(counts.keys().cloned());
+
+    // Spectra lacking the column form the Null group, drawn in the plot's
+    // fallback colour.
+    let mut values: Vec<MetadataValue> = values.into_iter().collect();
+    values.sort_by(|a, b| a.custom_cmp(b, state.value_order(col)));
+    let rows: Vec<(MetadataValue, Color32, usize)> = values
+        .into_iter()
+        .map(|v| {
+            let color = if known.contains(&v) {
+                cm.color_for(&v)
+            } else {
+                Color32::LIGHT_BLUE
+            };
+            let n = counts.get(&v).map_or(0, Vec::len);
+            (v, color, n)
+        })
+        .collect();
+
+    let overlay = state.overlay_tab.zip(state.overlay());
+    if overlay.is_some() {
+        ui.label(format!("{} (solid)", state.tab_name(state.active_tab)));
+    }
+    ui.strong(col);
+    ui.separator();
+    let mut toggled = None;
+    let mut highlighted = None;
+    ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .show(ui, |ui: &mut Ui| {
+            for (value, color, n) in &rows {
+                ui.horizontal(|ui: &mut Ui| {
+                    let mut shown = !state.tab.hidden_groups.contains(value);
+                    if ui
+                        .checkbox(&mut shown, "")
+                        .on_hover_text("Show in plot")
+                        .changed()
+                    {
+                        toggled = Some(value.clone());
+                    }
+                    ui.label(RichText::new("■").color(*color));
+                    let is_highlighted = state.tab.highlighted_group.as_ref() == Some(value);
+                    if ui
+                        .selectable_label(
+                            is_highlighted,
+                            format!("{} ({n})", state.format_value(value)),
+                        )
+                        .on_hover_text("Highlight this group")
+                        .clicked()
+                    {
+                        highlighted = Some(value.clone());
+                    }
+                });
+            }
+
+            // The overlay tab's colours, marked with a dashed swatch.
+            let Some((i, tab)) = overlay else {
+                return;
+            };
+            ui.separator();
+            ui.label(format!("{} (dashed)", state.tab_name(i)));
+            let (Some(col), Some(cm)) = (tab.color_column(), tab.color_map()) else {
+                return;
+            };
+            ui.strong(col);
+            for (label, color) in cm.legend_entries() {
+                ui.horizontal(|ui: &mut Ui| {
+                    ui.label(RichText::new("╌").strong().color(color));
+                    ui.label(label);
+                });
+            }
+        });
+
+    if let Some(value) = toggled {
+        state.toggle_group_hidden(&value);
+    }
+    if let Some(value) = highlighted {
+        state.toggle_group_highlight(&value);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Selected spectrum detail (bottom panel)
+// ---------------------------------------------------------------------------
+
+/// List the full metadata, point count and x range of the spectrum pinned
+/// by clicking its line.
+pub fn selection_panel(ui: &mut Ui, state: &mut AppState) {
+    let (Some(ds), Some(idx)) = (&state.tab.dataset, state.tab.selected_index) else {
+        return;
+    };
+    if idx >= ds.len() {
+        return;
+    }
+    let sp = ds.spectrum(idx);
+
+    let mut close = false;
+    ui.horizontal(|ui: &mut Ui| {
+        ui.strong(format!("Spectrum {idx}"));
+        let n_points = sp.x.len().min(sp.y.len());
+        let x_min = sp.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let x_max = sp.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if n_points > 0 {
+            ui.label(format!("{n_points} points, x {x_min:.2} – {x_max:.2}"));
+            if let Some(step) = sp.x_step() {
+                ui.label(format!("step {step:.3}"));
+            }
+            if sp.x_irregular() {
+                ui.label(RichText::new("irregular").color(Color32::YELLOW))
+                    .on_hover_text(format!(
+                        "The x spacing varies by more than {}% of the median step; \
+                         derivatives and binning follow the uneven spacing",
+                        IRREGULAR_X_TOLERANCE * 100.0
+                    ));
+            }
+        } else {
+            ui.label("no points");
+        }
+        ui.with_layout(
+            egui::Layout::right_to_left(egui::Align::Center),
+            |ui: &mut Ui| {
+                close = ui
+                    .small_button("×")
+                    .on_hover_text("Clear selection")
+                    .clicked();
+            },
+        );
+    });
+    ScrollArea::vertical()
+        .max_height(160.0)
+        .show(ui, |ui: &mut Ui| {
+            egui::Grid::new("selected_metadata")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui: &mut Ui| {
+                    for (key, value) in &sp.metadata {
+                        ui.strong(key);
+                        ui.label(state.format_value(value));
+                        ui.end_row();
+                    }
+                });
+        });
+
+    if close {
+        state.tab.selected_index = None;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// File dialog
+// ---------------------------------------------------------------------------
+
+pub fn open_file_dialog(state: &mut AppState) {
+    if let Some(path) = pick_data_file() {
+        load_path(state, path);
+    }
+}
+
+/// Pick a file and add its spectra to the current dataset.
+pub fn append_file_dialog(state: &mut AppState) {
+    if let Some(path) = pick_data_file() {
+        spawn_load(state, vec![path], LoadMode::Append);
+    }
+}
+
+/// Ask for a spectral data file to open.
+fn pick_data_file() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .set_title("Open spectral data")
+        .add_filter(
+            "Supported files",
+            &["parquet", "pq", "json", "csv", "gz", "jdx", "dx", "xlsx", "mat", "h5", "hdf5"],
+        )
+        .add_filter("Parquet", &["parquet", "pq"])
+        .add_filter("JSON", &["json", "gz"])
+        .add_filter("CSV", &["csv", "gz"])
+        .add_filter("JCAMP-DX", &["jdx", "dx"])
+        .add_filter("Excel", &["xlsx"])
+        .add_filter("MATLAB", &["mat"])
+        .add_filter("HDF5", &["h5", "hdf5"])
+        .pick_file()
+}
+
+/// Dim the window and invite a drop while files are dragged over it.
+pub fn drop_overlay(ctx: &egui::Context) {
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("drop_overlay"),
+    ));
+    let rect = ctx.screen_rect();
+    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+    painter.text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        "Drop file here",
+        egui::FontId::proportional(28.0),
+        egui::Color32::WHITE,
+    );
+}
+
+/// One entry per recent file; a file that no longer exists is reported and
+/// removed from the list.
+fn recent_files_menu(ui: &mut Ui, state: &mut AppState) {
+    let mut clicked = None;
+    for path in &state.recent_files {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into_owned(),
+        );
+        if ui
+            .button(name)
+            .on_hover_text(path.display().to_string())
+            .clicked()
+        {
+            clicked = Some(path.clone());
+        }
+    }
+    let Some(path) = clicked else {
+        return;
+    };
+    ui.close_menu();
+    if path.exists() {
+        load_path(state, path);
+    } else {
+        log::error!("Recent file {} no longer exists", path.display());
+        state.tab.status_message = Some(format!("Error: {} no longer exists", path.display()));
+        state.recent_files.retain(|p| *p != path);
+    }
+}
+
+/// Pick a directory and load all supported files in it as one dataset.
+pub fn open_folder_dialog(state: &mut AppState) {
+    if let Some(dir) = rfd::FileDialog::new()
+        .set_title("Open folder of spectra")
+        .pick_folder()
+    {
+        load_path(state, dir);
+    }
+}
+
+/// Load `path` (a file or a directory) and make it the current dataset, reporting errors in the UI.
+/// The file is read on a background thread; see [`poll_load`].
+pub fn load_path(state: &mut AppState, path: PathBuf) {
+    spawn_load(state, vec![path], LoadMode::Open);
+}
+
+/// Load several files as one dataset with a `source_file` column, like a
+/// directory. A single path is loaded as by [`load_path`].
+pub fn load_paths(state: &mut AppState, paths: Vec<PathBuf>) {
+    if !paths.is_empty() {
+        spawn_load(state, paths, LoadMode::Open);
+    }
+}
+
+/// Re-run the loader on the current source file, keeping matching filters.
+pub fn reload_file(state: &mut AppState) {
+    let Some(path) = state.tab.source_path.clone() else {
+        return;
+    };
+    spawn_load(state, vec![path], LoadMode::Reload);
+}
+
+/// Start loading `paths` on a background thread. Ignored (with a status
+/// message) while another load is still running.
+fn spawn_load(state: &mut AppState, paths: Vec<PathBuf>, mode: LoadMode) {
+    if state.pending_load.is_some() {
+        log::warn!("Ignoring load of {paths:?} while another file is loading");
+        state.tab.status_message = Some("A file is already loading".to_string());
+        return;
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let progress = Arc::new(AtomicU64::new(0.0f64.to_bits()));
+    let options = state.load_options.clone();
+    let thread_paths = paths.clone();
+    let thread_progress = progress.clone();
+    std::thread::spawn(move || {
+        let mut report = |f: f64| thread_progress.store(f.to_bits(), Ordering::Relaxed);
+        let result = match thread_paths.as_slice() {
+            [path] => load_file_with_progress(path, &options, &mut report),
+            paths => load_files(paths, &options, &mut report),
+        };
+        // The receiver is gone only if the app has shut down.
+        let _ = sender.send(result);
+    });
+
+    state.loading = true;
+    state.tab.status_message = None;
+    state.pending_load = Some(PendingLoad {
+        paths,
+        mode,
+        progress,
+        receiver,
+    });
+}
+
+/// Check whether a background load has finished and, if so, install its
+/// dataset or report its error.
+pub fn poll_load(state: &mut AppState) {
+    let Some(pending) = &state.pending_load else {
+        return;
+    };
+    let result = match pending.receiver.try_recv() {
+        Ok(result) => result,
+        Err(mpsc::TryRecvError::Empty) => return,
+        Err(mpsc::TryRecvError::Disconnected) => Err(anyhow::anyhow!("loader thread panicked")),
+    };
+    let Some(PendingLoad { paths, mode, .. }) = state.pending_load.take() else {
+        return;
+    };
+
+    match result {
+        Ok(dataset) if mode == LoadMode::Reload => {
+            log::info!("Reloaded {} spectra from {paths:?}", dataset.len());
+            state.reload_dataset(dataset);
+        }
+        Ok(dataset) if mode == LoadMode::Append => {
+            log::info!("Appended {} spectra from {paths:?}", dataset.len());
+            state.append_dataset(dataset);
+        }
+        Ok(dataset) => {
+            log::info!(
+                "Loaded {} spectra with columns {:?}",
+                dataset.len(),
+                dataset.column_names
+            );
+            // Opening a file keeps the current dataset in its own tab.
+            if state.tab.dataset.is_some() {
+                state.new_tab();
+            }
+            state.set_dataset(dataset);
+            // Several files combined have no single source to reload.
+            state.tab.source_path = match <[PathBuf; 1]>::try_from(paths) {
+                Ok([path]) => Some(path),
+                Err(_) => None,
+            };
+            if let Some(path) = state.tab.source_path.clone() {
+                state.add_recent_file(path);
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to load file: {e:#}");
+            state.tab.status_message = Some(format!("Error: {e:#}"));
+            state.loading = false;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Recipes
+// ---------------------------------------------------------------------------
+
+/// Ask for a path and save the current view as a JSON recipe.
+pub fn save_recipe_dialog(state: &mut AppState) {
+    let file = rfd::FileDialog::new()
+        .set_title("Save recipe")
+        .add_filter("Recipe", &["json"])
+        .set_file_name("recipe.json")
+        .save_file();
+
+    if let Some(path) = file {
+        match crate::recipe::save_recipe(&path, state) {
+            Ok(()) => log::info!("Saved recipe to {}", path.display()),
+            Err(e) => {
+                log::error!("Failed to save recipe: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+}
+
+/// Ask where to save the plot, then request a screenshot; the image is
+/// cropped and written when it arrives in a later frame.
+pub fn save_png_dialog(ctx: &egui::Context, state: &mut AppState) {
+    let file = rfd::FileDialog::new()
+        .set_title("Save plot as PNG")
+        .add_filter("PNG image", &["png"])
+        .set_file_name("plot.png")
+        .save_file();
+
+    if let Some(path) = file {
+        state.pending_screenshot = Some(ScreenshotTarget::File(path));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(Default::default()));
+    }
+}
+
+/// The high-resolution PNG export dialog: image size, supersampling and
+/// resolution, then where to save it. The plot is rendered when next drawn.
+pub fn png_export_window(ctx: &egui::Context, state: &mut AppState) {
+    let mut open = state.png_dialog_open;
+    let mut save = false;
+    egui::Window::new("Save high-resolution PNG")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui: &mut Ui| {
+            let size = &mut state.png_size;
+            egui::Grid::new("png_size")
+                .num_columns(2)
+                .show(ui, |ui: &mut Ui| {
+                    ui.label("Width");
+                    ui.add(
+                        egui::DragValue::new(&mut size.width)
+                            .range(1..=MAX_RENDER_SIDE)
+                            .suffix(" px"),
+                    );
+                    ui.end_row();
+                    ui.label("Height");
+                    ui.add(
+                        egui::DragValue::new(&mut size.height)
+                            .range(1..=MAX_RENDER_SIDE)
+                            .suffix(" px"),
+                    );
+                    ui.end_row();
+                    ui.label("Supersampling");
+                    ui.add(
+                        egui::DragValue::new(&mut size.supersample)
+                            .range(1..=MAX_SUPERSAMPLE)
+                            .prefix("×"),
+                    )
+                    .on_hover_text("Samples per pixel along each side, averaged for smooth lines");
+                    ui.end_row();
+                    ui.label("Resolution");
+                    ui.add(
+                        egui::DragValue::new(&mut size.dpi)
+                            .range(72..=1200)
+                            .suffix(" dpi"),
+                    )
+                    .on_hover_text("Recorded in the file; line widths and text scale with it");
+                    ui.end_row();
+                });
+            ui.weak(format!(
+                "{:.1} × {:.1} in when printed",
+                size.width as f32 / size.dpi as f32,
+                size.height as f32 / size.dpi as f32
+            ));
+            save = ui.button("Save…").clicked();
+        });
+    state.png_dialog_open = open && !save;
+    if !save {
+        return;
+    }
+
+    let file = rfd::FileDialog::new()
+        .set_title("Save high-resolution PNG")
+        .add_filter("PNG image", &["png"])
+        .set_file_name("plot.png")
+        .save_file();
+    if let Some(path) = file {
+        state.pending_render = Some((path, state.png_size));
+        ctx.request_repaint();
+    }
+}
+
+/// Request a screenshot to copy the plot to the clipboard; like
+/// [`save_png_dialog`] the image is cropped when it arrives.
+pub fn copy_plot(ctx: &egui::Context, state: &mut AppState) {
+    if state.tab.dataset.is_none() {
+        return;
+    }
+    state.pending_screenshot = Some(ScreenshotTarget::Clipboard);
+    ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(Default::default()));
+}
+
+/// Ask for a path and write the visible spectra to it as Parquet.
+pub fn export_parquet_dialog(state: &mut AppState) {
+    let indices = state.tab.visible_indices.clone();
+    save_parquet_dialog(
+        state,
+        "Export visible spectra",
+        "filtered.parquet",
+        &indices,
+    );
+}
+
+/// Ask for a path and write the box-selected spectra to it as Parquet.
+pub fn export_selection_dialog(state: &mut AppState) {
+    let indices: Vec<usize> = state.tab.box_selection.iter().copied().collect();
+    save_parquet_dialog(
+        state,
+        "Export selected spectra",
+        "selection.parquet",
+        &indices,
+    );
+}
+
+/// Ask for a path and write the spectra at `indices` to it as Parquet.
+fn save_parquet_dialog(state: &mut AppState, title: &str, file_name: &str, indices: &[usize]) {
+    let Some(ds) = &state.tab.dataset else {
+        return;
+    };
+    let file = rfd::FileDialog::new()
+        .set_title(title)
+        .add_filter("Parquet", &["parquet", "pq"])
+        .set_file_name(file_name)
+        .save_file();
+
+    if let Some(path) = file {
+        match crate::data::loader::save_parquet(&path, ds, indices, state.region) {
+            Ok(()) => log::info!("Exported {} spectra to {}", indices.len(), path.display()),
+            Err(e) => {
+                log::error!("Failed to export Parquet: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+}
+
+/// Ask for a path and write the mean spectrum of each colour group among
+/// the visible spectra to it as CSV.
+///
+/// Not offered for a lazily loaded dataset, whose visible spectra would all
+/// be read into memory at once.
+pub fn export_group_means_dialog(state: &mut AppState) {
+    let (Some(ds), Some(col)) = (&state.tab.dataset, state.tab.color_column.as_deref()) else {
+        return;
+    };
+    if ds.lazy.is_some() {
+        return;
+    }
+    let file = rfd::FileDialog::new()
+        .set_title("Export group means")
         .add_filter("CSV", &["csv"])
+        .set_file_name("group_means.csv")
+        .save_file();
+
+    if let Some(path) = file {
+        let shown: Vec<Spectrum> = ds
+            .spectra_at(&state.tab.visible_indices)
+            .iter()
+            .map(|sp| match state.region {
+                Some((lo, hi)) => trim_region(sp, lo, hi),
+                None => Spectrum::clone(sp),
+            })
+            .collect();
+        let members: Vec<&Spectrum> = shown.iter().collect();
+        let grid = common_grid(&members);
+        match crate::export::export_group_means_csv(
+            &path,
+            ds,
+            &state.tab.visible_indices,
+            col,
+            &grid,
+        ) {
+            Ok(()) => log::info!("Exported group means to {}", path.display()),
+            Err(e) => {
+                log::error!("Failed to export group means: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+}
+
+/// Ask for a path and render the colour legend to it as a PNG.
+pub fn export_legend_dialog(state: &mut AppState) {
+    let Some(cm) = &state.tab.color_map else {
+        return;
+    };
+    let file = rfd::FileDialog::new()
+        .set_title("Export legend")
+        .add_filter("PNG image", &["png"])
+        .set_file_name("legend.png")
+        .save_file();
+
+    if let Some(path) = file {
+        match crate::export::save_legend_png(&path, cm) {
+            Ok(()) => log::info!("Exported legend to {}", path.display()),
+            Err(e) => {
+                log::error!("Failed to export legend: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+}
+
+/// Ask for a path and save the current session as JSON.
+pub fn save_session_dialog(state: &mut AppState) {
+    let file = rfd::FileDialog::new()
+        .set_title("Save session")
+        .add_filter("Session", &["json"])
+        .set_file_name("session.json")
+        .save_file();
+
+    if let Some(path) = file {
+        match crate::session::save_session(&path, state) {
+            Ok(()) => log::info!("Saved session to {}", path.display()),
+            Err(e) => {
+                log::error!("Failed to save session: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+}
+
+/// Ask for a session file and restore it, re-opening its data file.
+pub fn load_session_dialog(state: &mut AppState) {
+    let file = rfd::FileDialog::new()
+        .set_title("Load session")
+        .add_filter("Session", &["json"])
         .pick_file();
 
     if let Some(path) = file {
-        state.loading = true;
-        match crate::data::loader::load_file(&path) {
-            Ok(dataset) => {
-                log::info!(
-                    "Loaded {} spectra with columns {:?}",
-                    dataset.len(),
-                    dataset.column_names
-                );
-                state.set_dataset(dataset);
+        match crate::session::load_session(&path) {
+            Ok(mut new_state) => {
+                new_state.recent_files = std::mem::take(&mut state.recent_files);
+                *state = new_state;
+            }
+            Err(e) => {
+                log::error!("Failed to load session: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+}
+
+/// Ask for a recipe file, load its data and apply it.
+pub fn open_recipe_dialog(state: &mut AppState) {
+    let file = rfd::FileDialog::new()
+        .set_title("Open recipe")
+        .add_filter("Recipe", &["json"])
+        .pick_file();
+
+    if let Some(path) = file {
+        match crate::recipe::apply_recipe(&path) {
+            Ok(mut new_state) => {
+                new_state.recent_files = std::mem::take(&mut state.recent_files);
+                *state = new_state;
             }
             Err(e) => {
-                log::error!("Failed to load file: {e:#}");
-                state.status_message = Some(format!("Error: {e:#}"));
-                state.loading = false;
+                log::error!("Failed to apply recipe: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
             }
         }
     }
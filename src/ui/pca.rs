@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use eframe::egui::{Color32, RichText, Ui};
+use egui_plot::{Plot, Points};
+
+use crate::data::analysis::{PcaResult, pca};
+use crate::data::group::{common_grid, group_indices, resample_to_grid};
+use crate::data::model::Spectrum;
+use crate::state::AppState;
+use crate::ui::heatmap::{heatmap_key, ordered_rows};
+use crate::ui::plot::display_spectrum;
+
+// ---------------------------------------------------------------------------
+// PCA scores view (central panel)
+// ---------------------------------------------------------------------------
+
+/// Most spectra analysed; larger selections are sampled down.
+const MAX_SPECTRA: usize = 2000;
+
+/// Most grid points the spectra are resampled onto.
+const MAX_GRID: usize = 1024;
+
+/// Number of components computed, and listed with their explained variance.
+const N_COMPONENTS: usize = 3;
+
+/// Scatter of the plotted spectra's PC1 vs PC2 scores, coloured by the
+/// colour column.
+///
+/// The analysis is only rerun when the plotted spectra or the display
+/// settings change.
+#[derive(Default)]
+pub struct PcaView {
+    /// Hash of the inputs the current result was computed from.
+    key: u64,
+    scores: Option<PcaScores>,
+}
+
+/// PCA of the plotted spectra.
+struct PcaScores {
+    /// Spectrum index of each row of `result.scores`.
+    rows: Vec<usize>,
+    result: PcaResult,
+    /// Number of plotted spectra before sampling down.
+    n_plotted: usize,
+}
+
+impl PcaView {
+    /// Render the scores plot, with the explained variance above it, in the
+    /// central panel.
+    pub fn show(&mut self, ui: &mut Ui, state: &mut AppState) {
+        let key = heatmap_key(state);
+        if self.key != key || self.scores.is_none() {
+            self.key = key;
+            self.scores = compute_scores(state);
+        }
+        let Some(scores) = &self.scores else {
+            ui.centered_and_justified(|ui: &mut Ui| {
+                ui.heading("PCA needs at least two spectra with varying intensities");
+            });
+            return;
+        };
+
+        let explained: Vec<String> = scores
+            .result
+            .explained
+            .iter()
+            .enumerate()
+            .map(|(i, e)| format!("PC{} {:.1}%", i + 1, e * 100.0))
+            .collect();
+        ui.label(format!("Explained variance: {}", explained.join(", ")));
+        if scores.rows.len() < scores.n_plotted {
+            ui.label(
+                RichText::new(format!(
+                    "Showing {} of {} spectra",
+                    scores.rows.len(),
+                    scores.n_plotted
+                ))
+                .color(ui.visuals().warn_fg_color),
+            );
+        }
+
+        let axis_label = |i: usize| match scores.result.explained.get(i) {
+            Some(e) => format!("PC{} ({:.1}%)", i + 1, e * 100.0),
+            None => format!("PC{}", i + 1),
+        };
+        let response = Plot::new("pca_scores")
+            .x_axis_label(axis_label(0))
+            .y_axis_label(axis_label(1))
+            .show(ui, |plot_ui| {
+                let Some(ds) = &state.tab.dataset else {
+                    return;
+                };
+                // Row of each spectrum, to look up its scores by group.
+                let row_of: HashMap<usize, usize> = scores
+                    .rows
+                    .iter()
+                    .enumerate()
+                    .map(|(row, &idx)| (idx, row))
+                    .collect();
+                let groups = group_indices(ds, &scores.rows, state.tab.color_column.as_deref());
+                for (value, members) in groups {
+                    let points: Vec<[f64; 2]> = members
+                        .iter()
+                        .filter_map(|&idx| {
+                            let s = &scores.result.scores[*row_of.get(&idx)?];
+                            Some([s[0], s.get(1).copied().unwrap_or(0.0)])
+                        })
+                        .collect();
+                    let color = state
+                        .tab
+                        .color_map
+                        .as_ref()
+                        .map_or(Color32::LIGHT_BLUE, |cm| cm.color_for(&value));
+                    plot_ui.points(
+                        Points::new(points)
+                            .color(color)
+                            .radius(3.0)
+                            .name(value.to_string()),
+                    );
+                }
+            });
+        state.plot_rect = Some(response.response.rect);
+    }
+}
+
+/// Resample the plotted spectra as displayed onto a common grid and run PCA
+/// on them. `None` if no component could be found.
+fn compute_scores(state: &AppState) -> Option<PcaScores> {
+    let ds = state.tab.dataset.as_ref()?;
+    let n_plotted = state.plotted_indices().len();
+    let rows = ordered_rows(state, ds, MAX_SPECTRA);
+
+    let shown: Vec<Spectrum> = rows
+        .iter()
+        .zip(ds.spectra_at(&rows))
+        .map(|(&i, sp)| display_spectrum(state, i, &sp))
+        .collect();
+    let members: Vec<&Spectrum> = shown.iter().collect();
+    let mut grid = common_grid(&members);
+    if grid.len() > MAX_GRID {
+        let (lo, hi) = (grid[0], grid[grid.len() - 1]);
+        let step = (hi - lo) / (MAX_GRID - 1) as f64;
+        grid = (0..MAX_GRID).map(|i| lo + step * i as f64).collect();
+    }
+    let matrix: Vec<Vec<f64>> = shown
+        .iter()
+        .map(|sp| resample_to_grid(&sp.x, &sp.y, &grid))
+        .collect();
+
+    let result = pca(&matrix, N_COMPONENTS);
+    if result.explained.is_empty() {
+        return None;
+    }
+    Some(PcaScores {
+        rows,
+        result,
+        n_plotted,
+    })
+}
@@ -1,15 +1,33 @@
-use eframe::egui::{Color32, Ui};
-use egui_plot::{Line, Plot, PlotPoints};
+use std::collections::{BTreeMap, BTreeSet};
 
-use crate::state::AppState;
+use eframe::egui::{self, Color32, Stroke, Ui, Vec2b};
+use egui_plot::{
+    Line, LineStyle, Plot, PlotBounds, PlotPoint, PlotPoints, PlotUi, Points, Polygon, Text, VLine,
+};
+
+use crate::color::darken_for_light_background;
+use crate::data::analysis::{find_peaks, group_outlier_scores};
+use crate::data::group::{GroupStats, common_grid, group_indices, group_stats, resample_to_grid};
+use crate::data::model::{MetadataValue, SpectralDataset, Spectrum};
+use crate::data::preprocess::{
+    Normalization, anchor_baseline, area_between, bin_spectrum, derivative, linear_baseline,
+    min_max_with_range, offset_at, savitzky_golay, scale_to_unit_area, trim_region,
+};
+use crate::export::{PlotImage, nice_ticks, save_plot_png};
+use crate::state::{AppState, PlotMode, PlotTheme, ViewBounds, sample_indices};
 
 // ---------------------------------------------------------------------------
 // Spectral plot (central panel)
 // ---------------------------------------------------------------------------
 
 /// Render the spectral plot in the central panel.
-pub fn spectral_plot(ui: &mut Ui, state: &AppState) {
-    let dataset = match &state.dataset {
+pub fn spectral_plot(ui: &mut Ui, state: &mut AppState) {
+    state.update_baseline_cache();
+    let pending_view = state.tab.pending_view.take();
+    let pending_auto = std::mem::take(&mut state.pending_auto_bounds);
+    let pending_fit = std::mem::take(&mut state.pending_fit);
+    let pending_render = state.pending_render.take();
+    let dataset = match &state.tab.dataset {
         Some(ds) => ds,
         None => {
             ui.centered_and_justified(|ui: &mut Ui| {
@@ -19,20 +37,75 @@ pub fn spectral_plot(ui: &mut Ui, state: &AppState) {
         }
     };
 
-    let color_map = &state.color_map;
-    let color_col = state.color_column.as_deref();
+    if dataset.is_empty() {
+        ui.centered_and_justified(|ui: &mut Ui| {
+            ui.heading("The loaded file contains no spectra");
+        });
+        return;
+    }
+
+    let color_map = &state.tab.color_map;
+    let color_col = state.tab.color_column.as_deref();
 
-    Plot::new("spectral_plot")
-        .legend(egui_plot::Legend::default())
+    let x_free = state.x_bounds.is_none();
+    let y_free = state.y_bounds.is_none();
+    let x_sign = x_sign(state);
+    let log_y = state.log_y;
+    let mut pointer = None;
+
+    let response = Plot::new("spectral_plot")
         .x_axis_label("Wavenumber")
-        .y_axis_label("Intensity")
+        .y_axis_label(y_axis_label(state))
+        // With a reversed axis the plot works on -x; show the true value.
+        .x_axis_formatter(move |mark, _range| {
+            format!("{:.*}", tick_decimals(mark.step_size), x_sign * mark.value)
+        })
+        .y_axis_formatter(move |mark, _range| {
+            if log_y {
+                format_log_tick(mark.value)
+            } else {
+                format!("{:.*}", tick_decimals(mark.step_size), mark.value)
+            }
+        })
+        .label_formatter(move |name, value| {
+            let y = if log_y { 10f64.powf(value.y) } else { value.y };
+            let coords = format!("x = {:.2}\ny = {:.4}", x_sign * value.x, y);
+            if name.is_empty() {
+                coords
+            } else {
+                format!("{name}\n{coords}")
+            }
+        })
+        .show_background(state.plot_theme != PlotTheme::Transparent)
         .allow_boxed_zoom(true)
-        .allow_drag(true)
-        .allow_scroll(true)
-        .allow_zoom(true)
+        .allow_drag([x_free && !state.box_select, y_free && !state.box_select])
+        .allow_scroll([x_free, y_free])
+        .allow_zoom([x_free, y_free])
         .show(ui, |plot_ui| {
-            for &idx in &state.visible_indices {
-                let sp = &dataset.spectra[idx];
+            apply_view(plot_ui, state, pending_view, pending_auto);
+            pointer = plot_ui.pointer_coordinate();
+            if let Some(ref_x) = state.baseline_point {
+                plot_ui.vline(
+                    VLine::new(x_sign * ref_x)
+                        .color(Color32::GRAY)
+                        .style(LineStyle::dashed_loose()),
+                );
+            }
+
+            if state.error_bars {
+                draw_error_bars(plot_ui, state, dataset);
+                if pending_fit {
+                    plot_ui.set_auto_bounds([x_free, y_free]);
+                }
+                return (None, BoxDrag::default(), None);
+            }
+
+            let plotted = state.rendered_indices();
+            let spectra = dataset.spectra_at(&plotted);
+            let mut traces = Vec::with_capacity(plotted.len());
+            // Displayed spectra by colour group, to score outliers on.
+            let mut groups: BTreeMap<MetadataValue, Vec<(usize, Spectrum)>> = BTreeMap::new();
+            for (idx, sp) in plotted.into_iter().zip(&spectra) {
 
                 // Determine colour from the colour-by column.
                 let color = color_col
@@ -42,39 +115,960 @@ pub fn spectral_plot(ui: &mut Ui, state: &AppState) {
                         Some(cm.color_for(val))
                     })
                     .unwrap_or(Color32::LIGHT_BLUE);
+                let color = legible(state, color);
 
-                // Build the legend name from the colour column value.
+                // Name the line by its colour column value for the hover label.
                 let name = color_col
                     .and_then(|col| sp.metadata.get(col))
-                    .map(|v| v.to_string())
+                    .map(|v| state.format_value(v))
                     .unwrap_or_else(|| format!("spectrum {idx}"));
 
-                let y_values: Vec<f64> = if state.minmax_scaling {
-                    let min = sp.y.iter().cloned().fold(f64::INFINITY, f64::min);
-                    let max = sp.y.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-                    let range = max - min;
-                    if range.abs() < f64::EPSILON {
-                        vec![0.0; sp.y.len()]
-                    } else {
-                        sp.y.iter().map(|&yi| (yi - min) / range).collect()
-                    }
-                } else {
-                    sp.y.clone()
-                };
-
-                let points: PlotPoints = sp
+                let shown = display_spectrum(state, idx, sp);
+                let points: Vec<[f64; 2]> = shown
                     .x
                     .iter()
-                    .zip(y_values.iter())
-                    .map(|(&xi, &yi)| [xi, yi])
+                    .zip(shown.y.iter())
+                    .filter(|(xi, yi)| xi.is_finite() && yi.is_finite())
+                    .map(|(&xi, &yi)| [x_sign * xi, yi])
                     .collect();
+                if state.highlight_outliers {
+                    let group = state.color_group(idx);
+                    groups.entry(group).or_default().push((idx, shown));
+                }
 
-                let line = Line::new(points)
-                    .name(&name)
-                    .color(color)
-                    .width(1.5);
+                traces.push(Trace {
+                    idx,
+                    points,
+                    color,
+                    name,
+                });
+            }
+
+            if state.plot_mode == PlotMode::Waterfall {
+                apply_waterfall(state, dataset, &mut traces);
+            }
+            if pending_fit {
+                fit_view(plot_ui, state, &traces);
+            }
+            let rendered = pending_render.map(|(path, size)| {
+                let result = save_plot_png(&path, &plot_image(plot_ui, state, &traces), size);
+                (path, result)
+            });
+            draw_regions(plot_ui, state, &traces);
+            draw_overlay(plot_ui, state);
+            let box_drag = box_select(plot_ui, state, &traces);
 
+            let outliers = outliers(state, &groups);
+
+            // Highlight the trace under the pointer, the selected one and
+            // the highlighted group, and dim the rest.
+            let hovered = nearest_trace(plot_ui, &traces);
+            let selected = state.tab.selected_index;
+            let group = state.tab.highlighted_group.as_ref();
+            let boxed = &state.tab.box_selection;
+            let max_points_shown = max_points_shown(plot_ui);
+            for trace in traces {
+                let emphasised = hovered == Some(trace.idx)
+                    || selected == Some(trace.idx)
+                    || group.is_some_and(|g| *g == state.color_group(trace.idx))
+                    || boxed.contains(&trace.idx);
+                let (color, width) = if emphasised {
+                    (trace.color, state.line_width + 1.5)
+                } else if hovered.is_some()
+                    || selected.is_some()
+                    || group.is_some()
+                    || !boxed.is_empty()
+                {
+                    (trace.color.gamma_multiply(0.35), state.line_width)
+                } else {
+                    (trace.color, state.line_width)
+                };
+                let (color, width) = if outliers.contains(&trace.idx) {
+                    (Color32::RED, width + 1.5)
+                } else {
+                    (color, width)
+                };
+                if state.show_peaks && hovered == Some(trace.idx) {
+                    draw_peaks(plot_ui, state, &trace);
+                }
+                if emphasised && group.is_none() && !state.subtract_anchors {
+                    draw_anchor_baseline(plot_ui, state, &trace);
+                }
+                let points = decimate_for_view(plot_ui, trace.points, max_points_shown);
+                let line = Line::new(PlotPoints::from(points))
+                    .color(color)
+                    .width(width)
+                    .style(state.line_style.plot_style())
+                    .name(&trace.name);
                 plot_ui.line(line);
             }
+            if state.show_std_band || state.show_group_means {
+                let groups = group_summaries(state, dataset);
+                if state.show_std_band {
+                    draw_std_bands(plot_ui, state, &groups);
+                }
+                if state.show_group_means {
+                    draw_group_means(plot_ui, state, &groups);
+                }
+            }
+            (hovered, box_drag, rendered)
+        });
+    let (hovered, box_drag, rendered) = response.inner;
+
+    if let Some((path, result)) = rendered {
+        match result {
+            Ok(()) => log::info!("Saved plot to {}", path.display()),
+            Err(e) => {
+                log::error!("Failed to save plot: {e:#}");
+                state.tab.status_message = Some(format!("Error: {e:#}"));
+            }
+        }
+    }
+
+    // Clicking a line pins it; clicking empty space clears the selection.
+    let clicked = response.response.clicked();
+    if let Some(idx) = hovered {
+        let sp = &dataset.spectra[idx];
+        response
+            .response
+            .clone()
+            .on_hover_ui_at_pointer(|ui: &mut Ui| {
+                ui.strong(format!("Spectrum {idx}"));
+                egui::Grid::new("hover_metadata")
+                    .num_columns(2)
+                    .show(ui, |ui: &mut Ui| {
+                        for (key, value) in &sp.metadata {
+                            ui.strong(key);
+                            ui.label(state.format_value(value));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    // Shift-click sets the offset reference point and Ctrl-click adds a
+    // baseline anchor instead.
+    let modifiers = ui.input(|i| i.modifiers);
+    match pointer.filter(|_| clicked) {
+        Some(p) if modifiers.shift => state.baseline_point = Some(x_sign * p.x),
+        Some(p) if modifiers.command => state.add_baseline_anchor(x_sign * p.x),
+        _ if clicked => state.tab.selected_index = hovered,
+        _ => {}
+    }
+    // Cursor readout in data coordinates: the true wavenumber and intensity.
+    if let Some(p) = pointer.filter(|_| response.response.hovered()) {
+        let y = if log_y { 10f64.powf(p.y) } else { p.y };
+        ui.painter().text(
+            response.response.rect.left_top() + egui::vec2(8.0, 6.0),
+            egui::Align2::LEFT_TOP,
+            format!("({:.2}, {y:.4})", x_sign * p.x),
+            egui::FontId::monospace(12.0),
+            ui.visuals().weak_text_color(),
+        );
+    }
+    state.plot_rect = Some(response.response.rect);
+    let bounds = response.transform.bounds();
+    state.tab.view_bounds = Some(ViewBounds {
+        x: to_plot_x_range(state, [bounds.min()[0], bounds.max()[0]]),
+        y: [bounds.min()[1], bounds.max()[1]],
+    });
+
+    if box_drag.started.is_some() {
+        state.box_start = box_drag.started;
+    }
+    if let Some(selection) = box_drag.selected {
+        state.box_start = None;
+        state.set_box_selection(selection);
+    }
+}
+
+/// Opacity of the overlay tab's lines.
+const OVERLAY_ALPHA: f32 = 0.5;
+
+/// Draw the filtered spectra of the overlay tab dashed and faded, in its own
+/// colours, under the active dataset's traces. Waterfall offsets and
+/// highlighting only apply to the active dataset.
+fn draw_overlay(plot_ui: &mut PlotUi, state: &AppState) {
+    let (Some(i), Some(tab)) = (state.overlay_tab, state.overlay()) else {
+        return;
+    };
+    let Some(ds) = tab.dataset() else {
+        return;
+    };
+    let tab_name = state.tab_name(i);
+    let indices = tab.visible_indices().to_vec();
+    let indices = match state.line_limit(ds) {
+        Some(limit) => sample_indices(indices, limit),
+        None => indices,
+    };
+    let x_sign = x_sign(state);
+    let max_points_shown = max_points_shown(plot_ui);
+    for (idx, sp) in indices.iter().copied().zip(ds.spectra_at(&indices)) {
+        let value = tab.color_column().and_then(|col| sp.metadata.get(col));
+        let color = value
+            .zip(tab.color_map())
+            .map_or(Color32::LIGHT_BLUE, |(v, cm)| cm.color_for(v));
+        let name = match value {
+            Some(v) => format!("{tab_name}: {}", state.format_value(v)),
+            None => format!("{tab_name}: spectrum {idx}"),
+        };
+        let shown = display_spectrum_with(state, &sp, tab.baseline_corrected(idx), None);
+        let points: Vec<[f64; 2]> = shown
+            .x
+            .iter()
+            .zip(&shown.y)
+            .filter(|(xi, yi)| xi.is_finite() && yi.is_finite())
+            .map(|(&xi, &yi)| [x_sign * xi, yi])
+            .collect();
+        let points = decimate_for_view(plot_ui, points, max_points_shown);
+        plot_ui.line(
+            Line::new(PlotPoints::from(points))
+                .color(legible(state, color).gamma_multiply(OVERLAY_ALPHA))
+                .width(state.line_width)
+                .style(LineStyle::dashed_dense())
+                .name(name),
+        );
+    }
+}
+
+/// What a selection box drag did this frame.
+#[derive(Default)]
+struct BoxDrag {
+    /// Plot coordinates where a new drag started.
+    started: Option<[f64; 2]>,
+    /// Spectra inside the box of a drag that ended.
+    selected: Option<BTreeSet<usize>>,
+}
+
+/// In box select mode, draw the box being dragged out and, when the drag
+/// ends, find the traces with a point inside it.
+fn box_select(plot_ui: &mut PlotUi, state: &AppState, traces: &[Trace]) -> BoxDrag {
+    let mut drag = BoxDrag::default();
+    if !state.box_select {
+        return drag;
+    }
+    let response = plot_ui.response().clone();
+    let pointer = response
+        .ctx
+        .input(|i| i.pointer.latest_pos())
+        .map(|p| plot_ui.plot_from_screen(p));
+    if response.drag_started() {
+        drag.started = pointer.map(|p| [p.x, p.y]);
+    }
+    let (Some(start), Some(end)) = (drag.started.or(state.box_start), pointer) else {
+        return drag;
+    };
+    let (x0, x1) = (start[0].min(end.x), start[0].max(end.x));
+    let (y0, y1) = (start[1].min(end.y), start[1].max(end.y));
+
+    if response.drag_stopped() {
+        let inside = |p: &[f64; 2]| (x0..=x1).contains(&p[0]) && (y0..=y1).contains(&p[1]);
+        drag.selected = Some(
+            traces
+                .iter()
+                .filter(|t| t.points.iter().any(inside))
+                .map(|t| t.idx)
+                .collect(),
+        );
+    } else if response.dragged() {
+        let color = plot_ui.ctx().style().visuals.selection.stroke.color;
+        plot_ui.polygon(
+            Polygon::new(PlotPoints::from(vec![
+                [x0, y0],
+                [x1, y0],
+                [x1, y1],
+                [x0, y1],
+            ]))
+            .fill_color(color.gamma_multiply(0.15))
+            .stroke(Stroke::new(1.0, color))
+            .allow_hover(false),
+        );
+    }
+    drag
+}
+
+/// One spectrum prepared for drawing, in plot coordinates.
+struct Trace {
+    idx: usize,
+    points: Vec<[f64; 2]>,
+    color: Color32,
+    name: String,
+}
+
+/// Order traces by the colour column value (if any) and shift each one up by
+/// `waterfall_spacing` times the largest y range among them.
+fn apply_waterfall(state: &AppState, dataset: &SpectralDataset, traces: &mut [Trace]) {
+    if let Some(col) = state.tab.color_column.as_deref() {
+        traces.sort_by(|a, b| {
+            let value = |t: &Trace| dataset.spectra[t.idx].metadata.get(col);
+            value(a).cmp(&value(b))
+        });
+    }
+    let span = traces
+        .iter()
+        .filter_map(|t| {
+            let (lo, hi) = t
+                .points
+                .iter()
+                .map(|p| p[1])
+                .filter(|y| y.is_finite())
+                .fold(None, |acc: Option<(f64, f64)>, y| match acc {
+                    None => Some((y, y)),
+                    Some((lo, hi)) => Some((lo.min(y), hi.max(y))),
+                })?;
+            Some(hi - lo)
+        })
+        .fold(0.0, f64::max);
+    let step = state.waterfall_spacing * span;
+    for (rank, trace) in traces.iter_mut().enumerate() {
+        let offset = step * rank as f64;
+        for p in &mut trace.points {
+            p[1] += offset;
+        }
+    }
+}
+
+/// Mark the peaks of `trace` with dots labelled by their wavenumber. The
+/// prominence threshold is relative to the trace's y range.
+fn draw_peaks(plot_ui: &mut PlotUi, state: &AppState, trace: &Trace) {
+    let (x, y): (Vec<f64>, Vec<f64>) = trace.points.iter().map(|p| (p[0], p[1])).unzip();
+    let (lo, hi) = y
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
         });
+    if lo >= hi {
+        return;
+    }
+    let params = state.peak_params;
+    let peaks = find_peaks(
+        &x,
+        &y,
+        params.min_prominence * (hi - lo),
+        params.min_distance,
+    );
+
+    let x_sign = x_sign(state);
+    let markers: Vec<[f64; 2]> = peaks.iter().map(|&i| [x[i], y[i]]).collect();
+    plot_ui.points(Points::new(markers).color(trace.color).radius(4.0));
+    for &i in &peaks {
+        plot_ui.text(
+            Text::new(PlotPoint::new(x[i], y[i]), format!("{:.0}", x_sign * x[i]))
+                .anchor(egui::Align2::CENTER_BOTTOM)
+                .color(trace.color),
+        );
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Decimation (live view only; exports always use the full data)
+// ---------------------------------------------------------------------------
+
+/// Most points worth drawing across the plot: two per pixel column.
+fn max_points_shown(plot_ui: &PlotUi) -> usize {
+    (plot_ui.response().rect.width() * plot_ui.ctx().pixels_per_point() * 2.0).max(100.0) as usize
+}
+
+/// Thin `points` so that at most about `max_shown` of them fall in the
+/// visible x range. The whole trace is decimated at that density, which
+/// keeps the auto-fitted bounds unchanged.
+fn decimate_for_view(plot_ui: &PlotUi, points: Vec<[f64; 2]>, max_shown: usize) -> Vec<[f64; 2]> {
+    if points.len() <= max_shown {
+        return points;
+    }
+    let bounds = plot_ui.plot_bounds();
+    let (lo, hi) = (bounds.min()[0], bounds.max()[0]);
+    let n_visible = points.iter().filter(|p| (lo..=hi).contains(&p[0])).count();
+    if n_visible <= max_shown {
+        return points;
+    }
+    let target = max_shown * points.len() / n_visible;
+    let (x, y): (Vec<f64>, Vec<f64>) = points.iter().map(|p| (p[0], p[1])).unzip();
+    let (x, y) = ltob_downsample(&x, &y, target);
+    x.into_iter().zip(y).map(|(x, y)| [x, y]).collect()
+}
+
+/// Reduce a series to `target` points with Largest-Triangle-Three-Buckets,
+/// which keeps the points that shape the line (peaks and dips). The first
+/// and last points are always kept.
+fn ltob_downsample(x: &[f64], y: &[f64], target: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = x.len().min(y.len());
+    if target >= n || target < 3 {
+        return (x[..n].to_vec(), y[..n].to_vec());
+    }
+
+    let mut out_x = Vec::with_capacity(target);
+    let mut out_y = Vec::with_capacity(target);
+    out_x.push(x[0]);
+    out_y.push(y[0]);
+
+    // Interior points split into `target - 2` buckets; from each, keep the
+    // point forming the largest triangle with the previously kept point and
+    // the average of the next bucket.
+    let bucket_size = (n - 2) as f64 / (target - 2) as f64;
+    let bucket = |i: usize| {
+        let start = 1 + (i as f64 * bucket_size) as usize;
+        let end = (1 + ((i + 1) as f64 * bucket_size) as usize).min(n - 1);
+        start..end.max(start + 1)
+    };
+    let mut prev = 0;
+    for i in 0..target - 2 {
+        let next = if i + 3 < target {
+            bucket(i + 1)
+        } else {
+            n - 1..n
+        };
+        let len = next.len() as f64;
+        let avg_x = next.clone().map(|j| x[j]).sum::<f64>() / len;
+        let avg_y = next.map(|j| y[j]).sum::<f64>() / len;
+
+        let (ax, ay) = (x[prev], y[prev]);
+        let chosen = bucket(i)
+            .max_by(|&a, &b| {
+                let area =
+                    |j: usize| ((ax - avg_x) * (y[j] - ay) - (ax - x[j]) * (avg_y - ay)).abs();
+                area(a).total_cmp(&area(b))
+            })
+            .unwrap_or(prev);
+        out_x.push(x[chosen]);
+        out_y.push(y[chosen]);
+        prev = chosen;
+    }
+
+    out_x.push(x[n - 1]);
+    out_y.push(y[n - 1]);
+    (out_x, out_y)
+}
+
+/// Pointer distance (in points) within which a trace counts as hovered.
+const HOVER_DISTANCE: f32 = 12.0;
+
+/// Index of the trace whose y at the pointer's x is closest to the pointer,
+/// if it is within [`HOVER_DISTANCE`].
+fn nearest_trace(plot_ui: &PlotUi, traces: &[Trace]) -> Option<usize> {
+    if !plot_ui.response().hovered() {
+        return None;
+    }
+    let pointer = plot_ui.pointer_coordinate()?;
+    let pointer_y = plot_ui.screen_from_plot(pointer).y;
+
+    traces
+        .iter()
+        .filter_map(|trace| {
+            let y = interpolate_at(&trace.points, pointer.x)?;
+            let screen_y = plot_ui.screen_from_plot(PlotPoint::new(pointer.x, y)).y;
+            Some((trace.idx, (screen_y - pointer_y).abs()))
+        })
+        .filter(|(_, d)| *d <= HOVER_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(idx, _)| idx)
+}
+
+/// Linearly interpolated y at `x` on the first segment of `points` spanning
+/// it, in either direction.
+fn interpolate_at(points: &[[f64; 2]], x: f64) -> Option<f64> {
+    points.windows(2).find_map(|w| {
+        let ([x0, y0], [x1, y1]) = (w[0], w[1]);
+        if (x0 <= x && x <= x1) || (x1 <= x && x <= x0) {
+            Some(if x1 == x0 {
+                y0
+            } else {
+                y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// Factor mapping data x to plot x: the plot works on -x when the axis is
+/// reversed.
+fn x_sign(state: &AppState) -> f64 {
+    if state.reverse_x { -1.0 } else { 1.0 }
+}
+
+/// Convert an x range between data and plot coordinates (the mapping is its
+/// own inverse).
+fn to_plot_x_range(state: &AppState, [lo, hi]: [f64; 2]) -> [f64; 2] {
+    if state.reverse_x {
+        [-hi, -lo]
+    } else {
+        [lo, hi]
+    }
+}
+
+/// Apply a requested view and pin locked axes to their fixed ranges,
+/// keeping auto-fit on the free ones. `pending_auto` re-enables auto-fit on
+/// axes that were just unlocked.
+fn apply_view(
+    plot_ui: &mut PlotUi,
+    state: &AppState,
+    pending_view: Option<ViewBounds>,
+    pending_auto: [bool; 2],
+) {
+    let auto = plot_ui.auto_bounds();
+    let mut auto = Vec2b::new(auto.x || pending_auto[0], auto.y || pending_auto[1]);
+    let locked = state.x_bounds.is_some() || state.y_bounds.is_some();
+
+    let mut bounds = match pending_view {
+        Some(view) => {
+            auto = Vec2b::FALSE;
+            let [x0, x1] = to_plot_x_range(state, view.x);
+            PlotBounds::from_min_max([x0, view.y[0]], [x1, view.y[1]])
+        }
+        None if locked => plot_ui.plot_bounds(),
+        None => {
+            if pending_auto.contains(&true) {
+                plot_ui.set_auto_bounds(auto);
+            }
+            return;
+        }
+    };
+    if let Some([lo, hi]) = state.x_bounds.map(|b| to_plot_x_range(state, b)) {
+        bounds.set_x(&PlotBounds::from_min_max([lo, 0.0], [hi, 0.0]));
+        auto.x = false;
+    }
+    if let Some([lo, hi]) = state.y_bounds {
+        bounds.set_y(&PlotBounds::from_min_max([0.0, lo], [0.0, hi]));
+        auto.y = false;
+    }
+    plot_ui.set_plot_bounds(bounds);
+    plot_ui.set_auto_bounds(auto);
+}
+
+/// Preview the linear baseline through the anchors under `trace` as a
+/// dashed line, before it is subtracted.
+fn draw_anchor_baseline(plot_ui: &mut PlotUi, state: &AppState, trace: &Trace) {
+    let x_sign = x_sign(state);
+    let anchors: Vec<f64> = state.baseline_anchors.iter().map(|a| x_sign * a).collect();
+    let (x, y): (Vec<f64>, Vec<f64>) = trace.points.iter().map(|p| (p[0], p[1])).unzip();
+    let Some(baseline) = anchor_baseline(&x, &y, &anchors) else {
+        return;
+    };
+    let points: Vec<[f64; 2]> = x.into_iter().zip(baseline).map(|(x, b)| [x, b]).collect();
+    plot_ui.line(
+        Line::new(PlotPoints::from(points))
+            .color(trace.color)
+            .style(LineStyle::dashed_dense())
+            .name("baseline"),
+    );
+}
+
+/// Spectra of `groups` whose RMS distance from their group's mean exceeds
+/// the group's mean distance by `outlier_sigma` standard deviations.
+fn outliers(
+    state: &AppState,
+    groups: &BTreeMap<MetadataValue, Vec<(usize, Spectrum)>>,
+) -> BTreeSet<usize> {
+    let groups: Vec<Vec<(usize, &Spectrum)>> = groups
+        .values()
+        .map(|members| members.iter().map(|(idx, sp)| (*idx, sp)).collect())
+        .collect();
+    let scores = group_outlier_scores(&groups);
+
+    let mut outliers = BTreeSet::new();
+    for members in &groups {
+        let values: Vec<f64> = members
+            .iter()
+            .map(|(idx, _)| scores[idx])
+            .filter(|v| v.is_finite())
+            .collect();
+        if values.len() < 2 {
+            continue;
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+        let threshold = mean + state.outlier_sigma * std;
+        outliers.extend(
+            members
+                .iter()
+                .map(|(idx, _)| *idx)
+                .filter(|idx| scores[idx] > threshold),
+        );
+    }
+    outliers
+}
+
+/// Opacity of the shaded region bands.
+const REGION_ALPHA: f32 = 0.2;
+
+/// Shade each marked region as a translucent band over the y range of the
+/// traces (and of the view, unless it auto-fits), labelled at the top. Drawn
+/// before the traces so it stays behind them.
+fn draw_regions(plot_ui: &mut PlotUi, state: &AppState, traces: &[Trace]) {
+    if state.tab.regions.is_empty() {
+        return;
+    }
+    let (mut lo, mut hi) = traces
+        .iter()
+        .flat_map(|t| &t.points)
+        .map(|p| p[1])
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), y| {
+            (lo.min(y), hi.max(y))
+        });
+    // Spanning the view itself would grow an auto-fitted axis every frame.
+    if !plot_ui.auto_bounds().y {
+        let view = plot_ui.plot_bounds();
+        lo = lo.min(view.min()[1]);
+        hi = hi.max(view.max()[1]);
+    }
+    if lo > hi {
+        return;
+    }
+
+    let x_sign = x_sign(state);
+    for (start, end, color, label) in &state.tab.regions {
+        let (x0, x1) = (x_sign * start, x_sign * end);
+        let band = vec![[x0, lo], [x1, lo], [x1, hi], [x0, hi]];
+        plot_ui.polygon(
+            Polygon::new(PlotPoints::from(band))
+                .fill_color(color.gamma_multiply(REGION_ALPHA))
+                .stroke(Stroke::NONE)
+                .allow_hover(false),
+        );
+        if !label.is_empty() {
+            plot_ui.text(
+                Text::new(PlotPoint::new((x0 + x1) / 2.0, hi), label.as_str())
+                    .anchor(egui::Align2::CENTER_TOP)
+                    .color(*color),
+            );
+        }
+    }
+}
+
+/// Margin added on each side of the data when fitting the view, as a
+/// fraction of its extent.
+const FIT_MARGIN: f64 = 0.05;
+
+/// Set the plot bounds to the extent of the drawn traces, so the fit
+/// matches the transformed data. Locked axes keep their range.
+fn fit_view(plot_ui: &mut PlotUi, state: &AppState, traces: &[Trace]) {
+    let mut bounds = PlotBounds::NOTHING;
+    for p in traces.iter().flat_map(|t| &t.points) {
+        if p[0].is_finite() && p[1].is_finite() {
+            bounds.extend_with(&PlotPoint::new(p[0], p[1]));
+        }
+    }
+    if !bounds.is_valid() {
+        return;
+    }
+    let [w, h] = [bounds.width(), bounds.height()];
+    bounds.expand_x(if w > 0.0 { w * FIT_MARGIN } else { 0.5 });
+    bounds.expand_y(if h > 0.0 { h * FIT_MARGIN } else { 0.5 });
+
+    let current = plot_ui.plot_bounds();
+    if state.x_bounds.is_some() {
+        bounds.set_x(&current);
+    }
+    if state.y_bounds.is_some() {
+        bounds.set_y(&current);
+    }
+    plot_ui.set_plot_bounds(bounds);
+}
+
+/// Intensities of `sp` as displayed, after baseline correction, smoothing,
+/// linear baseline and offset correction, normalization, differentiation
+/// and, on a log axis, log10. `corrected` and `range` are its cached
+/// baseline-corrected intensities and range before normalization, if known.
+///
+/// The log is taken last, so with min-max scaling each spectrum's minimum
+/// (0) has no log and becomes NaN, like any other non-positive value.
+fn display_y(
+    state: &AppState,
+    sp: &Spectrum,
+    corrected: Option<&[f64]>,
+    range: Option<(f64, f64)>,
+) -> Vec<f64> {
+    let y = corrected.unwrap_or(&sp.y);
+    let y = match state.smoothing {
+        Some(p) => savitzky_golay(y, p.window, p.poly_order),
+        None => y.to_vec(),
+    };
+    let y = if state.subtract_anchors {
+        linear_baseline(&sp.x, &y, &state.baseline_anchors)
+    } else {
+        y
+    };
+    let y = match state.baseline_point {
+        Some(ref_x) => offset_at(&sp.x, &y, ref_x),
+        None => y,
+    };
+    let y = match (state.normalization, range) {
+        (Normalization::MinMax, Some(range)) => min_max_with_range(&y, range),
+        // Unit area over the region shown, not the whole spectrum.
+        (Normalization::Area, _) => {
+            let (lo, hi) = state.region.unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+            let area = area_between(&sp.x, &y, lo, hi);
+            scale_to_unit_area(y, area)
+        }
+        (normalization, _) => normalization.apply(&sp.x, y),
+    };
+    let y = match state.derivative_order {
+        0 => y,
+        order => derivative(&y, &sp.x, order),
+    };
+    if state.log_y {
+        y.into_iter()
+            .map(|v| if v > 0.0 { v.log10() } else { f64::NAN })
+            .collect()
+    } else {
+        y
+    }
+}
+
+/// Tick label for a log10 axis position, as the original magnitude.
+/// Decimals that tell apart ticks `step` apart.
+fn tick_decimals(step: f64) -> usize {
+    (-step.log10()).ceil().max(0.0) as usize
+}
+
+/// The traces as drawn, with the current bounds, axes and theme, to render
+/// offscreen at a higher resolution.
+fn plot_image(plot_ui: &PlotUi, state: &AppState, traces: &[Trace]) -> PlotImage {
+    let bounds = plot_ui.plot_bounds();
+    let ([x0, y0], [x1, y1]) = (bounds.min(), bounds.max());
+    let x_sign = x_sign(state);
+    let (x_ticks, x_step) = nice_ticks(x0, x1, 8);
+    let x_ticks = x_ticks
+        .into_iter()
+        .map(|v| (v, format!("{:.*}", tick_decimals(x_step), x_sign * v)))
+        .collect();
+    let (y_ticks, y_step) = nice_ticks(y0, y1, 6);
+    let y_ticks = y_ticks
+        .into_iter()
+        .map(|v| {
+            let label = if state.log_y {
+                format_log_tick(v)
+            } else {
+                format!("{:.*}", tick_decimals(y_step), v)
+            };
+            (v, label)
+        })
+        .collect();
+    let visuals = state.plot_theme.egui_theme().default_visuals();
+    PlotImage {
+        lines: traces.iter().map(|t| (t.points.clone(), t.color)).collect(),
+        line_width: state.line_width,
+        x_range: [x0, x1],
+        y_range: [y0, y1],
+        x_ticks,
+        y_ticks,
+        x_label: "Wavenumber".to_string(),
+        y_label: y_axis_label(state),
+        background: (state.plot_theme != PlotTheme::Transparent)
+            .then_some(visuals.extreme_bg_color),
+        foreground: visuals.text_color(),
+    }
+}
+
+fn format_log_tick(exponent: f64) -> String {
+    let value = 10f64.powf(exponent);
+    if (1e-3..1e4).contains(&value) {
+        format!("{}", (value * 1e3).round() / 1e3)
+    } else {
+        format!("{value:.1e}")
+    }
+}
+
+/// Spectrum `idx` as displayed: x with the [`display_y`] intensities,
+/// averaged into bins if binning is on, and trimmed to the selected region.
+/// Trimming comes last so that smoothing and derivatives see the full
+/// spectrum. Points without a value on a log axis are dropped, so the line
+/// joins their neighbours.
+pub fn display_spectrum(state: &AppState, idx: usize, sp: &Spectrum) -> Spectrum {
+    let corrected = state.baseline_corrected(idx);
+    display_spectrum_with(state, sp, corrected, state.cached_y_range(idx))
+}
+
+/// [`display_spectrum`] for a spectrum whose caches are not the active
+/// dataset's, e.g. one of the overlay tab.
+fn display_spectrum_with(
+    state: &AppState,
+    sp: &Spectrum,
+    corrected: Option<&[f64]>,
+    range: Option<(f64, f64)>,
+) -> Spectrum {
+    let y = display_y(state, sp, corrected, range);
+    let (x, y) = if state.log_y {
+        sp.x.iter()
+            .zip(&y)
+            .filter(|(_, y)| y.is_finite())
+            .map(|(&x, &y)| (x, y))
+            .unzip()
+    } else {
+        (sp.x.clone(), y)
+    };
+    let mut shown = Spectrum {
+        x,
+        y,
+        metadata: BTreeMap::new(),
+    };
+    if let Some(width) = state.binning {
+        shown = bin_spectrum(&shown, width);
+    }
+    match state.region {
+        Some((lo, hi)) => trim_region(&shown, lo, hi),
+        None => shown,
+    }
+}
+
+/// Y-axis label for the displayed quantity.
+fn y_axis_label(state: &AppState) -> String {
+    let label = match state.derivative_order {
+        1 => "dA/dν",
+        2 => "d²A/dν²",
+        _ => "Intensity",
+    };
+    if state.log_y {
+        format!("{label} (log scale)")
+    } else {
+        label.to_string()
+    }
+}
+
+/// Colour used for a group value of the colour column.
+fn group_color(state: &AppState, value: &MetadataValue) -> Color32 {
+    let color = state
+        .tab
+        .color_map
+        .as_ref()
+        .map_or(Color32::LIGHT_BLUE, |cm| cm.color_for(value));
+    legible(state, color)
+}
+
+/// `color` darkened if needed to stand out on a light theme's background.
+fn legible(state: &AppState, color: Color32) -> Color32 {
+    if state.plot_theme.is_light() && state.darken_light_lines {
+        darken_for_light_background(color)
+    } else {
+        color
+    }
+}
+
+/// Displayed spectra of one colour group summarised on a common grid.
+struct GroupSummary {
+    value: MetadataValue,
+    n: usize,
+    grid: Vec<f64>,
+    stats: GroupStats,
+}
+
+/// Group the plotted spectra by the colour column and compute each group's
+/// point-wise mean and std of the displayed intensities.
+fn group_summaries(state: &AppState, dataset: &SpectralDataset) -> Vec<GroupSummary> {
+    let groups = group_indices(
+        dataset,
+        &state.plotted_indices(),
+        state.tab.color_column.as_deref(),
+    );
+    groups
+        .into_iter()
+        .map(|(value, indices)| {
+            let shown: Vec<Spectrum> = indices
+                .iter()
+                .zip(dataset.spectra_at(&indices))
+                .map(|(&i, sp)| display_spectrum(state, i, &sp))
+                .collect();
+            let members: Vec<&Spectrum> = shown.iter().collect();
+            let grid = common_grid(&members);
+            let series: Vec<Vec<f64>> = shown
+                .iter()
+                .map(|sp| resample_to_grid(&sp.x, &sp.y, &grid))
+                .collect();
+            GroupSummary {
+                value,
+                n: indices.len(),
+                stats: group_stats(&series),
+                grid,
+            }
+        })
+        .collect()
+}
+
+/// Plot line of a group's mean, skipping points with no data.
+fn mean_line(state: &AppState, group: &GroupSummary) -> Line<'static> {
+    let x_sign = x_sign(state);
+    let mean: PlotPoints = group
+        .grid
+        .iter()
+        .zip(&group.stats.mean)
+        .filter(|(_, m)| m.is_finite())
+        .map(|(&x, &m)| [x_sign * x, m])
+        .collect();
+    Line::new(mean).color(group_color(state, &group.value))
+}
+
+/// Draw each colour group's mean as a bold line over the individual traces.
+fn draw_group_means(plot_ui: &mut PlotUi, state: &AppState, groups: &[GroupSummary]) {
+    for group in groups {
+        let name = format!("{} mean (n={})", state.format_value(&group.value), group.n);
+        plot_ui.line(
+            mean_line(state, group)
+                .name(name)
+                .width(state.mean_line_width)
+                .style(state.mean_line_style.plot_style()),
+        );
+    }
+}
+
+/// Draw a translucent ±1σ band around each colour group's mean. The band is
+/// built from one quad per grid interval, as egui_plot only fills convex
+/// polygons correctly.
+fn draw_std_bands(plot_ui: &mut PlotUi, state: &AppState, groups: &[GroupSummary]) {
+    let x_sign = x_sign(state);
+    for group in groups {
+        let fill = group_color(state, &group.value).gamma_multiply(0.25);
+        let name = format!("{} ±1σ", state.format_value(&group.value));
+        let (grid, stats) = (&group.grid, &group.stats);
+        for i in 1..grid.len() {
+            let (m0, s0, m1, s1) = (
+                stats.mean[i - 1],
+                stats.std[i - 1],
+                stats.mean[i],
+                stats.std[i],
+            );
+            if ![m0, s0, m1, s1].iter().all(|v| v.is_finite()) {
+                continue;
+            }
+            let (x0, x1) = (x_sign * grid[i - 1], x_sign * grid[i]);
+            let quad = PlotPoints::from(vec![
+                [x0, m0 - s0],
+                [x1, m1 - s1],
+                [x1, m1 + s1],
+                [x0, m0 + s0],
+            ]);
+            plot_ui.polygon(
+                Polygon::new(quad)
+                    .name(&name)
+                    .fill_color(fill)
+                    .stroke(Stroke::NONE),
+            );
+        }
+    }
+}
+
+/// Draw each colour group's mean on a common grid with ±1σ whiskers at every
+/// `error_bar_spacing`-th point.
+fn draw_error_bars(plot_ui: &mut PlotUi, state: &AppState, dataset: &SpectralDataset) {
+    let spacing = state.error_bar_spacing.max(1);
+    let x_sign = x_sign(state);
+
+    for group in group_summaries(state, dataset) {
+        let color = group_color(state, &group.value);
+        let name = format!("{} (n={})", state.format_value(&group.value), group.n);
+        plot_ui.line(mean_line(state, &group).name(&name).width(2.0));
+
+        let (grid, stats) = (&group.grid, &group.stats);
+        for i in (0..grid.len()).step_by(spacing) {
+            let (m, sd) = (stats.mean[i], stats.std[i]);
+            if !m.is_finite() || !sd.is_finite() {
+                continue;
+            }
+            let x = x_sign * grid[i];
+            let whisker = PlotPoints::from(vec![[x, m - sd], [x, m + sd]]);
+            plot_ui.line(Line::new(whisker).name(&name).color(color).width(1.0));
+        }
+    }
 }